@@ -0,0 +1,213 @@
+use spirv_std::glam::{UVec2, Vec2, Vec4};
+use spirv_std::num_traits::Float;
+use shared::{CascadeParams, Constants, BATHYMETRY_SIZE};
+use core::f32::consts::PI;
+use crate::{bilinear, lerp, StorageImage, StorageImageArray};
+
+// the cascades are the same everywhere, so depth is applied when they are sampled. each cascade
+// is turned and shortened about pivots on a grid this many metres apart, and the four pivots
+// around a point are blended, which keeps the distortion from growing with distance
+const CELL_SIZE: f32 = 32.0;
+// waves break at about 0.78 of the depth, amplitude is half of that
+const BREAKER_AMPLITUDE: f32 = 0.39;
+// past this linear shoaling and refraction stop meaning much
+const MAX_GAIN: f32 = 3.0;
+const MAX_COMPRESSION: f32 = 4.0;
+
+// how sample treats the map being read
+pub const SCALAR: u32 = 0;
+pub const VECTOR: u32 = 1;
+pub const SLOPE: u32 = 2;
+
+// the four pivots around a point, with the point's offset from each in metres, the bilinear
+// weight, the depth and the direction towards shallower water
+pub struct Cells {
+    offsets: [Vec2; 4],
+    weights: [f32; 4],
+    depths: [f32; 4],
+    shoreward: [Vec2; 4],
+}
+
+// how one cascade is changed at each pivot. shifts move the texel coordinate, rotations turn
+// the horizontal part of vectors
+pub struct Refraction {
+    shifts: [Vec2; 4],
+    rotations: [Vec2; 4],
+    amplitudes: [f32; 4],
+    compressions: [f32; 4],
+    weights: [f32; 4],
+}
+
+// x is an undisplaced world position
+pub fn cells(x: Vec2, map: &StorageImage, consts: &Constants) -> Cells {
+    let f = x / CELL_SIZE - 0.5;
+    let base = f.floor();
+    let t = f - base;
+    let mut cells = Cells {
+        offsets: [Vec2::ZERO; 4],
+        weights: [0.0; 4],
+        depths: [0.0; 4],
+        shoreward: [Vec2::ZERO; 4],
+    };
+    // the gradient is taken over the cell so the contours are smoothed to its scale
+    let h = 0.5 * CELL_SIZE;
+    for c in 0..4 {
+        let corner = Vec2::new((c & 1) as f32, (c >> 1) as f32);
+        let pivot = (base + corner + 0.5) * CELL_SIZE;
+        let w = (Vec2::ONE - corner - t).abs();
+        let gradient = Vec2::new(
+            depth(pivot + Vec2::new(h, 0.0), map, consts) - depth(pivot - Vec2::new(h, 0.0), map, consts),
+            depth(pivot + Vec2::new(0.0, h), map, consts) - depth(pivot - Vec2::new(0.0, h), map, consts),
+        );
+        cells.offsets[c] = x - pivot;
+        cells.weights[c] = w.x * w.y;
+        cells.depths[c] = depth(pivot, map, consts);
+        cells.shoreward[c] = -gradient.normalize_or_zero();
+    }
+    cells
+}
+
+// metres below the still water level, negative on land. the map is clamped at its edges
+pub fn depth(x: Vec2, map: &StorageImage, consts: &Constants) -> f32 {
+    let origin = Vec2::new(consts.sim.bathymetry_x, consts.sim.bathymetry_z);
+    let extent = Vec2::new(consts.sim.bathymetry_width, consts.sim.bathymetry_length).max(Vec2::ONE);
+    let last = (BATHYMETRY_SIZE - 1) as f32;
+    let p = ((x - origin) / extent).clamp(Vec2::ZERO, Vec2::ONE) * last;
+    let base = p.floor();
+    let t = p - base;
+    let x0 = base.x as u32;
+    let y0 = base.y as u32;
+    let x1 = (x0 + 1).min(BATHYMETRY_SIZE - 1);
+    let y1 = (y0 + 1).min(BATHYMETRY_SIZE - 1);
+    let top = lerp(map.read(UVec2::new(x0, y0)), map.read(UVec2::new(x1, y0)), t.x);
+    let bottom = lerp(map.read(UVec2::new(x0, y1)), map.read(UVec2::new(x1, y1)), t.x);
+    let value: Vec4 = lerp(top, bottom, t.y);
+    -(value.x * consts.sim.bathymetry_scale + consts.sim.bathymetry_offset)
+}
+
+// linear theory for the waves of one cascade reaching each pivot. frequency doesnt change as
+// waves move into shallower water, so the wavenumber is found again from it, and the pattern is
+// shortened by the ratio. shoaling conserves the energy flux, snell's law against the local
+// contours turns the waves towards the shore, and the seabed damps them once they feel it
+pub fn refraction(cells: &Cells, params: &CascadeParams, consts: &Constants) -> Refraction {
+    let k = wavenumber(params);
+    // omega^2 / g, the deep water wavenumber of the same frequency
+    let deep = k * (k * consts.sim.depth).tanh();
+    let reference = group_factor(k * consts.sim.depth) / k;
+    let wind = Vec2::new(consts.sim.wind_offset.cos(), consts.sim.wind_offset.sin());
+    let texels_per_metre = params.size as f32 / (consts.sim.size as f32 * consts.sim.mesh_step);
+
+    let mut refraction = Refraction {
+        shifts: [Vec2::ZERO; 4],
+        rotations: [Vec2::X; 4],
+        amplitudes: [0.0; 4],
+        compressions: [1.0; 4],
+        weights: cells.weights,
+    };
+    for c in 0..4 {
+        let d = cells.depths[c];
+        if d > 0.0 {
+            let local = shallow_wavenumber(deep, d);
+            let s = (local / k).clamp(1.0 / MAX_COMPRESSION, MAX_COMPRESSION);
+            let shoaling = (reference * local / group_factor(local * d)).sqrt();
+
+            let shore = cells.shoreward[c];
+            let cos0 = wind.dot(shore);
+            let mut rotation = Vec2::X;
+            let mut turning = 1.0;
+            // waves heading out to sea are left alone
+            if cos0 > 0.0 {
+                let sin0 = shore.perp_dot(wind);
+                let sin1 = sin0 / s;
+                let cos1 = (1.0 - sin1 * sin1).max(0.0).sqrt();
+                let theta = sin1.atan2(cos1) - sin0.atan2(cos0);
+                rotation = Vec2::new(theta.cos(), theta.sin());
+                turning = (cos0 / cos1.max(0.0001)).sqrt();
+            }
+
+            let kd = 2.0 * local * d;
+            let friction = if kd < 20.0 {
+                (-consts.sim.bottom_friction * local / kd.sinh()).exp()
+            } else {
+                1.0
+            };
+
+            // read from pivot + s R(-theta) offset, so around the pivot the waves are turned by
+            // theta and shortened by s
+            let offset = cells.offsets[c];
+            let back = Vec2::new(
+                rotation.x * offset.x + rotation.y * offset.y,
+                rotation.x * offset.y - rotation.y * offset.x,
+            ) * s;
+            refraction.shifts[c] = (back - offset) * texels_per_metre;
+            refraction.rotations[c] = rotation;
+            refraction.amplitudes[c] = (shoaling * turning).min(MAX_GAIN) * friction;
+            refraction.compressions[c] = s;
+        }
+    }
+    refraction
+}
+
+// one cascade read through the refraction at texel p. VECTOR maps have their horizontal part
+// turned and are scaled by the amplitude, SLOPE maps also steepen as the waves shorten and
+// SCALAR maps are only blended
+pub fn sample(map: &StorageImageArray, p: Vec2, layer: u32, size: u32, refraction: &Refraction, mode: u32) -> Vec4 {
+    let size_f = size as f32;
+    let mut sum = Vec4::ZERO;
+    for c in 0..4 {
+        if refraction.weights[c] > 0.0 {
+            let q = p + refraction.shifts[c];
+            let q = q - size_f * (q / size_f).floor();
+            let mut value = bilinear(map, q, layer, size);
+            if mode != SCALAR {
+                let r = refraction.rotations[c];
+                let gain = if mode == SLOPE {
+                    refraction.amplitudes[c] * refraction.compressions[c]
+                } else {
+                    refraction.amplitudes[c]
+                };
+                value = Vec4::new(
+                    (r.x * value.x - r.y * value.z) * gain,
+                    value.y * gain,
+                    (r.y * value.x + r.x * value.z) * gain,
+                    value.w,
+                );
+            }
+            sum += value * refraction.weights[c];
+        }
+    }
+    sum
+}
+
+// depth limited breaking, heights are squashed towards BREAKER_AMPLITUDE of the depth and
+// whatever is lost becomes surf foam. returns (height, foam)
+pub fn break_waves(height: f32, depth: f32) -> Vec2 {
+    let limit = BREAKER_AMPLITUDE * depth;
+    if limit <= 0.0 {
+        return Vec2::ZERO;
+    }
+    let broken = limit * (height / limit).tanh();
+    Vec2::new(broken, ((height.abs() - broken.abs()) / limit).clamp(0.0, 1.0))
+}
+
+// representative of the band a cascade simulates, between its longest wave and the lower of its
+// high cutoff and nyquist
+fn wavenumber(params: &CascadeParams) -> f32 {
+    let low = (2.0 * PI / params.lengthscale).max(params.cutoff_low);
+    let high = (PI * params.size as f32 / params.lengthscale).min(params.cutoff_high);
+    (low * high.max(low)).sqrt()
+}
+
+// fenton and mckee's explicit approximation to omega^2 = g k tanh(kd)
+fn shallow_wavenumber(deep: f32, depth: f32) -> f32 {
+    deep / (deep * depth).powf(0.75).tanh().powf(2.0 / 3.0)
+}
+
+// group velocity over phase velocity
+fn group_factor(kd: f32) -> f32 {
+    if kd < 10.0 {
+        0.5 * (1.0 + 2.0 * kd / (2.0 * kd).sinh())
+    } else {
+        0.5
+    }
+}
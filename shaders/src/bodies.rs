@@ -0,0 +1,61 @@
+use spirv_std::glam::{Vec3, Vec4};
+use spirv_std::{spirv, image::Image2d, Sampler};
+use spirv_std::num_traits::Float;
+use shared::{BodyInstance, Constants};
+use crate::{equirectangular_to_uv, lerp, reflect, reinhard_tonemap};
+
+#[inline(never)]
+#[spirv(vertex)]
+pub fn body_vs(
+    pos: Vec4,
+    normal: Vec4,
+    #[spirv(instance_index)] instance_index: u32,
+    #[spirv(uniform, descriptor_set = 0, binding = 0)] consts: &Constants,
+    #[spirv(storage_buffer, descriptor_set = 3, binding = 0)] instances: &[BodyInstance],
+    #[spirv(position)] out_pos: &mut Vec4,
+    out_normal: &mut Vec3,
+    out_world_pos: &mut Vec4,
+    out_color: &mut Vec4,
+) {
+    let instance = instances[instance_index as usize];
+    let world_pos = instance.model * pos.truncate().extend(1.0);
+    *out_pos = consts.camera_viewproj * world_pos;
+    *out_normal = (instance.model * normal.truncate().extend(0.0)).truncate();
+    *out_world_pos = world_pos;
+    *out_color = instance.color;
+}
+
+// painted bodies lit by the sun and the hdri, the hdri has no prefiltered mips so sampling it
+// along the normal stands in for irradiance
+#[inline(never)]
+#[spirv(fragment)]
+pub fn body_fs(
+    normal: Vec3,
+    world_pos: Vec4,
+    color: Vec4,
+    #[spirv(uniform, descriptor_set = 0, binding = 0)] consts: &Constants,
+    #[spirv(descriptor_set = 1, binding = 0)] sampler: &Sampler,
+    #[spirv(descriptor_set = 2, binding = 0)] hdri: &Image2d,
+    output: &mut Vec4,
+) {
+    let pos = world_pos.truncate();
+    let n = normal.normalize();
+    let l = (consts.shader.light.truncate() - pos).normalize();
+    let v = (consts.eye.truncate() - pos).normalize();
+
+    let ambient = hdri.sample(*sampler, equirectangular_to_uv(n)).truncate();
+    let diffuse = l.dot(n).max(0.0) * consts.shader.sun_color.truncate();
+    // schlick with the f0 of paint
+    let fresnel = 0.04 + 0.96 * (1.0 - n.dot(v).max(0.0)).powf(5.0);
+    let reflected = hdri.sample(*sampler, equirectangular_to_uv(reflect(n, v))).truncate();
+    let l_eye = lerp(color.truncate() * (diffuse + ambient), reflected, fresnel);
+
+    // same fog as the ocean so bodies on the horizon fade with it
+    let dist = (consts.eye - world_pos).length();
+    let max_dist = (consts.eye - 0.5 * consts.sim.size as f32 * consts.sim.mesh_step * consts.sim.instances as f32).length();
+    let t = ((dist - consts.shader.fog_offset) / (max_dist - consts.shader.fog_offset)).clamp(0.0, 1.0);
+    let fog = t.powf(consts.shader.fog_falloff) * consts.shader.fog_density;
+    let l_eye = lerp(l_eye, consts.shader.fog_color.truncate(), fog);
+
+    *output = reinhard_tonemap(l_eye).extend(1.0);
+}
@@ -2,18 +2,25 @@
 pub mod sim;
 pub mod ui;
 pub mod skybox;
+pub mod bodies;
+pub mod wake;
+pub mod ripple;
+pub mod bathymetry;
+pub mod spray;
 
 use core::f32::consts;
 use core::ops::{Add, Mul};
 
-use spirv_std::glam::{Vec4, Vec3, UVec2, Vec2};
+use spirv_std::glam::{Vec4, Vec3, UVec2, UVec3, Vec2};
 use spirv_std::image::Image2d;
 use spirv_std::Sampler;
 use spirv_std::{spirv, image::Image};
 use spirv_std::num_traits::Float;
-use shared::Constants;
+use shared::{CascadeParams, Constants, Impact, WakeSource, MAX_IMPACTS, MAX_WAKES};
 
 type StorageImage = Image!(2D, format = rgba32f, sampled = false);
+// every cascade is a layer
+type StorageImageArray = Image!(2D, format = rgba32f, sampled = false, arrayed = true);
 
 #[spirv(vertex)]
 pub fn main_vs(
@@ -21,30 +28,17 @@ pub fn main_vs(
     uv: UVec2,
     #[spirv(instance_index)] instance_index: u32,
     #[spirv(uniform, descriptor_set = 0, binding = 0)] consts: &Constants,
-    #[spirv(descriptor_set = 3, binding = 3)] displacement_map0: &StorageImage,
-    #[spirv(descriptor_set = 3, binding = 4)] normal_map0: &StorageImage,
-    #[spirv(descriptor_set = 3, binding = 5)] foam_map0: &StorageImage,
-    #[spirv(descriptor_set = 4, binding = 3)] displacement_map1: &StorageImage,
-    #[spirv(descriptor_set = 4, binding = 4)] normal_map1: &StorageImage,
-    #[spirv(descriptor_set = 4, binding = 5)] foam_map1: &StorageImage,
-    #[spirv(descriptor_set = 5, binding = 3)] displacement_map2: &StorageImage,
-    #[spirv(descriptor_set = 5, binding = 4)] normal_map2: &StorageImage,
-    #[spirv(descriptor_set = 5, binding = 5)] foam_map2: &StorageImage,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] cascades: &[CascadeParams],
+    #[spirv(descriptor_set = 3, binding = 0)] displacement_map: &StorageImageArray,
+    #[spirv(descriptor_set = 3, binding = 1)] normal_map: &StorageImageArray,
+    #[spirv(descriptor_set = 3, binding = 2)] foam_map: &StorageImageArray,
+    #[spirv(storage_buffer, descriptor_set = 4, binding = 0)] wakes: &[WakeSource],
+    #[spirv(storage_buffer, descriptor_set = 4, binding = 1)] impacts: &[Impact],
+    #[spirv(descriptor_set = 5, binding = 0)] bathymetry: &StorageImage,
     #[spirv(position)] out_pos: &mut Vec4, out_normal: &mut Vec3,
     out_foam: &mut Vec3,
     out_world_pos: &mut Vec4,
 ) {
-    let mut displacement = displacement_map0.read(uv) * consts.sim.lengthscale0_sf;
-    displacement += displacement_map1.read(uv) * consts.sim.lengthscale1_sf;
-    displacement += displacement_map2.read(uv) * consts.sim.lengthscale2_sf;
-    let mut normal = normal_map0.read(uv) * consts.sim.lengthscale0_sf;
-    normal += normal_map1.read(uv) * consts.sim.lengthscale1_sf;
-    normal += normal_map2.read(uv) * consts.sim.lengthscale2_sf;
-    normal.y = 1.0;
-    let mut foam = foam_map0.read(uv) * consts.sim.lengthscale0_sf;
-    foam += foam_map1.read(uv) * consts.sim.lengthscale1_sf;
-    foam += foam_map2.read(uv) * consts.sim.lengthscale2_sf;
-
     let width = consts.sim.size as f32 * consts.sim.mesh_step;
     let x = instance_index % consts.sim.instances;
     let z  = instance_index / consts.sim.instances;
@@ -52,15 +46,92 @@ pub fn main_vs(
     let positive_offset = Vec4::new(width * 0.5, 0.0, width * 0.5, 0.0) * (consts.sim.instances as f32 - 1.0);
 
     let centring_offset = Vec4::new(0.5 * width, consts.sim.height_offset, 0.5 * width, 0.0);
+    let rest_pos = pos - centring_offset + tiling_offset - positive_offset;
+
+    let mut displacement = Vec4::ZERO;
+    let mut normal = Vec4::ZERO;
+    let mut foam = Vec4::ZERO;
+    if consts.sim.bathymetry != 0 {
+        let rest = Vec2::new(rest_pos.x, rest_pos.z);
+        let cells = bathymetry::cells(rest, bathymetry, consts);
+        for i in 0..consts.sim.cascade_count {
+            let params = cascades[i as usize];
+            let p = uv.as_vec2() * params.size as f32 / consts.sim.size as f32;
+            let refraction = bathymetry::refraction(&cells, &params, consts);
+            displacement += bathymetry::sample(displacement_map, p, i, params.size, &refraction, bathymetry::VECTOR) * params.scale_factor;
+            normal += bathymetry::sample(normal_map, p, i, params.size, &refraction, bathymetry::SLOPE) * params.scale_factor;
+            foam += bathymetry::sample(foam_map, p, i, params.size, &refraction, bathymetry::SCALAR) * params.scale_factor;
+        }
+        let broken = bathymetry::break_waves(displacement.y, bathymetry::depth(rest, bathymetry, consts));
+        displacement.y = broken.x;
+        foam.x += broken.y;
+    } else {
+        for i in 0..consts.sim.cascade_count {
+            let params = cascades[i as usize];
+            // cascades can be a different resolution to the mesh, so position within the cascade
+            let p = uv.as_vec2() * params.size as f32 / consts.sim.size as f32;
+            displacement += bilinear(displacement_map, p, i, params.size) * params.scale_factor;
+            normal += bilinear(normal_map, p, i, params.size) * params.scale_factor;
+            foam += bilinear(foam_map, p, i, params.size) * params.scale_factor;
+        }
+    }
+    normal.y = 1.0;
 
-    let mut resultant_pos = pos + displacement - centring_offset + tiling_offset - positive_offset;
+    let mut resultant_pos = rest_pos + displacement;
     resultant_pos.w = 1.0;
+
+    // wakes and ripples are in world space, their slope is taken by differences a fraction of a
+    // texel apart. most of the ocean is out of reach of every body, which skips all three
+    let p = Vec2::new(resultant_pos.x, resultant_pos.z);
+    if disturbed(p, wakes, impacts, consts) {
+        let e = 0.5 * consts.sim.mesh_step;
+        let added = disturbance(p, wakes, impacts, consts);
+        let added_x = disturbance(p + Vec2::new(e, 0.0), wakes, impacts, consts).x;
+        let added_z = disturbance(p + Vec2::new(0.0, e), wakes, impacts, consts).x;
+        resultant_pos.y += added.x;
+        normal.x -= (added_x - added.x) / e;
+        normal.z -= (added_z - added.x) / e;
+        foam.x += added.y;
+    }
     *out_pos = consts.camera_viewproj * resultant_pos;
     *out_normal = normal.truncate();
     *out_foam = foam.truncate();
     *out_world_pos = resultant_pos;
 }
 
+// height and foam added on top of the cascades
+fn disturbance(p: Vec2, wakes: &[WakeSource], impacts: &[Impact], consts: &Constants) -> Vec2 {
+    wake::wake(p, wakes, consts) + ripple::ripples(p, impacts, consts)
+}
+
+// whether any wake or ripple reaches p, only distances so its cheap next to disturbance
+fn disturbed(p: Vec2, wakes: &[WakeSource], impacts: &[Impact], consts: &Constants) -> bool {
+    for i in 0..MAX_WAKES {
+        if wake::reaches(p, &wakes[i]) {
+            return true;
+        }
+    }
+    for i in 0..MAX_IMPACTS {
+        if ripple::reaches(p, &impacts[i], consts) {
+            return true;
+        }
+    }
+    false
+}
+
+// storage images cant be sampled, so filtering is done by hand. wraps as the maps tile
+fn bilinear(map: &StorageImageArray, p: Vec2, layer: u32, size: u32) -> Vec4 {
+    let base = p.floor();
+    let t = p - base;
+    let x0 = base.x as u32 % size;
+    let y0 = base.y as u32 % size;
+    let x1 = (x0 + 1) % size;
+    let y1 = (y0 + 1) % size;
+    let top = lerp(map.read(UVec3::new(x0, y0, layer)), map.read(UVec3::new(x1, y0, layer)), t.x);
+    let bottom = lerp(map.read(UVec3::new(x0, y1, layer)), map.read(UVec3::new(x1, y1, layer)), t.x);
+    lerp(top, bottom, t.y)
+}
+
 #[inline(never)]
 #[spirv(fragment)]
 pub fn main_fs(
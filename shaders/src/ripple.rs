@@ -0,0 +1,75 @@
+use spirv_std::glam::{Vec2, Vec4Swizzles};
+use spirv_std::num_traits::Float;
+use shared::{Constants, Impact, IMPACT_LIFETIME, MAX_IMPACTS};
+use core::f32::consts::PI;
+
+// per second, splash foam lingers rather than following the whitecap decay
+const SPLASH_FOAM_DECAY: f32 = 0.5;
+// rings longer than this ka are left out, their amplitude goes as (ka)^1.25 so they are a few
+// percent of the peak
+const MIN_KA: f32 = 0.05;
+// the crater and foam gaussians are taken as zero this many widths out
+const GAUSSIAN_REACH: f32 = 3.0;
+
+// height and foam of the rings spreading from every impact at world (x, z)
+pub fn ripples(p: Vec2, impacts: &[Impact], consts: &Constants) -> Vec2 {
+    let mut result = Vec2::ZERO;
+    for i in 0..MAX_IMPACTS {
+        let impact = impacts[i];
+        if reaches(p, &impact, consts) {
+            result += ripple(p, &impact, consts);
+        }
+    }
+    result
+}
+
+// whether p is inside the front of the longest rings worth drawing, which have travelled out at
+// their group velocity, or the crater and its foam. nothing reaches before or after the impact
+pub fn reaches(p: Vec2, impact: &Impact, consts: &Constants) -> bool {
+    let age = consts.time - impact.origin.z;
+    if impact.radius <= 0.0 || age < 0.0 || age > IMPACT_LIFETIME {
+        return false;
+    }
+    let a = impact.radius;
+    let group_velocity = 0.5 * (consts.sim.gravity * a / MIN_KA).sqrt();
+    let reach = (age * group_velocity).max(GAUSSIAN_REACH * spread(a, age));
+    (p - impact.origin.xy()).length() < reach
+}
+
+// width of the splash foam, which spreads out as it ages
+fn spread(a: f32, age: f32) -> f32 {
+    a * (1.0 + 0.5 * age)
+}
+
+// the cauchy-poisson problem for a gaussian crater on deep water. by stationary phase each
+// wavenumber has travelled out at its group velocity, so a point at distance r sees
+// k = g t^2 / 4r^2, with the amplitude of the crater's hankel transform at that k. the crater
+// itself fills back in over about the time a wave takes to cross it
+fn ripple(p: Vec2, impact: &Impact, consts: &Constants) -> Vec2 {
+    let age = consts.time - impact.origin.z;
+    // rewound to before it happened, it replays once the clock catches up
+    if age < 0.0 || age > IMPACT_LIFETIME {
+        return Vec2::ZERO;
+    }
+    let g = consts.sim.gravity;
+    let a = impact.radius;
+    let h = -impact.depth;
+    let r = (p - impact.origin.xy()).length().max(0.001);
+
+    let collapse = age * (g / a).sqrt();
+    let crater = h * (-(r / a).powi(2) - collapse * collapse).exp();
+
+    let k = g * age * age / (4.0 * r * r);
+    let transform = 0.5 * h * a * a * (-0.25 * (k * a).powi(2)).exp();
+    // waves finer than the mesh are faded out rather than aliased
+    let fade = (1.0 - k * consts.sim.mesh_step / PI).max(0.0);
+    let rings = 2.0 * transform * k.powf(1.25) / (g.powf(0.25) * (r * age).sqrt().max(0.001))
+        * (g * age * age / (4.0 * r)).cos()
+        * fade;
+    let lifetime = 1.0 - age / IMPACT_LIFETIME;
+
+    let spread = spread(a, age);
+    let foam = impact.foam * (-(r / spread).powi(2) - SPLASH_FOAM_DECAY * age).exp() * a * a / (spread * spread);
+
+    Vec2::new((crater + rings) * lifetime * lifetime, foam)
+}
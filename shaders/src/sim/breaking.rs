@@ -0,0 +1,64 @@
+use spirv_std::spirv;
+use spirv_std::glam::{UVec2, UVec3, Vec2, Vec3Swizzles, Vec4, Vec4Swizzles};
+use shared::{CascadeParams, Constants, BREAKING_BLOCK};
+use crate::{bilinear, StorageImageArray};
+
+// sums the breaking texels of one BREAKING_BLOCK square of the mesh so the cpu only reads back a
+// small grid, which it then joins into regions. a texel breaks where the jacobian of the summed
+// displacement, the same surface main_vs draws, is under breaking_threshold. each block is
+// (texels, sum of threshold - J, sum of displaced x, sum of displaced z), positions are metres
+// from the corner of the block
+#[spirv(compute(threads(8, 8)))]
+pub fn main(
+    #[spirv(global_invocation_id)] id: UVec3,
+    #[spirv(uniform, descriptor_set = 0, binding = 0)] consts: &Constants,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] cascades: &[CascadeParams],
+    #[spirv(descriptor_set = 1, binding = 0)] displacement_map: &StorageImageArray,
+    #[spirv(storage_buffer, descriptor_set = 2, binding = 0)] blocks: &mut [Vec4],
+) {
+    let side = consts.sim.size / BREAKING_BLOCK;
+    if id.x >= side || id.y >= side {
+        return;
+    }
+    let mut sums = Vec4::ZERO;
+    for j in 0..BREAKING_BLOCK {
+        for i in 0..BREAKING_BLOCK {
+            let offset = UVec2::new(i, j);
+            let texel = id.xy() * BREAKING_BLOCK + offset;
+            let jacobian = jacobian(displacement_map, texel, consts, cascades);
+            if jacobian < consts.sim.breaking_threshold {
+                let position = offset.as_vec2() * consts.sim.mesh_step
+                    + displacement(displacement_map, texel, consts, cascades).xz();
+                sums += Vec4::new(1.0, consts.sim.breaking_threshold - jacobian, position.x, position.y);
+            }
+        }
+    }
+    blocks[(id.y * side + id.x) as usize] = sums;
+}
+
+// summed over the cascades at a mesh texel, wrapping at the edges of the tile
+fn displacement(map: &StorageImageArray, texel: UVec2, consts: &Constants, cascades: &[CascadeParams]) -> Vec4 {
+    let size = consts.sim.size;
+    let texel = UVec2::new(texel.x % size, texel.y % size);
+    let mut sum = Vec4::ZERO;
+    for i in 0..consts.sim.cascade_count {
+        let params = cascades[i as usize];
+        let p = texel.as_vec2() * params.size as f32 / size as f32;
+        sum += bilinear(map, p, i, params.size) * params.scale_factor;
+    }
+    sum
+}
+
+// central differences of the displacement, 1 where the surface is undisturbed and below 0 where
+// it has folded over itself
+fn jacobian(map: &StorageImageArray, texel: UVec2, consts: &Constants, cascades: &[CascadeParams]) -> f32 {
+    let size = consts.sim.size;
+    let left = UVec2::new((texel.x + size - 1) % size, texel.y);
+    let right = UVec2::new(texel.x + 1, texel.y);
+    let down = UVec2::new(texel.x, (texel.y + size - 1) % size);
+    let up = UVec2::new(texel.x, texel.y + 1);
+    let scale = 0.5 / consts.sim.mesh_step;
+    let dx: Vec2 = (displacement(map, right, consts, cascades) - displacement(map, left, consts, cascades)).xz() * scale;
+    let dz: Vec2 = (displacement(map, up, consts, cascades) - displacement(map, down, consts, cascades)).xz() * scale;
+    (1.0 + dx.x) * (1.0 + dz.y) - dx.y * dz.x
+}
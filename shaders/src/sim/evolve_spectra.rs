@@ -13,9 +13,12 @@ pub fn main(
     #[spirv(descriptor_set = 1, binding = 0)] wave_tex: &StorageImage,
     #[spirv(descriptor_set = 1, binding = 1)] initial_spectrum_tex: &StorageImage,
     #[spirv(descriptor_set = 2, binding = 0)] h_displacement: &StorageImage,
-    #[spirv(descriptor_set = 3, binding = 0)] v_displacement: &StorageImage,
-    #[spirv(descriptor_set = 4, binding = 0)] h_slope: &StorageImage,
-    #[spirv(descriptor_set = 5, binding = 0)] jacobian: &StorageImage,
+    #[spirv(descriptor_set = 2, binding = 1)] v_displacement: &StorageImage,
+    #[spirv(descriptor_set = 2, binding = 2)] h_slope: &StorageImage,
+    #[spirv(descriptor_set = 2, binding = 3)] jacobian: &StorageImage,
+    #[spirv(descriptor_set = 2, binding = 4)] h_velocity: &StorageImage,
+    #[spirv(descriptor_set = 2, binding = 5)] v_motion: &StorageImage,
+    #[spirv(descriptor_set = 2, binding = 6)] h_acceleration: &StorageImage,
 ) {
     // Evolving spectra
     let wave = wave_tex.read(id.xy());
@@ -50,6 +53,29 @@ pub fn main(
         h_slope.write(id.xy(), Vec4::new(nx.x, nx.y, nz.x, nz.y));
         jacobian.write(id.xy(), Vec4::new(j_xx.x, j_xx.y, j_zz.x, j_zz.y));
     }
+
+    if consts.sim.spectral_motion != 0 {
+        // the two halves of h turn in opposite directions, so the time derivative is
+        // i omega (h0 e^(i omega t) - h0c e^(-i omega t)) rather than i omega h
+        let omega = wave.w;
+        let ht = complex_mult(h0, exponent) - complex_mult(h0c, negative_exponent);
+        let iht = Vec2::new(-ht.y, ht.x) * omega;
+        let htt = -h * omega * omega;
+        let ihtt = Vec2::new(-htt.y, htt.x);
+
+        let vx = -iht * wave.x * wave.z;
+        let vy = iht;
+        let vz = -iht * wave.y * wave.z;
+        let ax = -ihtt * wave.x * wave.z;
+        let ay = htt;
+        let az = -ihtt * wave.y * wave.z;
+
+        unsafe {
+            h_velocity.write(id.xy(), Vec4::new(vx.x, vx.y, vz.x, vz.y));
+            v_motion.write(id.xy(), Vec4::new(vy.x, vy.y, ay.x, ay.y));
+            h_acceleration.write(id.xy(), Vec4::new(ax.x, ax.y, az.x, az.y));
+        }
+    }
 }
 
 pub fn complex_mult(a: Vec2, b: Vec2) -> Vec2 {
@@ -1,10 +1,11 @@
 use spirv_std::{
     spirv,
     num_traits::Float,
+    arch::workgroup_memory_barrier_with_group_sync,
 };
 use core::f32::consts;
-use crate::{sim::evolve_spectra::complex_mult, StorageImage};
-use shared::{Constants, FFTData};
+use crate::{sim::evolve_spectra::complex_mult, StorageImage, StorageImageArray};
+use shared::{FFTData, SharedFFTData, StockhamData, MAX_SHARED_FFT_SIZE, SHARED_FFT_THREADS};
 use spirv_std::glam::{UVec3, UVec2, Vec3Swizzles, Vec2, Vec4, Vec4Swizzles};
 
 
@@ -12,37 +13,44 @@ use spirv_std::glam::{UVec3, UVec2, Vec3Swizzles, Vec2, Vec4, Vec4Swizzles};
 pub fn hstep_ifft(
     #[spirv(global_invocation_id)] id: UVec3,
     #[spirv(push_constant)] data: &FFTData,
-    #[spirv(descriptor_set = 0, binding = 1)] butterfly_tex: &StorageImage,
-    #[spirv(descriptor_set = 1, binding = 0)] pingpong0: &StorageImage,
-    #[spirv(descriptor_set = 2, binding = 0)] pingpong1: &StorageImage,
+    #[spirv(descriptor_set = 0, binding = 0)] butterfly_tex: &StorageImage,
+    #[spirv(descriptor_set = 1, binding = 0)] pingpong0: &StorageImageArray,
+    #[spirv(descriptor_set = 2, binding = 0)] pingpong1: &StorageImageArray,
 ) {
+    let layer = id.z + data.layer;
     let butterfly_data: Vec4 = butterfly_tex.read(UVec2::new(data.stage, id.x));
     let twiddle: Vec2 = butterfly_data.xy();
     let indices: UVec2 = UVec2::new(butterfly_data.z as u32, butterfly_data.w as u32);
 
     if data.pingpong == 0 {
-        let top_signal0 = pingpong0.read(UVec2::new(indices.x, id.y)).xy();
-        let top_signal1 = pingpong0.read(UVec2::new(indices.x, id.y)).zw();
-        let bottom_signal0 = pingpong0.read(UVec2::new(indices.y, id.y)).xy();
-        let bottom_signal1 = pingpong0.read(UVec2::new(indices.y, id.y)).zw();
+        let mut top = pingpong0.read(UVec3::new(indices.x, id.y, layer));
+        let mut bottom = pingpong0.read(UVec3::new(indices.y, id.y, layer));
+        if data.forward == 1 && data.stage == 0 {
+            top = forward_prepare(top, indices.x, id.y);
+            bottom = forward_prepare(bottom, indices.y, id.y);
+        }
+        let top_signal0 = top.xy();
+        let top_signal1 = top.zw();
+        let bottom_signal0 = bottom.xy();
+        let bottom_signal1 = bottom.zw();
 
         let h0 = top_signal0 + complex_mult(twiddle, bottom_signal0);
         let h1 = top_signal1 + complex_mult(twiddle, bottom_signal1);
 
         unsafe {
-            pingpong1.write(id.xy(), Vec4::new(h0.x, h0.y, h1.x, h1.y));
+            pingpong1.write(UVec3::new(id.x, id.y, layer), Vec4::new(h0.x, h0.y, h1.x, h1.y));
         }
     } else if data.pingpong == 1 {
-        let top_signal0 = pingpong1.read(UVec2::new(indices.x, id.y)).xy();
-        let top_signal1 = pingpong1.read(UVec2::new(indices.x, id.y)).zw();
-        let bottom_signal0 = pingpong1.read(UVec2::new(indices.y, id.y)).xy();
-        let bottom_signal1 = pingpong1.read(UVec2::new(indices.y, id.y)).zw();
+        let top_signal0 = pingpong1.read(UVec3::new(indices.x, id.y, layer)).xy();
+        let top_signal1 = pingpong1.read(UVec3::new(indices.x, id.y, layer)).zw();
+        let bottom_signal0 = pingpong1.read(UVec3::new(indices.y, id.y, layer)).xy();
+        let bottom_signal1 = pingpong1.read(UVec3::new(indices.y, id.y, layer)).zw();
 
         let h0 = top_signal0 + complex_mult(twiddle, bottom_signal0);
         let h1 = top_signal1 + complex_mult(twiddle, bottom_signal1);
 
         unsafe {
-            pingpong0.write(id.xy(), Vec4::new(h0.x, h0.y, h1.x, h1.y));
+            pingpong0.write(UVec3::new(id.x, id.y, layer), Vec4::new(h0.x, h0.y, h1.x, h1.y));
         }
     }
 }
@@ -51,37 +59,38 @@ pub fn hstep_ifft(
 pub fn vstep_ifft(
     #[spirv(global_invocation_id)] id: UVec3,
     #[spirv(push_constant)] data: &FFTData,
-    #[spirv(descriptor_set = 0, binding = 1)] butterfly_tex: &StorageImage,
-    #[spirv(descriptor_set = 1, binding = 0)] pingpong0: &StorageImage,
-    #[spirv(descriptor_set = 2, binding = 0)] pingpong1: &StorageImage,
+    #[spirv(descriptor_set = 0, binding = 0)] butterfly_tex: &StorageImage,
+    #[spirv(descriptor_set = 1, binding = 0)] pingpong0: &StorageImageArray,
+    #[spirv(descriptor_set = 2, binding = 0)] pingpong1: &StorageImageArray,
 ) {
+    let layer = id.z + data.layer;
     let butterfly_data: Vec4 = butterfly_tex.read(UVec2::new(data.stage, id.y));
     let twiddle: Vec2 = butterfly_data.xy();
     let indices: UVec2 = UVec2::new(butterfly_data.z as u32, butterfly_data.w as u32);
 
     if data.pingpong == 0 {
-        let top_signal0 = pingpong0.read(UVec2::new(id.x, indices.x)).xy();
-        let top_signal1 = pingpong0.read(UVec2::new(id.x, indices.x)).zw();
-        let bottom_signal0 = pingpong0.read(UVec2::new(id.x, indices.y)).xy();
-        let bottom_signal1 = pingpong0.read(UVec2::new(id.x, indices.y)).zw();
+        let top_signal0 = pingpong0.read(UVec3::new(id.x, indices.x, layer)).xy();
+        let top_signal1 = pingpong0.read(UVec3::new(id.x, indices.x, layer)).zw();
+        let bottom_signal0 = pingpong0.read(UVec3::new(id.x, indices.y, layer)).xy();
+        let bottom_signal1 = pingpong0.read(UVec3::new(id.x, indices.y, layer)).zw();
 
         let h0 = top_signal0 + complex_mult(twiddle, bottom_signal0);
         let h1 = top_signal1 + complex_mult(twiddle, bottom_signal1);
 
         unsafe {
-            pingpong1.write(id.xy(), Vec4::new(h0.x, h0.y, h1.x, h1.y));
+            pingpong1.write(UVec3::new(id.x, id.y, layer), Vec4::new(h0.x, h0.y, h1.x, h1.y));
         }
     } else if data.pingpong == 1 {
-        let top_signal0 = pingpong1.read(UVec2::new(id.x, indices.x)).xy();
-        let top_signal1 = pingpong1.read(UVec2::new(id.x, indices.x)).zw();
-        let bottom_signal0 = pingpong1.read(UVec2::new(id.x, indices.y)).xy();
-        let bottom_signal1 = pingpong1.read(UVec2::new(id.x, indices.y)).zw();
+        let top_signal0 = pingpong1.read(UVec3::new(id.x, indices.x, layer)).xy();
+        let top_signal1 = pingpong1.read(UVec3::new(id.x, indices.x, layer)).zw();
+        let bottom_signal0 = pingpong1.read(UVec3::new(id.x, indices.y, layer)).xy();
+        let bottom_signal1 = pingpong1.read(UVec3::new(id.x, indices.y, layer)).zw();
 
         let h0 = top_signal0 + complex_mult(twiddle, bottom_signal0);
         let h1 = top_signal1 + complex_mult(twiddle, bottom_signal1);
 
         unsafe {
-            pingpong0.write(id.xy(), Vec4::new(h0.x, h0.y, h1.x, h1.y));
+            pingpong0.write(UVec3::new(id.x, id.y, layer), Vec4::new(h0.x, h0.y, h1.x, h1.y));
         }
     }
 }
@@ -89,28 +98,257 @@ pub fn vstep_ifft(
 #[spirv(compute(threads(8,8)))]
 pub fn permute(
     #[spirv(global_invocation_id)] id: UVec3,
-    #[spirv(descriptor_set = 1, binding = 0)] pingpong0: &StorageImage,
+    #[spirv(push_constant)] data: &FFTData,
+    #[spirv(descriptor_set = 1, binding = 0)] pingpong0: &StorageImageArray,
 ) {
-    let sign = match ((id.x + id.y) % 2) as f32 {
-        0.0 => 1.0,
+    let texel = UVec3::new(id.x, id.y, id.z + data.layer);
+    let value = finish(pingpong0.read(texel), id.x, id.y, data.size, data.forward);
+    unsafe {
+        pingpong0.write(texel, value);
+    }
+}
+
+// the butterflies all use exp(-2 pi i k / N), the forward transform needs exp(+2 pi i k / N) so
+// it is done as conj(fft(conj(x))). the spatial field is also multiplied by (-1)^(x + y) so the
+// spectrum comes out centred, matching the initial spectrum layout
+fn forward_prepare(value: Vec4, x: u32, y: u32) -> Vec4 {
+    conjugate(value) * checkerboard(x, y)
+}
+
+// last step of either direction. the inverse keeps the real part of both signals and undoes the
+// centring, the forward transform is normalised by 1 / N^2 so fft then ifft gives back the input
+fn finish(value: Vec4, x: u32, y: u32, size: u32, forward: u32) -> Vec4 {
+    if forward == 1 {
+        conjugate(value) / (size * size) as f32
+    } else {
+        let sign = checkerboard(x, y);
+        Vec4::new(sign * value.x, sign * value.z, 0.0, 1.0)
+    }
+}
+
+fn checkerboard(x: u32, y: u32) -> f32 {
+    match (x + y) % 2 {
+        0 => 1.0,
         _ => -1.0,
-    };
+    }
+}
 
-    let h0 = sign * pingpong0.read(id.xy()).x;
-    let h1 = sign * pingpong0.read(id.xy()).z;
+// both packed signals
+fn conjugate(value: Vec4) -> Vec4 {
+    Vec4::new(value.x, -value.y, value.z, -value.w)
+}
+
+// stockham autosort fft, every stage reads and writes in natural order so theres no bit reversal
+// and no butterfly texture. radix 4 stages with a single radix 2 stage first when log2(size) is
+// odd, so a 256x256 fft is 8 dispatches rather than 17. each thread does one butterfly of a row
+#[spirv(compute(threads(8,8)))]
+pub fn hstep_stockham(
+    #[spirv(global_invocation_id)] id: UVec3,
+    #[spirv(push_constant)] data: &StockhamData,
+    #[spirv(descriptor_set = 0, binding = 0)] pingpong0: &StorageImageArray,
+    #[spirv(descriptor_set = 1, binding = 0)] pingpong1: &StorageImageArray,
+) {
+    if data.pingpong == 0 {
+        stockham_stage(pingpong0, pingpong1, id.x, id.y, id.z + data.layer, data, true);
+    } else {
+        stockham_stage(pingpong1, pingpong0, id.x, id.y, id.z + data.layer, data, true);
+    }
+}
+
+// same again down the columns, the last stage also does what permute does
+#[spirv(compute(threads(8,8)))]
+pub fn vstep_stockham(
+    #[spirv(global_invocation_id)] id: UVec3,
+    #[spirv(push_constant)] data: &StockhamData,
+    #[spirv(descriptor_set = 0, binding = 0)] pingpong0: &StorageImageArray,
+    #[spirv(descriptor_set = 1, binding = 0)] pingpong1: &StorageImageArray,
+) {
+    if data.pingpong == 0 {
+        stockham_stage(pingpong0, pingpong1, id.y, id.x, id.z + data.layer, data, false);
+    } else {
+        stockham_stage(pingpong1, pingpong0, id.y, id.x, id.z + data.layer, data, false);
+    }
+}
+
+fn stockham_stage(
+    input: &StorageImageArray,
+    output: &StorageImageArray,
+    j: u32,
+    line: u32,
+    layer: u32,
+    data: &StockhamData,
+    horizontal: bool,
+) {
+    let stride = data.size / data.radix;
+    if j >= stride || line >= data.size {
+        return;
+    }
+    let k = j % data.span;
+    let base = (j - k) * data.radix + k;
+    let angle = -2.0 * consts::PI * k as f32 / (data.span * data.radix) as f32;
+    let first = horizontal && data.span == 1 && data.forward == 1;
+    let last = !horizontal && data.span * data.radix == data.size;
+
+    let a0 = fft_read(input, j, line, layer, horizontal, first);
+    let a1 = complex_mult_pair(twiddle(angle), fft_read(input, j + stride, line, layer, horizontal, first));
+    if data.radix == 2 {
+        fft_write(output, base, line, layer, a0 + a1, horizontal, last, data.size, data.forward);
+        fft_write(output, base + data.span, line, layer, a0 - a1, horizontal, last, data.size, data.forward);
+    } else {
+        let a2 = complex_mult_pair(twiddle(2.0 * angle), fft_read(input, j + 2 * stride, line, layer, horizontal, first));
+        let a3 = complex_mult_pair(twiddle(3.0 * angle), fft_read(input, j + 3 * stride, line, layer, horizontal, first));
+        let b0 = a0 + a2;
+        let b1 = a0 - a2;
+        let b2 = a1 + a3;
+        // multiplying by -i
+        let b3 = Vec4::new(a1.y - a3.y, a3.x - a1.x, a1.w - a3.w, a3.z - a1.z);
+        fft_write(output, base, line, layer, b0 + b2, horizontal, last, data.size, data.forward);
+        fft_write(output, base + data.span, line, layer, b1 + b3, horizontal, last, data.size, data.forward);
+        fft_write(output, base + 2 * data.span, line, layer, b0 - b2, horizontal, last, data.size, data.forward);
+        fft_write(output, base + 3 * data.span, line, layer, b1 - b3, horizontal, last, data.size, data.forward);
+    }
+}
+
+// first is the first stage of a forward transform
+fn fft_read(input: &StorageImageArray, i: u32, line: u32, layer: u32, horizontal: bool, first: bool) -> Vec4 {
+    let value = input.read(texel(i, line, layer, horizontal));
+    if first {
+        forward_prepare(value, i, line)
+    } else {
+        value
+    }
+}
+
+fn fft_write(
+    output: &StorageImageArray,
+    i: u32,
+    line: u32,
+    layer: u32,
+    value: Vec4,
+    horizontal: bool,
+    last: bool,
+    size: u32,
+    forward: u32,
+) {
+    let value = if last {
+        finish(value, i, line, size, forward)
+    } else {
+        value
+    };
     unsafe {
-        pingpong0.write(id.xy(), Vec4::new(h0, h1, 0.0, 1.0));
+        output.write(texel(i, line, layer, horizontal), value);
+    }
+}
+
+// a whole row is loaded into workgroup memory and every stage runs in the one dispatch, so a 2d
+// fft is 2 dispatches. one workgroup per row of each layer, threads loop over the butterflies as
+// there are at most 256 threads but up to 512 butterflies. runs in place so no pingpong texture
+// is needed
+#[spirv(compute(threads(256)))]
+pub fn hstep_shared(
+    #[spirv(local_invocation_id)] local_id: UVec3,
+    #[spirv(workgroup_id)] group: UVec3,
+    #[spirv(push_constant)] data: &SharedFFTData,
+    #[spirv(descriptor_set = 0, binding = 0)] pingpong0: &StorageImageArray,
+    #[spirv(workgroup)] row: &mut [Vec4; MAX_SHARED_FFT_SIZE],
+) {
+    shared_fft(pingpong0, row, local_id.x, group.x, group.y + data.layer, data, true);
+}
+
+// columns, also does the permute
+#[spirv(compute(threads(256)))]
+pub fn vstep_shared(
+    #[spirv(local_invocation_id)] local_id: UVec3,
+    #[spirv(workgroup_id)] group: UVec3,
+    #[spirv(push_constant)] data: &SharedFFTData,
+    #[spirv(descriptor_set = 0, binding = 0)] pingpong0: &StorageImageArray,
+    #[spirv(workgroup)] column: &mut [Vec4; MAX_SHARED_FFT_SIZE],
+) {
+    shared_fft(pingpong0, column, local_id.x, group.x, group.y + data.layer, data, false);
+}
+
+// in place radix 2, loading in bit reversed order means the output comes out in natural order
+fn shared_fft(
+    image: &StorageImageArray,
+    shared: &mut [Vec4; MAX_SHARED_FFT_SIZE],
+    thread: u32,
+    line: u32,
+    layer: u32,
+    data: &SharedFFTData,
+    horizontal: bool,
+) {
+    let size = data.size;
+    let forward = data.forward;
+    let mut logsize = 0;
+    while (1 << logsize) < size {
+        logsize += 1;
+    }
+
+    let mut i = thread;
+    while i < size {
+        shared[bit_reverse(i, logsize) as usize] = fft_read(image, i, line, layer, horizontal, horizontal && forward == 1);
+        i += SHARED_FFT_THREADS;
+    }
+    workgroup_memory_barrier_with_group_sync();
+
+    let mut span = 1;
+    while span < size {
+        let mut b = thread;
+        while b < size / 2 {
+            let k = b % span;
+            let top = ((b - k) * 2 + k) as usize;
+            let bottom = top + span as usize;
+            let w = twiddle(-consts::PI * k as f32 / span as f32);
+            let t = shared[top];
+            let u = complex_mult_pair(w, shared[bottom]);
+            shared[top] = t + u;
+            shared[bottom] = t - u;
+            b += SHARED_FFT_THREADS;
+        }
+        workgroup_memory_barrier_with_group_sync();
+        span *= 2;
+    }
+
+    let mut i = thread;
+    while i < size {
+        fft_write(image, i, line, layer, shared[i as usize], horizontal, !horizontal, size, forward);
+        i += SHARED_FFT_THREADS;
     }
 }
 
+fn texel(i: u32, line: u32, layer: u32, horizontal: bool) -> UVec3 {
+    if horizontal {
+        UVec3::new(i, line, layer)
+    } else {
+        UVec3::new(line, i, layer)
+    }
+}
+
+fn twiddle(angle: f32) -> Vec2 {
+    Vec2::new(angle.cos(), angle.sin())
+}
+
+// both packed signals at once
+fn complex_mult_pair(a: Vec2, b: Vec4) -> Vec4 {
+    let b0 = complex_mult(a, b.xy());
+    let b1 = complex_mult(a, b.zw());
+    Vec4::new(b0.x, b0.y, b1.x, b1.y)
+}
+
+// one butterfly texture per cascade size, so the size is pushed rather than read from consts
 #[spirv(compute(threads(1,8)))]
 pub fn precompute_butterfly(
     #[spirv(global_invocation_id)] id: UVec3,
-    #[spirv(uniform, descriptor_set = 0, binding = 0)] consts: &Constants,
-    #[spirv(descriptor_set = 1, binding = 1)] butterfly_tex: &StorageImage,
+    #[spirv(push_constant)] size: &u32,
+    #[spirv(descriptor_set = 0, binding = 0)] butterfly_tex: &StorageImage,
 ) {
-    let k = (id.y as f32 * consts.sim.size as f32 / 2.0_f32.powf(id.x as f32 + 1.0)) % consts.sim.size as f32;
-    let exp = -2.0 * consts::PI * k / consts.sim.size as f32;
+    let size = *size;
+    let mut logsize = 0;
+    while (1 << logsize) < size {
+        logsize += 1;
+    }
+    let k = (id.y as f32 * size as f32 / 2.0_f32.powf(id.x as f32 + 1.0)) % size as f32;
+    let exp = -2.0 * consts::PI * k / size as f32;
     let twiddle = Vec2::new(exp.cos(), exp.sin());
 
     let step = 2.0_f32.powf(id.x as f32);
@@ -125,8 +363,8 @@ pub fn precompute_butterfly(
         } else {
             yt -= 1;
         }
-        yt = bit_reverse(yt, consts.sim.logsize);
-        yb = bit_reverse(yb, consts.sim.logsize);
+        yt = bit_reverse(yt, logsize);
+        yb = bit_reverse(yb, logsize);
     } else {
         if wing {
             yb += step as u32;
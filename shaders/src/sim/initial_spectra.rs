@@ -2,53 +2,60 @@ use spirv_std::{
     spirv,
     num_traits::Float,
 };
-use crate::StorageImage;
+use crate::{StorageImage, StorageImageArray};
 use core::f32::consts::{self, PI};
 use spirv_std::glam::{UVec3, UVec2, Vec3Swizzles, Vec2, Vec4, Vec4Swizzles};
-use shared::{Constants, SimConstants};
+use shared::{CascadeParams, Constants, SimConstants, MEASURED_DIRECTIONS, MEASURED_FREQUENCIES};
 
 #[spirv(compute(threads(8,8)))]
 pub fn main(
 #[spirv(global_invocation_id)] id: UVec3,
 #[spirv(push_constant)] cascade: &u32,
 #[spirv(uniform, descriptor_set = 0, binding = 0)] consts: &Constants,
+#[spirv(storage_buffer, descriptor_set = 0, binding = 1)] cascades: &[CascadeParams],
 #[spirv(descriptor_set = 1, binding = 0)] gaussian_tex: &StorageImage,
+#[spirv(descriptor_set = 1, binding = 1)] measured_tex: &StorageImage,
+#[spirv(descriptor_set = 1, binding = 2)] heightmap_tex: &StorageImageArray,
 #[spirv(descriptor_set = 2, binding = 0)] wave_tex: &StorageImage,
 #[spirv(descriptor_set = 2, binding = 1)] spectrum_tex: &StorageImage
 ) {
-    let lengthscale = match cascade {
-        0 => consts.sim.lengthscale0,
-        1 => consts.sim.lengthscale1,
-        _ => consts.sim.lengthscale2,
-    } as f32;
-    let cutoff_high = match cascade {
-        0 => consts.sim.cutoff_high0,
-        1 => consts.sim.cutoff_high1,
-        _ => consts.sim.cutoff_high2,
-    };
-    let cutoff_low = match cascade {
-        0 => consts.sim.cutoff_low0,
-        1 => consts.sim.cutoff_low1,
-        _ => consts.sim.cutoff_low2,
-    };
-
-    let dk: f32 = 2.0 * consts::PI / lengthscale;
-    let n = id.x as f32 - 0.5 *  consts.sim.size as f32;
-    let m = id.y as f32 - 0.5 *  consts.sim.size as f32;
+    let params = cascades[*cascade as usize];
+    let dk: f32 = 2.0 * consts::PI / params.lengthscale;
+    let n = id.x as f32 - 0.5 * params.size as f32;
+    let m = id.y as f32 - 0.5 * params.size as f32;
     let k: Vec2 = Vec2::new(n, m) * dk;
     let k_length = k.length();
+    let in_band = k_length <= params.cutoff_high && k_length >= params.cutoff_low;
+    // the heightmap covers exactly one tile of the first cascade, so every wavenumber of that
+    // cascade is kept regardless of the cutoffs or the pattern would lose its large features
+    let painted = consts.sim.spectrum == 6 && *cascade == 0 && k_length > 0.0;
 
-    if k_length <= cutoff_high && k_length >= cutoff_low {
+    if in_band || painted {
         let theta = angle(k, consts.sim.wind_offset);
         let omega = dispersion_relation(k_length, &consts.sim);
         let domega_dk = dispersion_derivative(k_length, &consts.sim); //Derivative
-        let omega_peak = 22.0 * ((consts.sim.gravity * consts.sim.gravity) / (consts.sim.wind_speed * consts.sim.fetch)).powf(1.0 / 3.0);
-        let jonswap = jonswap(omega, omega_peak, &consts.sim);
-        let depth_attenuation = depth_attenuation(omega, &consts.sim);
-        let tma = jonswap * depth_attenuation;
-        let spread = final_spread(omega, omega_peak, theta, &consts);
-        let spectrum = 2.0 * tma * spread * domega_dk.abs() * dk * dk / k_length;
-        let h0 = 1.0 / 2.0_f32.sqrt() * gaussian_tex.read(id.xy()).xy() * spectrum.sqrt();
+        let wind_sea = match consts.sim.spectrum {
+            // measured spectra are already directional so skip the spreading function
+            5 => measured_spectrum(omega, angle(k, 0.0), &consts.sim, measured_tex),
+            // the heightmap replaces the wind sea, swells are still added on top
+            6 => 0.0,
+            _ => {
+                let omega_peak = peak_frequency(&consts.sim);
+                let energy = frequency_spectrum(omega, omega_peak, k_length, domega_dk, &consts.sim);
+                let spread = final_spread(omega, omega_peak, theta, k_length, dk, &consts);
+                energy * spread
+            }
+        };
+        let swells = swell_spectra(k, omega, &consts.sim);
+        let spectrum = if in_band {
+            2.0 * (wind_sea + swells) * domega_dk.abs() * dk * dk / k_length
+        } else {
+            0.0
+        };
+        let mut h0 = 1.0 / 2.0_f32.sqrt() * gaussian_tex.read(id.xy()).xy() * spectrum.sqrt();
+        if painted {
+            h0 += heightmap_spectrum(k, heightmap_tex.read(id.xy().extend(0)), &consts.sim);
+        }
         
         unsafe {
             wave_tex.write(id.xy(), Vec4::new(k.x, k.y, 1.0 / k_length, omega));
@@ -62,6 +69,22 @@ pub fn main(
     }
 }
 
+// the heightmap spectrum H(k) is deterministic so it skips the gaussian noise. evolve_spectra
+// adds h0(k) and conj(h0(-k)), so splitting H between the two halves of the plane facing with
+// and against the wind gives back H at t = 0 while every component travels downwind, rather
+// than the pattern splitting into two copies heading in opposite directions
+fn heightmap_spectrum(k: Vec2, spectrum: Vec4, consts: &SimConstants) -> Vec2 {
+    let facing = k.dot(Vec2::new(consts.wind_offset.cos(), consts.wind_offset.sin()));
+    let weight = if facing > 0.0 {
+        1.0
+    } else if facing == 0.0 {
+        0.5
+    } else {
+        0.0
+    };
+    spectrum.xy() * weight * consts.heightmap_amplitude
+}
+
 fn dispersion_relation(k: f32, consts: &SimConstants) -> f32 {
     (consts.gravity * k * (k * consts.depth).min(20.0).tanh()).sqrt()
 }
@@ -85,6 +108,29 @@ fn fmod(a: f32, b: f32) -> f32 {
     a - b * (a / b).floor()
 }
 
+// indices match the spectrum combo box in engine::ui
+fn frequency_spectrum(omega: f32, omega_p: f32, k: f32, domega_dk: f32, consts: &SimConstants) -> f32 {
+    match consts.spectrum {
+        1 => jonswap(omega, omega_p, consts),
+        2 => pierson_moskowitz(omega, omega_p, consts),
+        3 => phillips(omega, omega_p, consts),
+        // elfouhaily is defined over wavenumber so has to be converted to S(omega)
+        4 => elfouhaily(k, omega, consts) / domega_dk.abs(),
+        _ => jonswap(omega, omega_p, consts) * depth_attenuation(omega, consts),
+    }
+}
+
+fn peak_frequency(consts: &SimConstants) -> f32 {
+    match consts.spectrum {
+        // fully developed sea, so fetch is ignored
+        2 => 0.855 * consts.gravity / consts.wind_speed,
+        // peak of w^-5 * exp(-(w0/w)^4) sits at (4/5)^(1/4) * w0
+        3 => 0.8_f32.powf(0.25) * consts.gravity / consts.wind_speed,
+        4 => inverse_wave_age(consts) * consts.gravity / consts.wind_speed,
+        _ => 22.0 * ((consts.gravity * consts.gravity) / (consts.wind_speed * consts.fetch)).powf(1.0 / 3.0),
+    }
+}
+
 fn jonswap(omega: f32,omega_p: f32, consts: &SimConstants) -> f32 {
     let sigma: f32;
     if omega <= omega_p {
@@ -104,6 +150,112 @@ fn jonswap(omega: f32,omega_p: f32, consts: &SimConstants) -> f32 {
     (omega * omega * omega * omega * omega) * (-consts.beta * (omega_p / omega).powf(4.0)).exp() * consts.gamma.powf(r)
 }
 
+fn pierson_moskowitz(omega: f32, omega_p: f32, consts: &SimConstants) -> f32 {
+    let alpha = 0.0081;
+    alpha * consts.gravity * consts.gravity / omega.powf(5.0) * (-consts.beta * (omega_p / omega).powf(4.0)).exp()
+}
+
+// phillips spectrum rewritten in terms of omega using deep water dispersion, exp(-1 / (kL)^2)
+// with L = U^2 / g becomes exp(-(g / (U * omega))^4)
+fn phillips(omega: f32, omega_p: f32, consts: &SimConstants) -> f32 {
+    let alpha = 0.0081;
+    let omega_0 = omega_p / 0.8_f32.powf(0.25);
+    alpha * consts.gravity * consts.gravity / omega.powf(5.0) * (-(omega_0 / omega).powf(4.0)).exp()
+}
+
+// unified spectrum from elfouhaily et al 1997, returns S(k)
+fn elfouhaily(k: f32, omega: f32, consts: &SimConstants) -> f32 {
+    let u = consts.wind_speed;
+    let omega_c = inverse_wave_age(consts);
+    let k_p = consts.gravity * omega_c * omega_c / (u * u);
+    let c_p = (consts.gravity / k_p).sqrt();
+    let c = omega / k;
+
+    let gamma = if omega_c <= 1.0 {
+        1.7
+    } else {
+        1.7 + 6.0 * omega_c.log10()
+    };
+    let sigma = 0.08 * (1.0 + 4.0 / (omega_c * omega_c * omega_c));
+    let r = (-((k / k_p).sqrt() - 1.0).powf(2.0) / (2.0 * sigma * sigma)).exp();
+    let l_pm = (-1.25 * (k_p / k) * (k_p / k)).exp();
+    let j_p = gamma.powf(r);
+
+    // long wave curvature
+    let alpha_p = 0.006 * omega_c.sqrt();
+    let f_p = l_pm * j_p * (-omega_c / 10.0_f32.sqrt() * ((k / k_p).sqrt() - 1.0)).exp();
+    let b_l = 0.5 * alpha_p * (c_p / c) * f_p;
+
+    // short wave curvature, friction velocity from the charnock style roughness length
+    let c_m = 0.23;
+    let k_m = 370.0;
+    let z_0 = 3.7e-5 * u * u / consts.gravity * (u / c_p).powf(0.9);
+    let u_star = 0.41 * u / (10.0 / z_0).ln();
+    let alpha_m = if u_star <= c_m {
+        0.01 * (1.0 + (u_star / c_m).ln())
+    } else {
+        0.01 * (1.0 + 3.0 * (u_star / c_m).ln())
+    };
+    let f_m = l_pm * j_p * (-0.25 * (k / k_m - 1.0) * (k / k_m - 1.0)).exp();
+    let b_h = (0.5 * alpha_m * (c_m / c) * f_m).max(0.0);
+
+    (b_l + b_h) / (k * k * k)
+}
+
+// omega_c = U / c_p, limited by fetch
+fn inverse_wave_age(consts: &SimConstants) -> f32 {
+    let x = consts.gravity * consts.fetch / (consts.wind_speed * consts.wind_speed);
+    0.84 * ((x / 22000.0).powf(0.4)).tanh().powf(-0.75)
+}
+
+// bilinearly samples the resampled S(f, theta) table, rows span the measured frequency range
+// and columns span [0, 2pi). S(omega, theta) = S(f, theta) / 2pi as f = omega / 2pi
+fn measured_spectrum(omega: f32, theta: f32, consts: &SimConstants, measured_tex: &StorageImage) -> f32 {
+    let f = omega / (2.0 * PI);
+    if f < consts.measured_freq_min || f > consts.measured_freq_max {
+        return 0.0;
+    }
+    let row = (f - consts.measured_freq_min) / (consts.measured_freq_max - consts.measured_freq_min)
+        * (MEASURED_FREQUENCIES - 1) as f32;
+    let col = fmod(theta, 2.0 * PI) / (2.0 * PI) * MEASURED_DIRECTIONS as f32;
+
+    let row0 = (row.floor() as u32).min(MEASURED_FREQUENCIES - 1);
+    let row1 = (row0 + 1).min(MEASURED_FREQUENCIES - 1);
+    let col0 = (col.floor() as u32) % MEASURED_DIRECTIONS;
+    let col1 = (col0 + 1) % MEASURED_DIRECTIONS;
+    let tr = row - row.floor();
+    let tc = col - col.floor();
+
+    let low = measured_tex.read(UVec2::new(col0, row0)).x * (1.0 - tc) + measured_tex.read(UVec2::new(col1, row0)).x * tc;
+    let high = measured_tex.read(UVec2::new(col0, row1)).x * (1.0 - tc) + measured_tex.read(UVec2::new(col1, row1)).x * tc;
+    (low * (1.0 - tr) + high * tr) / (2.0 * PI)
+}
+
+// directional spectrum of every active swell train, added on top of the local wind sea
+fn swell_spectra(k: Vec2, omega: f32, consts: &SimConstants) -> f32 {
+    let mut sum = 0.0;
+    for i in 0..consts.swell_count as usize {
+        let swell = consts.swells[i];
+        let omega_p = 2.0 * PI / swell.peak_period;
+        let theta = angle(k, swell.direction);
+        sum += swell_spectrum(omega, omega_p, swell.significant_height, swell.peakedness) * cos_2s(theta, swell.spread);
+    }
+    sum
+}
+
+// jonswap parameterised by significant height and peak period instead of wind speed and fetch,
+// the pierson-moskowitz part integrates to Hs^2 / 16 and 1 - 0.287 ln(gamma) corrects for the peak
+fn swell_spectrum(omega: f32, omega_p: f32, hs: f32, gamma: f32) -> f32 {
+    let sigma = if omega <= omega_p { 0.07 } else { 0.09 };
+    let r = (
+        -1.0 * (omega - omega_p) * (omega - omega_p)
+        / (2.0 * omega_p * omega_p * sigma * sigma)
+    ).exp();
+    let pm = 5.0 / 16.0 * hs * hs * omega_p.powf(4.0) / omega.powf(5.0)
+        * (-1.25 * (omega_p / omega).powf(4.0)).exp();
+    pm * gamma.powf(r) * (1.0 - 0.287 * gamma.ln())
+}
+
 fn depth_attenuation(omega: f32, consts: &SimConstants) -> f32 {
     let omega_h = omega * (consts.depth / consts.gravity).sqrt();
     if omega_h <= 1.0 {
@@ -135,10 +287,78 @@ fn directional_spread(omega: f32, omega_p: f32, theta: f32, consts: &Constants)
     base * swell
 }
 
-fn final_spread(omega: f32, omega_p: f32, theta: f32, consts: &Constants) -> f32 {
-    let spread = directional_spread(omega, omega_p, theta, consts);
-    let integral = integral(omega_p, omega, consts);
-    spread * integral
+// indices match the spreading combo box in engine::ui, each of the analytic spreads is
+// normalised so it integrates to 1 over [-pi, pi]
+fn final_spread(omega: f32, omega_p: f32, theta: f32, k: f32, dk: f32, consts: &Constants) -> f32 {
+    match consts.sim.spreading {
+        1 => cos_2s(theta, hasselmann_exponent(omega, omega_p, &consts.sim)),
+        2 => longuet_higgins(theta, consts.sim.spread_exponent),
+        3 => positive_cosine_squared(theta),
+        4 => unidirectional(theta, k, dk),
+        _ => {
+            let spread = directional_spread(omega, omega_p, theta, consts);
+            let integral = integral(omega_p, omega, consts);
+            spread * integral
+        }
+    }
+}
+
+// mitsuyasu style cos^2s(theta / 2) spread
+fn cos_2s(theta: f32, s: f32) -> f32 {
+    let q = ((2.0 * s - 1.0) * 2.0_f32.ln() + 2.0 * ln_gamma(s + 1.0) - ln_gamma(2.0 * s + 1.0)).exp() / PI;
+    q * (theta / 2.0).cos().abs().powf(2.0 * s)
+}
+
+// spreading exponent fitted by hasselmann et al 1980
+fn hasselmann_exponent(omega: f32, omega_p: f32, consts: &SimConstants) -> f32 {
+    let ratio = omega / omega_p;
+    if ratio < 1.05 {
+        6.97 * ratio.powf(4.06)
+    } else {
+        let mu = -2.33 - 1.45 * (consts.wind_speed * omega_p / consts.gravity - 1.17);
+        9.77 * ratio.powf(mu)
+    }
+}
+
+// cos^2s(theta) over the forward half plane only
+fn longuet_higgins(theta: f32, s: f32) -> f32 {
+    if theta.abs() >= 0.5 * PI {
+        return 0.0;
+    }
+    let n = (ln_gamma(s + 1.0) - ln_gamma(s + 0.5)).exp() / PI.sqrt();
+    n * theta.cos().powf(2.0 * s)
+}
+
+fn positive_cosine_squared(theta: f32) -> f32 {
+    if theta.abs() >= 0.5 * PI {
+        return 0.0;
+    }
+    2.0 / PI * theta.cos() * theta.cos()
+}
+
+// a dirac delta along the wind, a texel at radius k covers roughly dk / k radians so all the
+// energy goes into the texel that the wind direction passes through
+fn unidirectional(theta: f32, k: f32, dk: f32) -> f32 {
+    let width = dk / k;
+    if theta.abs() < 0.5 * width {
+        1.0 / width
+    } else {
+        0.0
+    }
+}
+
+// stirling series, shifted upwards using ln(gamma(x)) = ln(gamma(x + 1)) - ln(x) as it is only
+// accurate for larger x, gamma itself overflows f32 for the exponents used by the spreads
+fn ln_gamma(x: f32) -> f32 {
+    let mut z = x;
+    let mut shift = 0.0;
+    while z < 7.0 {
+        shift -= z.ln();
+        z += 1.0;
+    }
+    let z2 = z * z;
+    shift + (z - 0.5) * z.ln() - z + 0.5 * (2.0 * PI).ln()
+        + 1.0 / (12.0 * z) - 1.0 / (360.0 * z * z2) + 1.0 / (1260.0 * z * z2 * z2)
 }
 
 fn integral(omega_p: f32, omega: f32, consts: &Constants) -> f32 {
@@ -173,13 +393,15 @@ fn normalisation_factor(s: f32) -> f32 {
 #[spirv(compute(threads(8,8)))]
 pub fn pack_conjugates(
     #[spirv(global_invocation_id)] id: UVec3,
-    #[spirv(uniform, descriptor_set = 0, binding = 0)] consts: &Constants,
+    #[spirv(push_constant)] cascade: &u32,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] cascades: &[CascadeParams],
     #[spirv(descriptor_set = 2, binding = 1)] spectrum_tex: &StorageImage,
 ) {
+    let size = cascades[*cascade as usize].size;
     let h0 = spectrum_tex.read(id.xy());
     let h0c = spectrum_tex.read(UVec2::new(
-        (consts.sim.size - id.x) % consts.sim.size,
-        (consts.sim.size - id.y) % consts.sim.size
+        (size - id.x) % size,
+        (size - id.y) % size
     )).xy();
     unsafe {
         spectrum_tex.write(id.xy(), Vec4::new(h0.x, h0.y, h0c.x, -h0c.y));
@@ -2,3 +2,5 @@ pub mod initial_spectra;
 pub mod evolve_spectra;
 pub mod fft;
 pub mod process_deltas;
+pub mod query;
+pub mod breaking;
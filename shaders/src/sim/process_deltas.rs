@@ -2,22 +2,31 @@ use spirv_std::{
     spirv,
     num_traits::Float,
 };
-use spirv_std::glam::{UVec3, Vec3Swizzles, Vec3, Vec4};
-use shared::Constants;
+use spirv_std::glam::{UVec2, UVec3, Vec2, Vec3Swizzles, Vec3, Vec4, Vec4Swizzles};
+use shared::{CascadeParams, Constants, SimConstants, MAX_FOAM_STEP, MAX_FOAM_SUBSTEPS};
 use crate::StorageImage;
 
 #[spirv(compute(threads(8,8)))]
 pub fn main(
     #[spirv(global_invocation_id)] id: UVec3,
+    #[spirv(push_constant)] cascade: &u32,
     #[spirv(uniform, descriptor_set = 0, binding = 0)] consts: &Constants,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] cascades: &[CascadeParams],
     #[spirv(descriptor_set = 1, binding = 0)] h_displacement: &StorageImage,
-    #[spirv(descriptor_set = 2, binding = 0)] v_displacement: &StorageImage,
-    #[spirv(descriptor_set = 3, binding = 0)] h_slope: &StorageImage,
-    #[spirv(descriptor_set = 4, binding = 0)] jacobian: &StorageImage,
-    #[spirv(descriptor_set = 5, binding = 3)] displacement_map: &StorageImage,
-    #[spirv(descriptor_set = 5, binding = 4)] normal_map: &StorageImage,
-    #[spirv(descriptor_set = 5, binding = 5)] foam_map: &StorageImage,
+    #[spirv(descriptor_set = 1, binding = 1)] v_displacement: &StorageImage,
+    #[spirv(descriptor_set = 1, binding = 2)] h_slope: &StorageImage,
+    #[spirv(descriptor_set = 1, binding = 3)] jacobian: &StorageImage,
+    #[spirv(descriptor_set = 1, binding = 4)] h_velocity: &StorageImage,
+    #[spirv(descriptor_set = 1, binding = 5)] v_motion: &StorageImage,
+    #[spirv(descriptor_set = 1, binding = 6)] h_acceleration: &StorageImage,
+    #[spirv(descriptor_set = 2, binding = 3)] displacement_map: &StorageImage,
+    #[spirv(descriptor_set = 2, binding = 4)] normal_map: &StorageImage,
+    #[spirv(descriptor_set = 2, binding = 5)] foam_map: &StorageImage,
+    #[spirv(descriptor_set = 2, binding = 6)] foam_history: &StorageImage,
+    #[spirv(descriptor_set = 2, binding = 7)] velocity_map: &StorageImage,
+    #[spirv(descriptor_set = 2, binding = 8)] acceleration_map: &StorageImage,
 ) {
+    let params = cascades[*cascade as usize];
     let dy = v_displacement.read(id.xy()).x;
     let dx = h_displacement.read(id.xy()).x;
     let dz = h_displacement.read(id.xy()).y;
@@ -32,15 +41,109 @@ pub fn main(
     let jxz = consts.sim.choppiness * v_displacement.read(id.xy()).y;
 
     let jacobian = consts.sim.foam_bias - (jxx * jzz - jxz * jxz);
-    let mut accumulation = foam_map.read(id.xy()).x * (-consts.sim.foam_decay).exp();
-    if jacobian >= consts.sim.injection_threshold {
-        accumulation += jacobian * consts.sim.injection_amount;
-    }
-    let foam = Vec3::splat(accumulation).extend(1.0);
+    // the displacement map still holds last frame's displacement at this point
+    let previous = displacement_map.read(id.xy());
+    let (velocity, acceleration) = if consts.sim.spectral_motion != 0 {
+        let h_velocity = h_velocity.read(id.xy());
+        let v_motion = v_motion.read(id.xy());
+        let h_acceleration = h_acceleration.read(id.xy());
+        (
+            Vec4::new(h_velocity.x * consts.sim.choppiness, v_motion.x, h_velocity.y * consts.sim.choppiness, 0.0),
+            Vec4::new(h_acceleration.x * consts.sim.choppiness, v_motion.y, h_acceleration.y * consts.sim.choppiness, 0.0),
+        )
+    } else {
+        let velocity = surface_velocity(displacement, previous, consts.deltatime);
+        (velocity, surface_velocity(velocity, velocity_map.read(id.xy()), consts.deltatime))
+    };
+    let offset = foam_offset(displacement, previous, &params, &consts.sim, consts.deltatime);
+    let advected = wrapped_bilinear(foam_history, id.xy().as_vec2() - offset, params.size);
+    let source = foam_history.read(id.xy()).y;
+    let foam = accumulate_foam(advected, source, jacobian, consts.deltatime, &consts.sim);
 
     unsafe {
         displacement_map.write(id.xy(), displacement);
         normal_map.write(id.xy(), normal);
         foam_map.write(id.xy(), foam);
+        velocity_map.write(id.xy(), velocity);
+        acceleration_map.write(id.xy(), acceleration);
+    }
+}
+
+// finite difference over the frame, zero while paused or seeking as no time passes. also gives the
+// acceleration from the velocity
+fn surface_velocity(displacement: Vec4, previous: Vec4, dt: f32) -> Vec4 {
+    if dt != 0.0 {
+        ((displacement - previous) / dt).truncate().extend(0.0)
+    } else {
+        Vec4::ZERO
+    }
+}
+
+// how many texels the foam moved this frame, it is then read from that far upstream. the maps are
+// lagrangian, the renderer moves every texel by its displacement, so foam that stays put in the
+// map already rides the orbital motion. what it lacks is the mean drift of the surface, taken as
+// a fraction of the wind speed, and the surge of a breaking crest carrying its whitecap forward,
+// taken as a fraction of the change in horizontal displacement
+fn foam_offset(displacement: Vec4, previous: Vec4, params: &CascadeParams, consts: &SimConstants, dt: f32) -> Vec2 {
+    let wind = Vec2::new(consts.wind_offset.cos(), consts.wind_offset.sin());
+    let drift = wind * consts.wind_speed * consts.foam_drift * dt;
+    // seeking moves the displacement without any time passing
+    let orbital = if dt != 0.0 {
+        (displacement - previous).xz() * consts.foam_orbital
+    } else {
+        Vec2::ZERO
+    };
+    (drift + orbital) * params.size as f32 / params.lengthscale
+}
+
+// semi-lagrangian lookup, wrapping as the foam tiles like everything else
+fn wrapped_bilinear(map: &StorageImage, p: Vec2, size: u32) -> Vec4 {
+    let size_f = size as f32;
+    let p = p - size_f * (p / size_f).floor();
+    let base = p.floor();
+    let t = p - base;
+    let x0 = base.x as u32 % size;
+    let y0 = base.y as u32 % size;
+    let x1 = (x0 + 1) % size;
+    let y1 = (y0 + 1) % size;
+    let top = map.read(UVec2::new(x0, y0)).lerp(map.read(UVec2::new(x1, y0)), t.x);
+    let bottom = map.read(UVec2::new(x0, y1)).lerp(map.read(UVec2::new(x1, y1)), t.x);
+    top.lerp(bottom, t.y)
+}
+
+// fresh whitecap foam decays at foam_decay per second and is injected at injection_amount per
+// second wherever the jacobian is over the threshold. with the source held constant
+// df/dt = s - decay * f has an exact solution, so a substep is stable for any dt. long frames are
+// split into substeps with the source ramping from the previous frame's value so a slow frame
+// doesnt inject as if the crest had been there for the whole frame. a fraction of the fresh foam
+// that decays is left behind as old foam, which fades far slower and forms the streaks. the
+// texel is (fresh + old, source, fresh, old)
+fn accumulate_foam(foam: Vec4, previous_source: f32, jacobian: f32, dt: f32, consts: &SimConstants) -> Vec4 {
+    let source = if jacobian >= consts.injection_threshold {
+        jacobian * consts.injection_amount
+    } else {
+        0.0
+    };
+    // foam isnt reversible, so running the clock backwards still integrates forwards
+    let dt = dt.abs();
+    let substeps = (dt / MAX_FOAM_STEP).ceil().max(1.0).min(MAX_FOAM_SUBSTEPS as f32) as u32;
+    let step = dt / substeps as f32;
+    let decay = (-consts.foam_decay * step).exp();
+    let old_decay = (-consts.old_foam_decay * step).exp();
+    // integral of exp(-foam_decay * t) over a substep
+    let weight = if consts.foam_decay > 0.0 {
+        (1.0 - decay) / consts.foam_decay
+    } else {
+        step
+    };
+
+    let mut fresh = foam.z;
+    let mut old = foam.w;
+    for i in 0..substeps {
+        let t = (i as f32 + 0.5) / substeps as f32;
+        let decayed = (fresh * (1.0 - decay)).max(0.0);
+        fresh = fresh * decay + (previous_source + (source - previous_source) * t) * weight;
+        old = old * old_decay + decayed * consts.old_foam_transfer;
     }
+    Vec4::new(fresh + old, source, fresh, old)
 }
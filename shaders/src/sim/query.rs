@@ -0,0 +1,114 @@
+use spirv_std::spirv;
+use spirv_std::glam::{UVec3, Vec2, Vec4, Vec4Swizzles};
+use shared::{CascadeParams, Constants, Impact, SurfaceSample, WakeSource};
+use crate::{bathymetry, bilinear, disturbance, StorageImage, StorageImageArray};
+
+// horizontal displacement is small next to the wavelengths involved, so the search for the
+// undisplaced point converges in a handful of steps
+const INVERSION_STEPS: u32 = 6;
+
+// samples the surface under a batch of world positions (x, _, z, _). main_vs moves each vertex
+// by its displacement, so the texel that ends up above a position is found by iterating
+// p = target - D(p) before reading the height
+#[spirv(compute(threads(64)))]
+pub fn main(
+    #[spirv(global_invocation_id)] id: UVec3,
+    #[spirv(push_constant)] count: &u32,
+    #[spirv(uniform, descriptor_set = 0, binding = 0)] consts: &Constants,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] cascades: &[CascadeParams],
+    #[spirv(descriptor_set = 1, binding = 0)] displacement_map: &StorageImageArray,
+    #[spirv(descriptor_set = 1, binding = 1)] normal_map: &StorageImageArray,
+    #[spirv(descriptor_set = 1, binding = 3)] velocity_map: &StorageImageArray,
+    #[spirv(descriptor_set = 1, binding = 4)] acceleration_map: &StorageImageArray,
+    #[spirv(storage_buffer, descriptor_set = 2, binding = 0)] positions: &[Vec4],
+    #[spirv(storage_buffer, descriptor_set = 2, binding = 1)] samples: &mut [SurfaceSample],
+    #[spirv(descriptor_set = 3, binding = 0)] bathymetry: &StorageImage,
+    #[spirv(storage_buffer, descriptor_set = 4, binding = 0)] wakes: &[WakeSource],
+    #[spirv(storage_buffer, descriptor_set = 4, binding = 1)] impacts: &[Impact],
+) {
+    if id.x >= *count {
+        return;
+    }
+    let target = positions[id.x as usize].xz();
+    let local = mesh_position(target, consts);
+
+    // the bathymetry is looked up at the undisplaced world position
+    let mut shift = Vec2::ZERO;
+    for _ in 0..INVERSION_STEPS {
+        let uv = (local - shift) / consts.sim.mesh_step;
+        let displacement = sample_cascades(displacement_map, uv, target - shift, consts, cascades, bathymetry, bathymetry::VECTOR);
+        shift = displacement.xz();
+    }
+    let uv = (local - shift) / consts.sim.mesh_step;
+    let rest = target - shift;
+    let mut displacement = sample_cascades(displacement_map, uv, rest, consts, cascades, bathymetry, bathymetry::VECTOR);
+    let mut normal = sample_cascades(normal_map, uv, rest, consts, cascades, bathymetry, bathymetry::SLOPE);
+    normal.y = 1.0;
+    let velocity = sample_cascades(velocity_map, uv, rest, consts, cascades, bathymetry, bathymetry::VECTOR);
+    let acceleration = sample_cascades(acceleration_map, uv, rest, consts, cascades, bathymetry, bathymetry::VECTOR);
+    if consts.sim.bathymetry != 0 {
+        displacement.y = bathymetry::break_waves(displacement.y, bathymetry::depth(rest, bathymetry, consts)).x;
+    }
+
+    // wakes and ripples go on top at the displaced position, which is target, with their slope by
+    // the same differences as main_vs
+    let e = 0.5 * consts.sim.mesh_step;
+    let added = disturbance(target, wakes, impacts, consts).x;
+    let added_x = disturbance(target + Vec2::new(e, 0.0), wakes, impacts, consts).x;
+    let added_z = disturbance(target + Vec2::new(0.0, e), wakes, impacts, consts).x;
+    displacement.y += added;
+    normal.x -= (added_x - added) / e;
+    normal.z -= (added_z - added) / e;
+
+    samples[id.x as usize] = SurfaceSample {
+        position: Vec4::new(target.x, displacement.y - consts.sim.height_offset, target.y, 1.0),
+        normal: normal.truncate().normalize().extend(0.0),
+        velocity: velocity.truncate().extend(0.0),
+        acceleration: acceleration.truncate().extend(0.0),
+    };
+}
+
+// undoes the centring and instance offsets of main_vs, giving the displaced position relative to
+// the corner of the tile under it. instances overlap slightly when instance_micro_offset < 1, the
+// later one wins as it does when rendering
+fn mesh_position(target: Vec2, consts: &Constants) -> Vec2 {
+    let width = consts.sim.size as f32 * consts.sim.mesh_step;
+    let spacing = width * consts.sim.instance_micro_offset;
+    let p = target + Vec2::splat(0.5 * width * consts.sim.instances as f32);
+    let last = (consts.sim.instances - 1) as f32;
+    let instance = (p / spacing).floor().clamp(Vec2::ZERO, Vec2::splat(last));
+    p - instance * spacing
+}
+
+// same sum over cascades as main_vs, wrapped first as uv can land outside the tile. rest is the
+// undisplaced world position and mode is how bathymetry::sample treats the map. uv is in mesh
+// texels. spray reads its crests through this too
+pub fn sample_cascades(
+    map: &StorageImageArray,
+    uv: Vec2,
+    rest: Vec2,
+    consts: &Constants,
+    cascades: &[CascadeParams],
+    bathymetry: &StorageImage,
+    mode: u32,
+) -> Vec4 {
+    let mut sum = Vec4::ZERO;
+    if consts.sim.bathymetry != 0 {
+        let cells = bathymetry::cells(rest, bathymetry, consts);
+        for i in 0..consts.sim.cascade_count {
+            let params = cascades[i as usize];
+            let p = uv * params.size as f32 / consts.sim.size as f32;
+            let refraction = bathymetry::refraction(&cells, &params, consts);
+            sum += bathymetry::sample(map, p, i, params.size, &refraction, mode) * params.scale_factor;
+        }
+    } else {
+        for i in 0..consts.sim.cascade_count {
+            let params = cascades[i as usize];
+            let size = params.size as f32;
+            let p = uv * size / consts.sim.size as f32;
+            let p = p - size * (p / size).floor();
+            sum += bilinear(map, p, i, params.size) * params.scale_factor;
+        }
+    }
+    sum
+}
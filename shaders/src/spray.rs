@@ -0,0 +1,195 @@
+use spirv_std::glam::{UVec2, UVec3, Vec2, Vec3, Vec4};
+use spirv_std::{spirv, image::Image2d, Sampler};
+use spirv_std::num_traits::Float;
+use shared::{surf_injection, CascadeParams, Constants, Particle, MAX_PARTICLES};
+use core::f32::consts::PI;
+use crate::sim::query::sample_cascades;
+use crate::{bathymetry, equirectangular_to_uv, lerp, reinhard_tonemap, StorageImage, StorageImageArray};
+
+// per second, how quickly a droplet picks up the wind
+const SPRAY_DRAG: f32 = 2.0;
+// below the still water level by this much a droplet is assumed to have landed
+const LANDED_DEPTH: f32 = 2.0;
+// droplets grow by this many times their size over their life as the spray spreads out
+const SPREAD: f32 = 3.0;
+// asymmetry of the henyey-greenstein phase function, water droplets scatter strongly forwards
+const FORWARD_SCATTERING: f32 = 0.7;
+
+// steps every particle and respawns dead ones. a dead particle tries one random texel of a random
+// instance each frame, with probability spray_rate * dt, and is thrown off the surface there if
+// the summed foam injection crosses spray_threshold, so spray comes off crests in proportion to
+// how much of the ocean is breaking without needing to count anything across threads
+#[spirv(compute(threads(64)))]
+pub fn spray_cs(
+    #[spirv(global_invocation_id)] id: UVec3,
+    #[spirv(uniform, descriptor_set = 0, binding = 0)] consts: &Constants,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] cascades: &[CascadeParams],
+    #[spirv(descriptor_set = 1, binding = 0)] displacement_map: &StorageImageArray,
+    #[spirv(descriptor_set = 1, binding = 1)] normal_map: &StorageImageArray,
+    #[spirv(descriptor_set = 1, binding = 2)] foam_map: &StorageImageArray,
+    #[spirv(descriptor_set = 1, binding = 3)] velocity_map: &StorageImageArray,
+    #[spirv(storage_buffer, descriptor_set = 2, binding = 0)] particles: &mut [Particle],
+    #[spirv(descriptor_set = 3, binding = 0)] bathymetry: &StorageImage,
+) {
+    if id.x >= MAX_PARTICLES {
+        return;
+    }
+    let dt = consts.deltatime;
+    // nothing moves while paused and spray isnt reversible
+    if dt <= 0.0 {
+        return;
+    }
+    let mut particle = particles[id.x as usize];
+    let wind = Vec3::new(consts.sim.wind_offset.cos(), 0.0, consts.sim.wind_offset.sin())
+        * consts.sim.wind_speed
+        * consts.sim.spray_wind;
+
+    if particle.position.w < particle.velocity.w {
+        let mut velocity = particle.velocity.truncate();
+        velocity.y -= consts.sim.gravity * dt;
+        let horizontal = (wind - velocity) * (1.0 - (-SPRAY_DRAG * dt).exp());
+        velocity += Vec3::new(horizontal.x, 0.0, horizontal.z);
+        let position = particle.position.truncate() + velocity * dt;
+        let age = particle.position.w + dt;
+        let age = if position.y < -consts.sim.height_offset - LANDED_DEPTH { particle.velocity.w } else { age };
+        particle = Particle {
+            position: position.extend(age),
+            velocity: velocity.extend(particle.velocity.w),
+        };
+    } else {
+        let mut seed = hash(id.x ^ hash(consts.time.to_bits()));
+        if random(&mut seed) < consts.sim.spray_rate * dt {
+            let size = consts.sim.size;
+            let texel = UVec2::new(hash(seed) % size, hash(seed ^ 0x9e3779b9) % size);
+            seed = hash(seed ^ 0x85ebca6b);
+            let instance = UVec2::new(seed % consts.sim.instances, hash(seed) % consts.sim.instances);
+
+            // the same placement as main_vs
+            let width = size as f32 * consts.sim.mesh_step;
+            let tiling = instance.as_vec2() * width * consts.sim.instance_micro_offset;
+            let positive = 0.5 * width * (consts.sim.instances as f32 - 1.0);
+            let rest = texel.as_vec2() * consts.sim.mesh_step - 0.5 * width + tiling - positive;
+
+            let uv = texel.as_vec2();
+            let mut foam = sample_cascades(foam_map, uv, rest, consts, cascades, bathymetry, bathymetry::SCALAR);
+            let mut displacement = sample_cascades(displacement_map, uv, rest, consts, cascades, bathymetry, bathymetry::VECTOR);
+            if consts.sim.bathymetry != 0 {
+                // surf throws spray as whitecaps do
+                let broken = bathymetry::break_waves(displacement.y, bathymetry::depth(rest, bathymetry, consts));
+                displacement.y = broken.x;
+                foam.y += surf_injection(broken.y, &consts.sim);
+            }
+            if foam.y > consts.sim.spray_threshold {
+                let mut normal = sample_cascades(normal_map, uv, rest, consts, cascades, bathymetry, bathymetry::SLOPE);
+                normal.y = 1.0;
+                let surface = sample_cascades(velocity_map, uv, rest, consts, cascades, bathymetry, bathymetry::VECTOR).truncate();
+                let position = Vec3::new(rest.x, -consts.sim.height_offset, rest.y) + displacement.truncate();
+
+                // thrown off along the normal, harder the further over the threshold, with some
+                // scatter so a crest doesnt fire every droplet the same way
+                let excess = (foam.y / consts.sim.spray_threshold.max(0.001)).min(2.0);
+                let scatter = Vec3::new(random(&mut seed) - 0.5, random(&mut seed), random(&mut seed) - 0.5);
+                let throw = (normal.truncate().normalize() + scatter) * consts.sim.spray_speed * excess;
+                let lifetime = consts.sim.spray_lifetime * (0.5 + random(&mut seed));
+                particle = Particle {
+                    position: position.extend(0.0),
+                    velocity: (surface + throw + wind * random(&mut seed)).extend(lifetime),
+                };
+            }
+        }
+    }
+    particles[id.x as usize] = particle;
+}
+
+// camera facing quads, two triangles per particle with no vertex buffer. dead particles collapse
+// to a point so nothing is drawn
+#[inline(never)]
+#[spirv(vertex)]
+pub fn spray_vs(
+    #[spirv(vertex_index)] vertex_index: u32,
+    #[spirv(instance_index)] instance_index: u32,
+    #[spirv(uniform, descriptor_set = 0, binding = 0)] consts: &Constants,
+    #[spirv(storage_buffer, descriptor_set = 3, binding = 0)] particles: &[Particle],
+    #[spirv(position)] out_pos: &mut Vec4,
+    out_corner: &mut Vec2,
+    out_world_pos: &mut Vec4,
+    out_fade: &mut f32,
+) {
+    let particle = particles[instance_index as usize];
+    let corners = [
+        Vec2::new(-1.0, -1.0),
+        Vec2::new(1.0, -1.0),
+        Vec2::new(1.0, 1.0),
+        Vec2::new(-1.0, -1.0),
+        Vec2::new(1.0, 1.0),
+        Vec2::new(-1.0, 1.0),
+    ];
+    let corner = corners[(vertex_index % 6) as usize];
+    let life = (particle.position.w / particle.velocity.w.max(0.0001)).min(1.0);
+    let alive = if particle.position.w < particle.velocity.w { 1.0 } else { 0.0 };
+
+    let centre = particle.position.truncate();
+    let to_eye = (consts.eye.truncate() - centre).normalize();
+    let right = Vec3::Y.cross(to_eye).normalize();
+    let up = to_eye.cross(right);
+    let size = consts.sim.spray_size * (1.0 + (SPREAD - 1.0) * life) * alive;
+    let world_pos = (centre + (right * corner.x + up * corner.y) * size).extend(1.0);
+
+    *out_pos = consts.camera_viewproj * world_pos;
+    *out_corner = corner;
+    *out_world_pos = world_pos;
+    // fades in quickly and out over the rest of its life
+    *out_fade = (life * 10.0).min(1.0) * (1.0 - life);
+}
+
+// soft round droplets. the sun is scattered mostly forwards, so spray between the eye and the sun
+// glows, and the sky lights it from above
+#[inline(never)]
+#[spirv(fragment)]
+pub fn spray_fs(
+    corner: Vec2,
+    world_pos: Vec4,
+    fade: f32,
+    #[spirv(uniform, descriptor_set = 0, binding = 0)] consts: &Constants,
+    #[spirv(descriptor_set = 1, binding = 0)] sampler: &Sampler,
+    #[spirv(descriptor_set = 2, binding = 0)] hdri: &Image2d,
+    output: &mut Vec4,
+) {
+    let r2 = corner.dot(corner);
+    let alpha = (-3.0 * r2).exp() * (1.0 - r2).max(0.0) * fade;
+
+    let pos = world_pos.truncate();
+    let l = (consts.shader.light.truncate() - pos).normalize();
+    let v = (consts.eye.truncate() - pos).normalize();
+    let phase = henyey_greenstein(l.dot(-v), FORWARD_SCATTERING) * 4.0 * PI;
+    let sky = hdri.sample(*sampler, equirectangular_to_uv(Vec3::Y)).truncate();
+    let color = consts.shader.foam_color.truncate() * (sky + consts.shader.sun_color.truncate() * (0.3 + phase));
+
+    // same fog as the ocean
+    let dist = (consts.eye - world_pos).length();
+    let max_dist = (consts.eye - 0.5 * consts.sim.size as f32 * consts.sim.mesh_step * consts.sim.instances as f32).length();
+    let t = ((dist - consts.shader.fog_offset) / (max_dist - consts.shader.fog_offset)).clamp(0.0, 1.0);
+    let fog = t.powf(consts.shader.fog_falloff) * consts.shader.fog_density;
+    let color = lerp(color, consts.shader.fog_color.truncate(), fog);
+
+    *output = reinhard_tonemap(color).extend(alpha);
+}
+
+// normalised over the sphere, cos_theta is between the light and view directions
+fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+}
+
+// pcg hash
+fn hash(x: u32) -> u32 {
+    let state = x.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+// uniform in [0, 1), advancing the seed
+fn random(seed: &mut u32) -> f32 {
+    *seed = hash(*seed);
+    (*seed >> 8) as f32 / (1u32 << 24) as f32
+}
@@ -0,0 +1,117 @@
+use spirv_std::glam::{Vec2, Vec4Swizzles};
+use spirv_std::num_traits::Float;
+use shared::{Constants, WakeSource, MAX_WAKES};
+use core::f32::consts::PI;
+
+// tan^2 of kelvin's half angle asin(1 / 3), outside of it no waves from the vessel arrive
+const KELVIN_TAN_SQUARED: f32 = 0.125;
+
+// height and foam of every wake at world (x, z). the closest point on each trail gives how far
+// behind the vessel and how far off its track the point is, and the steady pattern of a vessel
+// on a straight course is laid along the trail in those coordinates, so wakes bend with the path
+pub fn wake(p: Vec2, sources: &[WakeSource], consts: &Constants) -> Vec2 {
+    let mut result = Vec2::ZERO;
+    for i in 0..MAX_WAKES {
+        let source = sources[i];
+        if reaches(p, &source) {
+            result += source_wake(p, &source, consts);
+        }
+    }
+    result
+}
+
+// whether p is inside the circle around the trail that its kelvin wedge stays within
+pub fn reaches(p: Vec2, source: &WakeSource) -> bool {
+    source.count >= 2 && (p - source.bounds.xy()).length() < source.bounds.z
+}
+
+fn source_wake(p: Vec2, source: &WakeSource, consts: &Constants) -> Vec2 {
+    let mut closest = f32::MAX;
+    let mut along = 0.0;
+    let mut emitted = Vec2::ZERO;
+    let mut travelled = 0.0;
+    for j in 0..source.count as usize - 1 {
+        let a = source.points[j];
+        let b = source.points[j + 1];
+        let length = (b.xy() - a.xy()).length().max(0.0001);
+        let direction = (b.xy() - a.xy()) / length;
+        let mut t = (p - a.xy()).dot(direction).min(length);
+        // the first segment carries on ahead of the vessel for the bow wave
+        if j != 0 {
+            t = t.max(0.0);
+        }
+        let distance = (p - a.xy() - direction * t).length_squared();
+        if distance < closest {
+            closest = distance;
+            along = travelled + t;
+            emitted = a.zw().lerp(b.zw(), (t / length).max(0.0));
+        }
+        travelled += length;
+    }
+    let across = closest.sqrt();
+    let (speed, time) = (emitted.x, emitted.y);
+    if speed <= 0.0 {
+        return Vec2::ZERO;
+    }
+
+    let age = (consts.time - time).max(0.0);
+    let fade = (-age / consts.sim.wake_lifetime.max(0.001)).exp();
+    // tapers off over the last fifth of the trail rather than ending in a cliff
+    let tail = ((travelled - along) / (0.2 * travelled)).clamp(0.0, 1.0);
+    let amplitude = consts.sim.wake_amplitude * speed * speed / (2.0 * consts.sim.gravity) * fade * tail;
+    let (length, beam) = (source.length, source.beam);
+
+    // crests at the bow and stern and a trough amidships, along is measured from the centre
+    let near = hump(along + 0.5 * length, across, length, beam)
+        - 0.6 * hump(along, across, length, beam)
+        + 0.5 * hump(along - 0.5 * length, across, length, beam);
+    // the kelvin pattern spreads from the bow
+    let behind = along + 0.5 * length;
+    let far = if behind > 0.0 {
+        0.5 * kelvin(behind, across, speed, length, consts)
+    } else {
+        0.0
+    };
+
+    // churned water behind the hull, widening as it moves away, and white water at the bow
+    let width = 0.5 * beam + 0.05 * along.max(0.0);
+    let strip = (behind / (0.5 * length)).clamp(0.0, 1.0) * (-(across / width).powi(2)).exp() * 0.5 * beam / width;
+    let bow = hump(along + 0.5 * length, across, length, beam);
+    let foam = consts.sim.wake_foam * (speed / 4.0).min(1.0) * fade * tail * (strip + bow);
+
+    Vec2::new(amplitude * (near + far), foam)
+}
+
+fn hump(x: f32, y: f32, length: f32, beam: f32) -> f32 {
+    (-(x / (0.3 * length)).powi(2) - (y / (0.75 * beam)).powi(2)).exp()
+}
+
+// stationary phase solution for a point disturbance moving in a straight line over deep water.
+// at a point x behind and y across, waves travelling at an angle with tangent t arrive when
+// y / x = t / (1 + 2t^2), which has a transverse and a divergent solution inside the kelvin angle
+fn kelvin(x: f32, y: f32, speed: f32, length: f32, consts: &Constants) -> f32 {
+    let q = y / x;
+    if q * q >= KELVIN_TAN_SQUARED {
+        return 0.0;
+    }
+    let root = (1.0 - q * q / KELVIN_TAN_SQUARED).sqrt();
+    let transverse = if q > 0.0001 { (1.0 - root) / (4.0 * q) } else { q };
+    let divergent = (1.0 + root) / (4.0 * q.max(0.0001));
+    // both systems meet at the cusp lines, where the stationary phase result blows up
+    let cusp = (root / 0.3).clamp(0.0, 1.0);
+    let cusp = cusp * cusp * (3.0 - 2.0 * cusp);
+    let spreading = (length / (x * x + y * y).sqrt().max(length)).sqrt();
+
+    let k0 = consts.sim.gravity / (speed * speed);
+    // waves finer than the mesh are faded out rather than aliased
+    let k_max = PI / consts.sim.mesh_step;
+    (kelvin_wave(x, y, transverse, k0, k_max) + kelvin_wave(x, y, divergent, k0, k_max)) * cusp * spreading
+}
+
+// the wavenumber is k0 sec^2 of the angle, and the phase is k along the direction of travel
+fn kelvin_wave(x: f32, y: f32, tangent: f32, k0: f32, k_max: f32) -> f32 {
+    let secant = (1.0 + tangent * tangent).sqrt();
+    let k = k0 * secant * secant;
+    let fade = (1.0 - k / k_max).max(0.0);
+    (k0 * secant * (x + y * tangent)).cos() * fade
+}
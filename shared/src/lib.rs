@@ -100,21 +100,97 @@ impl Default for ShaderConstants {
     }
 }
 
+// resolution of the resampled measured spectrum texture, rows are frequency and columns direction
+pub const MEASURED_FREQUENCIES: u32 = 64;
+pub const MEASURED_DIRECTIONS: u32 = 72;
+
+// upper bound on swell trains so the uniform buffer stays fixed size
+pub const MAX_SWELLS: usize = 4;
+
+// aligned so the array in SimConstants has a stride of a multiple of 16 bytes in the uniform buffer
+#[repr(C, align(16))]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SwellSystem {
+    pub direction: f32,
+    pub peak_period: f32,
+    pub significant_height: f32,
+    pub spread: f32,
+    // jonswap gamma of this swell alone, swell that has travelled far is much narrower than the
+    // wind sea so it doesnt share SimConstants::gamma
+    pub peakedness: f32,
+}
+impl Default for SwellSystem {
+    fn default() -> Self {
+        Self {
+            direction: 0.0,
+            peak_period: 10.0,
+            significant_height: 1.0,
+            spread: 20.0,
+            peakedness: 7.0,
+        }
+    }
+}
+
+// foam is integrated in substeps of at most this many seconds, capped so a stalled frame cant
+// run away with the dispatch
+pub const MAX_FOAM_STEP: f32 = 1.0 / 60.0;
+pub const MAX_FOAM_SUBSTEPS: u32 = 8;
+
+// upper bound on cascades, only limits the size of the cascade buffer and the ui
+pub const MAX_CASCADES: usize = 8;
+
+// parameters of a single cascade, stored in a storage buffer indexed by cascade rather than in
+// the uniform so the number of cascades can change at runtime
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CascadeParams {
+    // fft resolution of this cascade, independent of the mesh resolution in SimConstants::size
+    pub size: u32,
+    pub lengthscale: f32,
+    pub cutoff_low: f32,
+    pub cutoff_high: f32,
+    pub scale_factor: f32,
+}
+impl Default for CascadeParams {
+    fn default() -> Self {
+        Self {
+            size: 128,
+            lengthscale: 64.0,
+            cutoff_low: 0.0,
+            cutoff_high: 999.0,
+            scale_factor: 1.0,
+        }
+    }
+}
+
+pub const DEFAULT_CASCADES: [CascadeParams; 3] = [
+    CascadeParams {
+        size: 128,
+        lengthscale: 20.0,
+        cutoff_low: 0.00000001,
+        cutoff_high: 1.0,
+        scale_factor: 0.8,
+    },
+    CascadeParams {
+        size: 128,
+        lengthscale: 124.0,
+        cutoff_low: 1.0,
+        cutoff_high: 2.0,
+        scale_factor: 1.0,
+    },
+    CascadeParams {
+        size: 128,
+        lengthscale: 256.0,
+        cutoff_low: 2.0,
+        cutoff_high: 999.0,
+        scale_factor: 1.0,
+    },
+];
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct SimConstants {
     pub size: u32,
-    pub lengthscale0: u32,
-    pub cutoff_low0: f32,
-    pub cutoff_high0: f32,
-    pub lengthscale1: u32,
-    pub cutoff_low1: f32,
-    pub cutoff_high1: f32,
-    pub lengthscale2: u32,
-    pub cutoff_low2: f32,
-    pub cutoff_high2: f32,
-    pub lengthscale0_sf: f32,
-    pub lengthscale1_sf: f32,
-    pub lengthscale2_sf: f32,
+    pub cascade_count: u32,
     pub mesh_step: f32,
     pub standard_deviation: f32,
     pub mean: f32,
@@ -122,17 +198,49 @@ pub struct SimConstants {
     pub gravity: f32,
     pub beta: f32,
     pub gamma: f32,
+    pub spectrum: u32,
+    pub measured_freq_min: f32,
+    pub measured_freq_max: f32,
+    pub heightmap_amplitude: f32,
     pub wind_speed: f32,
     pub wind_offset: f32,
     pub fetch: f32,
     pub choppiness: f32,
     pub logsize: u32,
     pub swell: f32,
+    pub swell_count: u32,
+    pub swells: [SwellSystem; MAX_SWELLS],
+    pub spreading: u32,
+    pub spread_exponent: f32,
     pub integration_step: f32,
     pub foam_bias: f32,
     pub foam_decay: f32,
     pub injection_threshold: f32,
     pub injection_amount: f32,
+    pub foam_drift: f32,
+    pub foam_orbital: f32,
+    pub old_foam_transfer: f32,
+    pub old_foam_decay: f32,
+    pub spectral_motion: u32,
+    pub wake_amplitude: f32,
+    pub wake_foam: f32,
+    pub wake_lifetime: f32,
+    pub impact_foam: f32,
+    pub bathymetry: u32,
+    pub bathymetry_x: f32,
+    pub bathymetry_z: f32,
+    pub bathymetry_width: f32,
+    pub bathymetry_length: f32,
+    pub bathymetry_scale: f32,
+    pub bathymetry_offset: f32,
+    pub bottom_friction: f32,
+    pub breaking_threshold: f32,
+    pub spray_threshold: f32,
+    pub spray_rate: f32,
+    pub spray_speed: f32,
+    pub spray_wind: f32,
+    pub spray_lifetime: f32,
+    pub spray_size: f32,
     pub height_offset: f32,
     pub instances: u32,
     pub instance_micro_offset: f32,
@@ -140,25 +248,15 @@ pub struct SimConstants {
 }
 impl Default for SimConstants {
     fn default() -> Self {
-        // Defining simulation resolution, cannot be updated at runtime so defined here
+        // Defining default simulation resolution, changed at runtime through Engine::set_resolution
         let size = 128;
         // Defining seed for gaussian number
         let seed = 1;
         Self {
             depth: 500.0,
             size,
-            lengthscale0: 20,
-            cutoff_low0: 0.00000001,
-            cutoff_high0: 1.0,
-            lengthscale0_sf: 0.8,
-            lengthscale1: 124,
-            cutoff_low1: 1.0,
-            cutoff_high1: 2.0, 
-            lengthscale1_sf: 1.0,
-            lengthscale2: 256,
-            cutoff_low2: 2.0,
-            cutoff_high2: 999.0,
-            lengthscale2_sf: 1.0,
+            // kept in sync with the cascade buffer every frame
+            cascade_count: DEFAULT_CASCADES.len() as u32,
             wind_speed: 0.5,
             fetch: 100000.0,
             choppiness: 0.6,
@@ -169,14 +267,65 @@ impl Default for SimConstants {
             gravity: 9.81,
             beta: 5.0 / 4.0,
             gamma: 3.3,
+            // TMA, see engine::ui for the full list of spectrum models
+            spectrum: 0,
+            // overwritten when a measured spectrum is loaded
+            measured_freq_min: 0.0,
+            measured_freq_max: 0.0,
+            // grayscale heightmaps come in as 0..1 so this is their height in metres
+            heightmap_amplitude: 1.0,
             wind_offset: f32::consts::FRAC_PI_4,
             logsize: 0,
             swell: 0.1,
+            swell_count: 0,
+            swells: [SwellSystem::default(); MAX_SWELLS],
+            // donelan-banner, see engine::ui for the full list of spreading functions
+            spreading: 0,
+            spread_exponent: 2.0,
             integration_step: 0.01,
             foam_bias: 0.92,
-            foam_decay: 0.3,
+            // decay and injection are per second, these match the old per frame values at 60fps
+            foam_decay: 18.0,
             injection_threshold: -0.3,
-            injection_amount: 1.0,
+            injection_amount: 60.0,
+            // surface drift is roughly 3% of the wind speed
+            foam_drift: 0.03,
+            foam_orbital: 0.3,
+            // fraction of decaying whitecap foam left behind as streaks, which decay per second
+            old_foam_transfer: 0.5,
+            old_foam_decay: 0.2,
+            // velocity and acceleration go through the ifft with everything else, otherwise they
+            // are differenced between frames, which is cheaper but noisy and zero while paused
+            spectral_motion: 1,
+            // fraction of the stagnation height u^2 / 2g a bow wave reaches
+            wake_amplitude: 0.25,
+            wake_foam: 1.0,
+            // seconds for a wake to fade to 1 / e of its height
+            wake_lifetime: 30.0,
+            impact_foam: 1.0,
+            // set once a bathymetry map is loaded, the map covers width by length metres from
+            // (bathymetry_x, bathymetry_z) and its values become elevations through scale and
+            // offset, so by default a grayscale image spans 45 m deep to 5 m above the water
+            bathymetry: 0,
+            bathymetry_x: -500.0,
+            bathymetry_z: -500.0,
+            bathymetry_width: 1000.0,
+            bathymetry_length: 1000.0,
+            bathymetry_scale: 50.0,
+            bathymetry_offset: -45.0,
+            // metres, how strongly the seabed damps waves that feel it
+            bottom_friction: 2.0,
+            // jacobian below which the surface counts as breaking, 0 is where it folds over
+            breaking_threshold: 0.2,
+            // spray leaves crests injecting more foam than the threshold per second. rate is how
+            // often per second a spare particle tries a random spot, speed is how hard it is
+            // thrown off the surface and wind is the fraction of the wind speed it is carried at
+            spray_threshold: 40.0,
+            spray_rate: 4.0,
+            spray_speed: 3.0,
+            spray_wind: 0.6,
+            spray_lifetime: 2.5,
+            spray_size: 0.12,
             height_offset: 4.5,
             instances: 5,
             instance_micro_offset: 0.99,
@@ -189,4 +338,126 @@ impl Default for SimConstants {
 pub struct FFTData {
     pub stage: u32,
     pub pingpong: u32,
+    // every cascade's deltas are layers of one array, so a batch starts at this layer
+    pub layer: u32,
+    pub size: u32,
+    // 1 for spatial -> spectral, 0 for the usual spectral -> spatial
+    pub forward: u32,
+}
+
+// a whole row of the largest size has to fit in workgroup memory, 1024 * 16 bytes is exactly
+// the default 16kb limit
+pub const MAX_SHARED_FFT_SIZE: usize = 1024;
+pub const SHARED_FFT_THREADS: u32 = 256;
+
+// push constants for the stockham fft, span is the length of the sub transforms already
+// combined by previous stages
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct StockhamData {
+    pub size: u32,
+    pub span: u32,
+    pub radix: u32,
+    pub pingpong: u32,
+    pub layer: u32,
+    pub forward: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SharedFFTData {
+    pub size: u32,
+    pub layer: u32,
+    pub forward: u32,
+}
+
+// result of a surface query at a world position, height is in position.y. normal, velocity and
+// acceleration are the sums over every cascade, the same as the vertex shader sees
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct SurfaceSample {
+    pub position: Vec4,
+    pub normal: Vec4,
+    pub velocity: Vec4,
+    pub acceleration: Vec4,
+}
+
+// upper bound on floating bodies, only limits the size of the instance buffer
+pub const MAX_BODIES: usize = 32;
+
+// per body data for the body shaders, indexed by instance
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BodyInstance {
+    pub model: Mat4,
+    pub color: Vec4,
+}
+
+// wakes are drawn for at most this many moving bodies, each along a trail of WAKE_POINTS
+pub const MAX_WAKES: usize = 8;
+pub const WAKE_POINTS: usize = 32;
+
+// the trail a moving body leaves, points are (x, z, speed, time) newest first and the first is
+// the body itself. bounds is (x, z, radius) of a circle outside of which the wake is zero
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct WakeSource {
+    pub points: [Vec4; WAKE_POINTS],
+    pub bounds: Vec4,
+    pub count: u32,
+    pub length: f32,
+    pub beam: f32,
+    pub _padding: u32,
+}
+
+impl Default for WakeSource {
+    fn default() -> Self {
+        Self {
+            points: [Vec4::ZERO; WAKE_POINTS],
+            bounds: Vec4::ZERO,
+            count: 0,
+            length: 0.0,
+            beam: 0.0,
+            _padding: 0,
+        }
+    }
+}
+
+// breaking detection sums the mesh in squares of this many texels a side
+pub const BREAKING_BLOCK: u32 = 8;
+
+// size of the spray particle buffer, particles are only emitted into slots that have died
+pub const MAX_PARTICLES: u32 = 16384;
+
+// a droplet of spray, position.w is its age and velocity.w its lifetime. it is dead once its age
+// reaches its lifetime, so a zeroed buffer is all dead particles
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Particle {
+    pub position: Vec4,
+    pub velocity: Vec4,
+}
+
+// bathymetry maps are resampled to this square resolution whatever their extents
+pub const BATHYMETRY_SIZE: u32 = 512;
+
+// surf foam from depth limited breaking is the fraction of the height lost, 0 to 1, while whitecaps
+// inject foam per second. surf is injected as a jacobian of the same size would be, so spray
+// compares both against spray_threshold
+pub fn surf_injection(surf: f32, sim: &SimConstants) -> f32 {
+    surf * sim.injection_amount
+}
+
+// ripples from at most this many impacts at once, each lasting IMPACT_LIFETIME seconds
+pub const MAX_IMPACTS: usize = 16;
+pub const IMPACT_LIFETIME: f32 = 30.0;
+
+// something hitting the water, left as a gaussian crater of radius and depth at origin (x, z)
+// and time origin.z. a zero radius marks an unused slot
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Impact {
+    pub origin: Vec4,
+    pub radius: f32,
+    pub depth: f32,
+    pub foam: f32,
+    pub _padding: u32,
 }
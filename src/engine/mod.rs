@@ -1,11 +1,14 @@
-use super::{FORMAT, WG_SIZE};
+use super::FORMAT;
 use crate::{cast_slice, Result};
 use {
     renderer::Renderer,
     scene::{Mesh, Scene},
-    crate::sim::Simulation,
+    crate::sim::{bodies::Shape, cpu::CpuSimulation, heightmap::Heightmap, measured::MeasuredSpectrum, Simulation},
     ui::UI,
 };
+use glam::{Vec2, Vec3};
+use shared::{SimConstants, StockhamData};
+use std::mem;
 use winit::event::{Event, WindowEvent};
 use winit::event_loop::EventLoop;
 use winit::keyboard::{KeyCode, PhysicalKey};
@@ -27,6 +30,47 @@ pub struct Engine<'a> {
     pub ui: UI,
 }
 
+// everything the simulation and renderer need from the device, shared with the headless tests
+pub fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+    let required_limits = wgpu::Limits {
+        // process_deltas binds every delta, motion and map layer of a cascade
+        max_storage_textures_per_shader_stage: 16,
+        max_bind_groups: 6,
+        // StockhamData is the largest push constant
+        max_push_constant_size: mem::size_of::<StockhamData>() as u32,
+        ..Default::default()
+    };
+    pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            required_features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                | wgpu::Features::VERTEX_WRITABLE_STORAGE
+                | wgpu::Features::PUSH_CONSTANTS,
+            required_limits,
+            memory_hints: wgpu::MemoryHints::Performance,
+            label: None,
+        },
+        None,
+    ))
+}
+
+// a software adapter so the tests run on ci without a gpu. the gpu tests fail rather than skip
+// where there isnt one, so a missing adapter cant pass for a working backend
+#[cfg(test)]
+pub fn fallback_device() -> (wgpu::Device, wgpu::Queue) {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::default(),
+        force_fallback_adapter: true,
+        compatible_surface: None,
+    }))
+    .expect("needs a wgpu fallback adapter");
+    request_device(&adapter).expect("fallback adapter lacks a required feature or limit")
+}
+
+pub fn create_shader(device: &wgpu::Device) -> wgpu::ShaderModule {
+    device.create_shader_module(wgpu::include_spirv!(env!("shaders.spv")))
+}
+
 impl<'a> Engine<'a> {
     pub fn new(window: &'a winit::window::Window) -> Self {
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
@@ -38,26 +82,8 @@ impl<'a> Engine<'a> {
         }))
         .expect("failed to create adapter");
 
-        let required_limits = wgpu::Limits {
-            max_storage_textures_per_shader_stage: 6,
-            max_bind_groups: 6,
-            max_push_constant_size: 8,
-            ..Default::default()
-        };
-        let (device, queue) = pollster::block_on(adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                required_features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
-                    | wgpu::Features::VERTEX_WRITABLE_STORAGE
-                    | wgpu::Features::PUSH_CONSTANTS,
-                required_limits,
-                memory_hints: wgpu::MemoryHints::Performance,
-                label: None,
-            },
-            None,
-        ))
-        .expect("failed to create device & queue");
-
-        let shader = device.create_shader_module(wgpu::include_spirv!(env!("shaders.spv")));
+        let (device, queue) = request_device(&adapter).expect("failed to create device & queue");
+        let shader = create_shader(&device);
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -71,7 +97,7 @@ impl<'a> Engine<'a> {
         };
         surface.configure(&device, &config);
 
-        let scene = Scene::new(&device, window);
+        let scene = Scene::new(&device, config.width as f32 / config.height as f32);
         let simulation = Simulation::new(&device, &queue, &shader, &scene);
         let renderer = Renderer::new(&device, &queue, &shader, window, &simulation, &scene);
         let ui = UI::new(&device, &queue, window, &shader, &renderer, &scene);
@@ -91,8 +117,6 @@ impl<'a> Engine<'a> {
 
     pub fn run(&mut self, event_loop: EventLoop<()>) -> Result {
         let mut last_frame = std::time::Instant::now();
-        let mut first_frame = true;
-        let workgroup_size = self.scene.consts.sim.size / WG_SIZE;
 
         event_loop.run(move |event, elwt| match event {
             Event::AboutToWait => self.window.request_redraw(),
@@ -109,6 +133,14 @@ impl<'a> Engine<'a> {
                 match event {
                     WindowEvent::RedrawRequested => {
                         self.scene.update_redraw(self.window);
+                        // resizes asked for last frame, applied before anything is recorded so
+                        // no pass runs with the new sizes against the old textures
+                        if let Some(size) = self.ui.state.resolution.take() {
+                            self.set_resolution(size);
+                        }
+                        if self.simulation.outdated(&self.scene) {
+                            self.simulation.rebuild_cascades(&self.device, &self.queue, &self.scene);
+                        }
 
                         let surface = self
                             .surface
@@ -121,64 +153,29 @@ impl<'a> Engine<'a> {
                             .device
                             .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
 
-                        if first_frame {
-                            self.simulation.butterfly_precompute_pass.compute(
-                                &mut encoder,
-                                "Precompute Butterfly",
-                                &[
-                                    &self.scene.consts_bind_group,
-                                    &self.simulation.simdata.bind_group,
-                                ],
-                                self.scene.consts.sim.size.ilog2(),
-                                self.scene.consts.sim.size / WG_SIZE,
-                            );
-                        }
-
                         // Compute Initial spectrum on param change
                         if self.scene.consts_changed {
                             self.scene.write(&self.queue);
-                            self.simulation.compute_initial(
-                                &mut encoder, 
-                                &[
-                                    &self.scene.consts_bind_group,
-                                    &self.simulation.simdata.bind_group,
-                                    &self.simulation.cascade0.bind_group,
-                                ],
-                                0, 
-                                workgroup_size,
-                                workgroup_size,
-                            );
-                            self.simulation.compute_initial(
-                                &mut encoder, 
-                                &[
-                                    &self.scene.consts_bind_group,
-                                    &self.simulation.simdata.bind_group,
-                                    &self.simulation.cascade1.bind_group,
-                                ],
-                                1, 
-                                workgroup_size,
-                                workgroup_size,
-                            );
-                            self.simulation.compute_initial(
-                                &mut encoder, 
-                                &[
-                                    &self.scene.consts_bind_group,
-                                    &self.simulation.simdata.bind_group,
-                                    &self.simulation.cascade2.bind_group,
-                                ],
-                                2, 
-                                workgroup_size,
-                                workgroup_size,
-                            );
+                            self.simulation.compute_initial_spectra(&mut encoder, &self.scene);
+                            if let Some(cpu) = &mut self.simulation.cpu {
+                                cpu.compute_initial(&self.scene.consts, &self.scene.cascades, &self.simulation.simdata);
+                            }
                             // updates mesh based on mesh_step input, technically redundant to do
                             // on every param change but not an issue in any practical sense
                             self.scene.mesh = Mesh::new(&self.device, &self.scene.consts);
                         }
 
                         // per frame computation
-                        self.simulation.compute_cascade(&mut encoder, &self.simulation.cascade0, &mut self.scene, workgroup_size, 0);
-                        self.simulation.compute_cascade(&mut encoder, &self.simulation.cascade1, &mut self.scene, workgroup_size, 1);
-                        self.simulation.compute_cascade(&mut encoder, &self.simulation.cascade2, &mut self.scene, workgroup_size, 2);
+                        if let Some(cpu) = &mut self.simulation.cpu {
+                            cpu.compute(&self.scene.consts, &self.scene.cascades);
+                            cpu.upload(&self.queue, &self.simulation.maps);
+                        } else {
+                            self.simulation.compute(&mut encoder, &self.scene);
+                        }
+                        if self.ui.state.clear_spray {
+                            self.simulation.spray.clear(&mut encoder);
+                        }
+                        self.simulation.compute_spray(&mut encoder, &self.scene);
 
                         // Render Skybox
                         self.renderer
@@ -197,20 +194,48 @@ impl<'a> Engine<'a> {
                                 &self.scene.consts_bind_group,
                                 &self.renderer.sampler_bind_group,
                                 &self.renderer.hdri.bind_group,
-                                &self.simulation.cascade0.bind_group,
-                                &self.simulation.cascade1.bind_group,
-                                &self.simulation.cascade2.bind_group,
+                                &self.simulation.maps.bind_group,
+                                &self.simulation.wakes.bind_group,
+                                &self.simulation.bathymetry.texture.bind_group,
                             ],
                             &surface_view,
                             &self.scene.mesh,
                             self.scene.consts.sim.instances,
                         );
+                        self.renderer.render_bodies(
+                            &mut encoder,
+                            &[
+                                &self.scene.consts_bind_group,
+                                &self.renderer.sampler_bind_group,
+                                &self.renderer.hdri.bind_group,
+                                &self.simulation.bodies.bind_group,
+                            ],
+                            &surface_view,
+                            &self.simulation.bodies,
+                        );
+                        self.renderer.render_spray(
+                            &mut encoder,
+                            &[
+                                &self.scene.consts_bind_group,
+                                &self.renderer.sampler_bind_group,
+                                &self.renderer.hdri.bind_group,
+                                &self.simulation.spray.bind_group,
+                            ],
+                            &surface_view,
+                        );
 
                         // UI Pass
                         let consts_copy = self.scene.consts;
+                        let cascades_copy = self.scene.cascades.clone();
                         self.ui.update_cursor(self.window);
                         let ui_frame = self.ui.context.frame();
-                        self.ui.focused = ui::build(ui_frame, &mut self.scene.consts);
+                        self.ui.focused = ui::build(
+                            ui_frame,
+                            &mut self.scene.consts,
+                            &mut self.scene.cascades,
+                            &mut self.scene.clock,
+                            &mut self.ui.state,
+                        );
                         self.ui.render(
                             &self.device,
                             &self.queue,
@@ -221,11 +246,77 @@ impl<'a> Engine<'a> {
                         );
 
                         // updating some rendering logic
-                        self.scene.consts_changed = consts_copy != self.scene.consts;
-                        first_frame = false;
+                        self.scene.consts_changed = consts_copy != self.scene.consts
+                            || cascades_copy != self.scene.cascades;
+                        self.simulation.fft.algorithm = self.ui.state.fft_algorithm;
+                        if self.ui.state.load_spectrum {
+                            self.load_spectrum();
+                        }
+                        if self.ui.state.load_heightmap {
+                            self.load_heightmap();
+                        }
+                        if self.ui.state.clear_heightmap {
+                            self.simulation.set_heightmap(&self.device, &self.queue, None);
+                            // the heightmap spectrum would read the cleared texture and flatten
+                            // the ocean, so it goes back to the default model
+                            if self.scene.consts.sim.spectrum == 6 {
+                                self.scene.consts.sim.spectrum = SimConstants::default().spectrum;
+                            }
+                            self.scene.consts_changed = true;
+                        }
+                        if self.ui.state.load_bathymetry {
+                            self.load_bathymetry();
+                        }
+                        if self.ui.state.clear_bathymetry {
+                            self.simulation.bathymetry.clear(&self.queue);
+                            self.scene.consts.sim.bathymetry = 0;
+                        }
+                        if let Some(shape) = self.ui.state.spawn_body {
+                            self.spawn_body(shape);
+                        }
+                        if self.ui.state.clear_bodies {
+                            self.simulation.bodies.bodies.clear();
+                        }
+                        if let Some(point) = self.scene.clicked.take() {
+                            let state = &self.ui.state;
+                            self.simulation.ripples.impact(
+                                Vec2::new(point.x, point.z),
+                                state.impact_radius,
+                                state.impact_depth,
+                                self.scene.consts.sim.impact_foam,
+                                self.scene.consts.time,
+                            );
+                        }
+                        if self.ui.state.clear_ripples {
+                            self.simulation.ripples.clear();
+                        }
+                        self.simulation.bodies.vessel_speed = self.ui.state.vessel_speed;
+                        self.simulation.bodies.vessel_turn_rate = self.ui.state.vessel_turn_rate;
+                        if self.ui.state.cpu_backend != self.simulation.cpu.is_some() {
+                            self.simulation.cpu = self
+                                .ui
+                                .state
+                                .cpu_backend
+                                .then(|| CpuSimulation::new(&self.scene.cascades));
+                            // cpu backend needs its own initial spectrum
+                            self.scene.consts_changed = true;
+                        }
 
                         // Submitting queue to be computed
                         self.queue.submit([encoder.finish()]);
+                        if self.ui.state.compare_backends {
+                            self.compare_backends();
+                        }
+                        let probe = self.ui.state.probe.then(|| Vec2::from(self.ui.state.probe_position));
+                        self.simulation.step_bodies(&self.device, &self.queue, &self.scene, probe);
+                        self.ui.state.probe_sample = self.simulation.probe_sample;
+                        if self.ui.state.detect_breaking {
+                            self.simulation.detect_breaking(&self.device, &self.queue, &self.scene);
+                            self.ui.state.breaking_events.clone_from(&self.simulation.breaking.events);
+                        }
+                        if self.ui.state.measure_sea_state || self.ui.state.export_sea_state {
+                            self.measure_sea_state();
+                        }
                         surface.present();
                     }
                     WindowEvent::Resized(size) => {
@@ -257,4 +348,103 @@ impl<'a> Engine<'a> {
         })?;
         Ok(())
     }
+
+    // size has to be a power of 2 from 64 to 1024, the same sizes the ui offers
+    pub fn set_resolution(&mut self, size: u32) {
+        if !size.is_power_of_two() || !(64..=1024).contains(&size) {
+            log::error!("unsupported simulation resolution {}", size);
+            return;
+        }
+        self.scene.set_resolution(size);
+        self.scene.write(&self.queue);
+        self.simulation.rebuild_cascades(&self.device, &self.queue, &self.scene);
+    }
+
+    // only meaningful on the gpu backend, otherwise the maps being read back are the cpu uploads
+    fn compare_backends(&self) {
+        if self.simulation.cpu.is_some() {
+            log::warn!("switch to the gpu backend to compare against the cpu");
+            return;
+        }
+        let diffs = match self.simulation.compare(&self.device, &self.queue, &self.scene) {
+            Ok(diffs) => diffs,
+            Err(e) => {
+                log::error!("failed to read back the gpu maps: {}", e);
+                return;
+            }
+        };
+        for (i, (displacement, normal)) in diffs.iter().enumerate() {
+            log::info!(
+                "cascade {}: max displacement error {:e}, max normal error {:e}",
+                i, displacement, normal
+            );
+        }
+    }
+
+    // exporting measures first so the file is always of the current frame
+    fn measure_sea_state(&mut self) {
+        let report = match self.simulation.sea_state(&self.device, &self.queue, &self.scene) {
+            Ok(report) => report,
+            Err(e) => {
+                log::error!("failed to read back the sea state: {}", e);
+                return;
+            }
+        };
+        self.ui.state.sea_state = Some(report);
+        if self.ui.state.export_sea_state {
+            let path = &self.ui.state.sea_state_path;
+            if let Err(e) = report.export(path) {
+                log::error!("failed to export sea state {}: {}", path, e);
+            }
+        }
+    }
+
+    fn load_spectrum(&mut self) {
+        let state = &self.ui.state;
+        match MeasuredSpectrum::load(&state.spectrum_path, state.spectrum_record as usize) {
+            Ok(spectrum) => {
+                self.simulation.simdata.write_measured(&self.queue, &spectrum);
+                let (min, max) = spectrum.frequency_range();
+                self.scene.consts.sim.measured_freq_min = min;
+                self.scene.consts.sim.measured_freq_max = max;
+                self.scene.consts.sim.spectrum = 5;
+                // forced as reloading a file with the same range leaves the constants unchanged
+                self.scene.consts_changed = true;
+            }
+            Err(e) => log::error!("failed to load spectrum {}: {}", state.spectrum_path, e),
+        }
+    }
+
+    fn load_heightmap(&mut self) {
+        let path = &self.ui.state.heightmap_path;
+        match Heightmap::load(path) {
+            Ok(heightmap) => {
+                self.simulation.set_heightmap(&self.device, &self.queue, Some(heightmap));
+                self.scene.consts.sim.spectrum = 6;
+                // the spectrum texture changed even if the constants didnt
+                self.scene.consts_changed = true;
+            }
+            Err(e) => log::error!("failed to load heightmap {}: {}", path, e),
+        }
+    }
+
+    fn load_bathymetry(&mut self) {
+        let path = &self.ui.state.bathymetry_path;
+        match self.simulation.bathymetry.load(&self.queue, path) {
+            Ok(()) => self.scene.consts.sim.bathymetry = 1,
+            Err(e) => log::error!("failed to load bathymetry {}: {}", path, e),
+        }
+    }
+
+    // dropped from just above the still water level, new bodies are placed in rows so they dont
+    // land on top of each other
+    fn spawn_body(&mut self, shape: Shape) {
+        let count = self.simulation.bodies.bodies.len() as f32;
+        let position = Vec3::new(
+            8.0 * (count % 4.0) - 12.0,
+            1.0 - self.scene.consts.sim.height_offset,
+            8.0 * (count / 4.0).floor() - 12.0,
+        );
+        self.simulation.bodies.spawn(shape, position);
+    }
 }
@@ -2,6 +2,8 @@ use super::util::Texture;
 use crate::{DEPTH_FORMAT, FORMAT};
 use super::scene::{Mesh, Scene};
 use super::Simulation;
+use crate::sim::bodies::{Bodies, BodyVertex};
+use shared::MAX_PARTICLES;
 
 pub struct Renderer {
     pub sampler_bind_group: wgpu::BindGroup,
@@ -10,6 +12,8 @@ pub struct Renderer {
     pub std_pipeline: wgpu::RenderPipeline,
     pub hdri: Texture,
     pub skybox_pipeline: wgpu::RenderPipeline,
+    pub body_pipeline: wgpu::RenderPipeline,
+    pub spray_pipeline: wgpu::RenderPipeline,
 }
 
 impl Renderer {
@@ -101,9 +105,9 @@ impl Renderer {
                 &scene.consts_layout,
                 &sampler_layout,
                 &hdri.layout,
-                &sim.cascade0.layout,
-                &sim.cascade1.layout,
-                &sim.cascade2.layout,
+                &sim.maps.layout,
+                &sim.wakes.layout,
+                &sim.bathymetry.texture.layout,
             ],
             push_constant_ranges: &[],
             label: None,
@@ -144,6 +148,103 @@ impl Renderer {
             cache: None,
         });
 
+        let body_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[
+                &scene.consts_layout,
+                &sampler_layout,
+                &hdri.layout,
+                &sim.bodies.layout,
+            ],
+            push_constant_ranges: &[],
+            label: None,
+        });
+        let body_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            layout: Some(&body_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("bodies::body_vs"),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<BodyVertex>() as _,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x4],
+                }],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some("bodies::body_fs"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: FORMAT,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            // the generated meshes dont keep a consistent winding
+            primitive: wgpu::PrimitiveState {
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            label: None,
+            cache: None,
+        });
+
+        let spray_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[
+                &scene.consts_layout,
+                &sampler_layout,
+                &hdri.layout,
+                &sim.spray.layout,
+            ],
+            push_constant_ranges: &[],
+            label: None,
+        });
+        let spray_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            layout: Some(&spray_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("spray::spray_vs"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some("spray::spray_fs"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: FORMAT,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: None,
+                ..Default::default()
+            },
+            // tested against the ocean and bodies but not written, so droplets dont cut into
+            // each other
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            label: None,
+            cache: None,
+        });
+
         Self {
             sampler_layout,
             sampler_bind_group,
@@ -151,6 +252,8 @@ impl Renderer {
             std_pipeline,
             hdri,
             skybox_pipeline,
+            body_pipeline,
+            spray_pipeline,
         }
     }
 
@@ -246,4 +349,78 @@ impl Renderer {
         // Draw multiple mesh instances for tiling
         pass.draw_indexed(0..(mesh.length as _), 0, 0..(instances * instances));
     }
+
+    // drawn over the ocean, keeping its depth so the water hides the submerged parts
+    pub fn render_bodies<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        bind_groups: &[&wgpu::BindGroup],
+        surface_view: &wgpu::TextureView,
+        bodies: &Bodies,
+    ) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: surface_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            label: None,
+        });
+        pass.set_pipeline(&self.body_pipeline);
+        for (i, bind_group) in bind_groups.iter().enumerate() {
+            pass.set_bind_group(i as _, *bind_group, &[]);
+        }
+        pass.set_vertex_buffer(0, bodies.vtx_buf.slice(..));
+        for (vertices, instance) in bodies.draws() {
+            pass.draw(vertices, instance..instance + 1);
+        }
+    }
+
+    // drawn last as its blended over everything else
+    pub fn render_spray<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        bind_groups: &[&wgpu::BindGroup],
+        surface_view: &wgpu::TextureView,
+    ) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: surface_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            label: None,
+        });
+        pass.set_pipeline(&self.spray_pipeline);
+        for (i, bind_group) in bind_groups.iter().enumerate() {
+            pass.set_bind_group(i as _, *bind_group, &[]);
+        }
+        pass.draw(0..6, 0..MAX_PARTICLES);
+    }
 }
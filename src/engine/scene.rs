@@ -1,14 +1,17 @@
 use crate::cast_slice;
-use glam::{Mat4, Vec3, Vec4};
-use shared::{Constants, ShaderConstants, SimConstants};
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use shared::{CascadeParams, Constants, ShaderConstants, SimConstants, DEFAULT_CASCADES, MAX_CASCADES};
 use std::{f32::consts::PI, mem, time::Instant};
 use wgpu::{util::DeviceExt, Buffer};
-use winit::event::WindowEvent;
+use winit::event::{MouseButton, WindowEvent};
 use winit::{dpi::PhysicalPosition, event::MouseScrollDelta, window::Window};
 
 pub struct Scene {
-    start_time: Instant,
     cursor_down: bool,
+    cursor: PhysicalPosition<f64>,
+    // where the water was right clicked, taken by the engine to drop an impact
+    pub clicked: Option<Vec3>,
+    pub clock: SimClock,
     pub camera: Camera,
     pub mesh: Mesh,
     pub consts: Constants,
@@ -16,6 +19,8 @@ pub struct Scene {
     pub consts_buf: wgpu::Buffer,
     pub consts_bind_group: wgpu::BindGroup,
     pub consts_changed: bool,
+    pub cascades: Vec<CascadeParams>,
+    pub cascades_buf: wgpu::Buffer,
 }
 
 pub struct Camera {
@@ -47,9 +52,9 @@ pub struct Vertex {
 }
 
 impl Scene {
-    pub fn new(device: &wgpu::Device, window: &Window) -> Self {
+    pub fn new(device: &wgpu::Device, aspect: f32) -> Self {
         let cursor_down = false;
-        let camera = Camera::new(window);
+        let camera = Camera::new(aspect);
         let consts = Constants {
             time: 0.0,
             deltatime: 0.0,
@@ -61,19 +66,31 @@ impl Scene {
             sim: SimConstants::default(),
         };
         let mesh = Mesh::new(device, &consts);
-        let start_time = Instant::now();
+        let clock = SimClock::default();
 
         let consts_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
-                count: None,
-            }],
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
             label: None,
         });
         let mem_size = (mem::size_of::<Constants>()
@@ -85,12 +102,27 @@ impl Scene {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             label: Some("Consts Buffer"),
         });
+        // sized for the maximum so the bind group survives cascades being added or removed,
+        // shaders only read the first cascade_count entries
+        let cascades = DEFAULT_CASCADES.to_vec();
+        let cascades_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            size: (MAX_CASCADES * mem::size_of::<CascadeParams>()) as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            label: Some("Cascades Buffer"),
+        });
         let consts_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &consts_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: consts_buf.as_entire_binding(),
-            }],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: consts_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: cascades_buf.as_entire_binding(),
+                },
+            ],
             label: Some("Consts Bind Group"),
         });
 
@@ -99,7 +131,9 @@ impl Scene {
 
         Self {
             cursor_down,
-            start_time,
+            cursor: PhysicalPosition::default(),
+            clicked: None,
+            clock,
             consts,
             camera,
             mesh,
@@ -107,14 +141,15 @@ impl Scene {
             consts_buf,
             consts_bind_group,
             consts_changed,
+            cascades,
+            cascades_buf,
         }
     }
 
     pub fn update_redraw(&mut self, window: &Window) {
         // Update the constants for use in shaders
-        let duration = self.start_time.elapsed().as_secs_f32();
-        self.consts.deltatime = duration - self.consts.time;
-        self.consts.time = duration;
+        self.consts.deltatime = self.clock.tick();
+        self.consts.time = self.clock.time as f32;
 
         self.consts.eye = self.camera.eye.extend(1.0);
         self.consts.shader.proj_mat = self.camera.proj;
@@ -128,6 +163,7 @@ impl Scene {
         self.consts.shader.light =
             Mat4::from_rotation_y(self.consts.shader.sun_angle) * self.consts.shader.light;
         self.consts.sim.logsize = self.consts.sim.size.ilog2();
+        self.consts.sim.cascade_count = self.cascades.len() as u32;
 
         // update incase resized
         let dimensions = window.inner_size();
@@ -135,20 +171,37 @@ impl Scene {
         self.consts.height = dimensions.height as f32;
     }
 
+    // keeps the world size of a tile the same, so only the detail changes. cascades that were at
+    // the old resolution follow it, any the user sized individually are left alone
+    pub fn set_resolution(&mut self, size: u32) {
+        let previous = self.consts.sim.size;
+        self.consts.sim.mesh_step *= previous as f32 / size as f32;
+        self.consts.sim.size = size;
+        self.consts.sim.logsize = size.ilog2();
+        for cascade in self.cascades.iter_mut().filter(|c| c.size == previous) {
+            cascade.size = size;
+        }
+        self.consts_changed = true;
+    }
+
     pub fn write(&self, queue: &wgpu::Queue) {
         queue.write_buffer(&self.consts_buf, 0, cast_slice(&[self.consts]));
+        queue.write_buffer(&self.cascades_buf, 0, cast_slice(&self.cascades));
     }
 
     pub fn update_camera(&mut self, event: &WindowEvent, window: &Window) {
         match event {
-            WindowEvent::MouseInput { state, button, .. } => if button == &winit::event::MouseButton::Left {
-                self.cursor_down = state.is_pressed()
+            WindowEvent::MouseInput { state, button, .. } => match button {
+                MouseButton::Left => self.cursor_down = state.is_pressed(),
+                MouseButton::Right if state.is_pressed() => self.clicked = self.cursor_on_water(window),
+                _ => {}
             },
             WindowEvent::MouseWheel { delta, .. } => {
                 self.camera.zoom(*delta);
                 self.consts.camera_viewproj = self.camera.proj * self.camera.view;
             }
             WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = *position;
                 if self.cursor_down {
                     self.camera.pan(*position, window);
                     self.consts.camera_viewproj = self.camera.proj * self.camera.view;
@@ -157,6 +210,90 @@ impl Scene {
             _ => {}
         }
     }
+
+    // where the ray under the cursor meets the still water level, the waves themselves are ignored
+    fn cursor_on_water(&self, window: &Window) -> Option<Vec3> {
+        let size = window.inner_size();
+        let ndc = Vec2::new(
+            2.0 * self.cursor.x as f32 / size.width as f32 - 1.0,
+            1.0 - 2.0 * self.cursor.y as f32 / size.height as f32,
+        );
+        let inverse = (self.camera.proj * self.camera.view).inverse();
+        let near = inverse.project_point3(ndc.extend(0.0));
+        let far = inverse.project_point3(ndc.extend(1.0));
+        let t = (-self.consts.sim.height_offset - near.y) / (far.y - near.y);
+        (0.0..=1.0).contains(&t).then(|| near.lerp(far, t))
+    }
+}
+
+// simulation time, decoupled from the wall clock so the ocean can be paused, slowed, run
+// backwards with a negative speed or scrubbed. in fixed step mode time is frame * step, so a
+// given frame always shows the same surface regardless of framerate
+pub struct SimClock {
+    last_tick: Instant,
+    // f64 so long captures dont lose precision, shaders get it truncated to f32
+    pub time: f64,
+    pub paused: bool,
+    pub speed: f32,
+    pub fixed_step: bool,
+    // seconds per frame in fixed step mode, also the size of a manual step in realtime mode
+    pub step: f32,
+    pub frame: u64,
+    // manual steps requested by the ui since the last tick, negative steps go backwards
+    pub steps: i64,
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        Self {
+            last_tick: Instant::now(),
+            time: 0.0,
+            paused: false,
+            speed: 1.0,
+            fixed_step: false,
+            step: 1.0 / 60.0,
+            frame: 0,
+            steps: 0,
+        }
+    }
+}
+
+impl SimClock {
+    // advances once per redraw, returning how far the simulation moved. a paused clock only moves
+    // by manual steps, and seeking between ticks doesnt count as movement
+    pub fn tick(&mut self) -> f32 {
+        let elapsed = self.last_tick.elapsed().as_secs_f64();
+        self.last_tick = Instant::now();
+        let previous = self.time;
+        let steps = mem::take(&mut self.steps);
+
+        if self.fixed_step {
+            let frames = if self.paused { steps } else { steps + 1 };
+            self.frame = self.frame.saturating_add_signed(frames);
+            self.time = self.frame as f64 * self.step as f64;
+        } else {
+            self.time += steps as f64 * self.step as f64;
+            if !self.paused {
+                self.time += elapsed * self.speed as f64;
+            }
+        }
+        (self.time - previous) as f32
+    }
+
+    // jumps straight to a time, snapping to the nearest frame in fixed step mode
+    pub fn seek(&mut self, time: f64) {
+        if self.fixed_step {
+            self.frame = (time / self.step as f64).round().max(0.0) as u64;
+            self.time = self.frame as f64 * self.step as f64;
+        } else {
+            self.time = time;
+        }
+    }
+
+    pub fn set_fixed_step(&mut self, fixed_step: bool) {
+        self.fixed_step = fixed_step;
+        self.seek(self.time);
+    }
 }
 
 impl Mesh {
@@ -207,7 +344,7 @@ impl Mesh {
 }
 
 impl Camera {
-    pub fn new(window: &Window) -> Camera {
+    pub fn new(aspect: f32) -> Camera {
         let pitch: f32 = -PI / 2.0;
         let yaw: f32 = PI / 12.0;
         let zoom: f32 = 50.0;
@@ -224,7 +361,7 @@ impl Camera {
             target: Vec3::new(0.0, 0.0, 0.0),
             // defined y axis as up
             up: Vec3::new(0.0, 1.0, 0.0),
-            aspect: window.inner_size().width as f32 / window.inner_size().height as f32,
+            aspect,
             fovy: PI / 4.0,
             znear: 0.1,
             // set high enough to not be an issue
@@ -275,3 +412,67 @@ impl Camera {
         self.proj = Mat4::perspective_rh(self.fovy, self.aspect, self.znear, self.zfar);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_step_time_is_frame_times_step() {
+        let mut clock = SimClock {
+            fixed_step: true,
+            step: 1.0 / 30.0,
+            ..Default::default()
+        };
+        for frame in 1..=1000u64 {
+            let deltatime = clock.tick();
+            assert_eq!(clock.frame, frame);
+            assert_eq!(clock.time, frame as f64 * clock.step as f64);
+            assert!((deltatime - clock.step).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn paused_only_moves_by_steps() {
+        for fixed_step in [false, true] {
+            let mut clock = SimClock {
+                paused: true,
+                fixed_step,
+                ..Default::default()
+            };
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            assert_eq!(clock.tick(), 0.0);
+            assert_eq!(clock.time, 0.0);
+
+            clock.steps = 3;
+            let deltatime = clock.tick();
+            assert!((deltatime - 3.0 * clock.step).abs() < 1e-6);
+            clock.steps = -1;
+            let deltatime = clock.tick();
+            assert!((deltatime + clock.step).abs() < 1e-6);
+            assert!((clock.time - 2.0 * clock.step as f64).abs() < 1e-9);
+        }
+    }
+
+    // a jump is not movement, so foam and bodies dont integrate over it
+    #[test]
+    fn seek_is_not_a_deltatime() {
+        let mut clock = SimClock {
+            paused: true,
+            ..Default::default()
+        };
+        clock.seek(100.0);
+        assert_eq!(clock.tick(), 0.0);
+        assert_eq!(clock.time, 100.0);
+
+        let mut clock = SimClock {
+            fixed_step: true,
+            ..Default::default()
+        };
+        clock.seek(-50.0);
+        clock.tick();
+        clock.seek(10.0);
+        assert!((clock.tick() - clock.step).abs() < 1e-6);
+        assert!((clock.time - 10.0 - clock.step as f64).abs() < 1e-4);
+    }
+}
@@ -1,6 +1,7 @@
-use {crate::{cast_slice, FORMAT}, super::renderer::Renderer, super::scene::Scene, super::util::Texture};
+use {crate::{cast_slice, FORMAT}, super::renderer::Renderer, super::scene::{Scene, SimClock}, super::util::Texture};
+use crate::sim::{bodies::Shape, breaking::BreakingEvent, fft::FftAlgorithm, statistics::SeaStateReport};
 use imgui::{BackendFlags, DrawVert, FontSource, Key, MouseCursor, TreeNodeFlags, Ui};
-use shared::Constants;
+use shared::{CascadeParams, Constants, SurfaceSample, SwellSystem, MAX_CASCADES, MAX_SWELLS};
 use std::{f32::consts::PI, mem};
 use wgpu::{util::DeviceExt, Buffer, Device, Queue, RenderPipeline};
 use winit::{
@@ -15,9 +16,49 @@ pub struct UI {
     pub idx_buf: Buffer,
     pub context: imgui::Context,
     pub focused: bool,
+    pub state: UIState,
     texture: Texture,
 }
 
+// anything edited through the ui that isnt a shader constant, the engine acts on it after the ui
+// has been built each frame
+#[derive(Default)]
+pub struct UIState {
+    pub spectrum_path: String,
+    pub spectrum_record: i32,
+    pub load_spectrum: bool,
+    pub heightmap_path: String,
+    pub load_heightmap: bool,
+    pub clear_heightmap: bool,
+    pub bathymetry_path: String,
+    pub load_bathymetry: bool,
+    pub clear_bathymetry: bool,
+    pub resolution: Option<u32>,
+    pub cpu_backend: bool,
+    pub compare_backends: bool,
+    pub probe: bool,
+    pub probe_position: [f32; 2],
+    // filled in by the engine after the frame is submitted
+    pub probe_sample: Option<SurfaceSample>,
+    pub spawn_body: Option<Shape>,
+    pub clear_bodies: bool,
+    pub vessel_speed: f32,
+    pub vessel_turn_rate: f32,
+    pub impact_radius: f32,
+    pub impact_depth: f32,
+    pub clear_ripples: bool,
+    pub detect_breaking: bool,
+    pub clear_spray: bool,
+    // filled in by the engine after the frame is submitted
+    pub breaking_events: Vec<BreakingEvent>,
+    pub measure_sea_state: bool,
+    pub export_sea_state: bool,
+    pub sea_state_path: String,
+    // filled in by the engine after the frame is submitted
+    pub sea_state: Option<SeaStateReport>,
+    pub fft_algorithm: FftAlgorithm,
+}
+
 impl UI {
     pub fn new(
         device: &Device,
@@ -112,6 +153,12 @@ impl UI {
         });
 
         let focused = true;
+        // a stone sized splash rather than nothing until the sliders are touched
+        let state = UIState {
+            impact_radius: 1.0,
+            impact_depth: 0.5,
+            ..Default::default()
+        };
 
         Self {
             pipeline,
@@ -120,6 +167,7 @@ impl UI {
             context,
             texture,
             focused,
+            state,
         }
     }
 
@@ -280,58 +328,263 @@ impl UI {
     }
 }
 
-pub fn build(ui: &Ui, consts: &mut Constants) -> bool {
+// order has to match sim::initial_spectra::frequency_spectrum in the shader
+const SPECTRUM_MODELS: [&str; 7] = [
+    "TMA",
+    "JONSWAP",
+    "Pierson-Moskowitz",
+    "Phillips",
+    "Elfouhaily",
+    "Measured",
+    "Heightmap",
+];
+// order has to match sim::initial_spectra::final_spread in the shader
+const SPREADING_FUNCTIONS: [&str; 5] = [
+    "Donelan-Banner",
+    "Mitsuyasu-Hasselmann (cos-2s)",
+    "Longuet-Higgins (cos^2s)",
+    "Positive Cosine Squared",
+    "Unidirectional",
+];
+
+const FFT_ALGORITHMS: [&str; 3] = ["Radix-2 Cooley-Tukey", "Radix-4 Stockham", "Shared Memory"];
+
+// resolutions selectable in the ui, index i is 64 << i
+const RESOLUTIONS: [&str; 5] = ["64x64", "128x128", "256x256", "512x512", "1024x1024"];
+
+pub fn build(
+    ui: &Ui,
+    consts: &mut Constants,
+    cascades: &mut Vec<CascadeParams>,
+    clock: &mut SimClock,
+    state: &mut UIState,
+) -> bool {
     let mut focused = false;
+    let cascades_len = cascades.len();
+    let current_resolution = (consts.sim.size / 64).ilog2() as usize;
+    let mut resolution = current_resolution;
     let mut pbr_bool = consts.shader.pbr != 0;
+    let mut spectral_motion = consts.sim.spectral_motion != 0;
+    let mut spectrum = consts.sim.spectrum as usize;
+    let mut spreading = consts.sim.spreading as usize;
+    let mut fft_algorithm = state.fft_algorithm as usize;
     ui.window("NEA Ocean Simulation")
         .always_auto_resize(true)
         .build(|| {
             ui.text("Parameters marked with (*) generally should not be changed");
             ui.text("Info:");
-            ui.text(format!("{:.1$} Elapsed", consts.time, 2));
             ui.text(format!(
                 "A {}x{} simulation, running at {} fps",
                 consts.sim.size,
                 consts.sim.size,
                 ui.io().framerate
             ));
+            if ui.collapsing_header("Time", TreeNodeFlags::DEFAULT_OPEN) {
+                let mut time = clock.time as f32;
+                if ui.input_float("Time (s)", &mut time).build() {
+                    clock.seek(time as f64);
+                }
+                if ui.button(if clock.paused { "Resume" } else { "Pause" }) {
+                    clock.paused = !clock.paused;
+                }
+                ui.same_line();
+                if ui.button("Step Back") {
+                    clock.steps -= 1;
+                }
+                ui.same_line();
+                if ui.button("Step") {
+                    clock.steps += 1;
+                }
+                ui.slider("Speed", -4.0, 4.0, &mut clock.speed);
+                let mut fixed_step = clock.fixed_step;
+                if ui.checkbox("Fixed Timestep", &mut fixed_step) {
+                    clock.set_fixed_step(fixed_step);
+                }
+                ui.input_float("Step (s)", &mut clock.step).build();
+                clock.step = clock.step.max(0.0001);
+                if clock.fixed_step {
+                    let mut frame = clock.frame as i32;
+                    if ui.input_int("Frame", &mut frame).build() {
+                        clock.seek(frame.max(0) as f64 * clock.step as f64);
+                    }
+                }
+            }
+            ui.separator();
             if ui.collapsing_header("Simulation Parameters", TreeNodeFlags::DEFAULT_OPEN) {
+                ui.combo_simple_string("Resolution", &mut resolution, &RESOLUTIONS);
                 ui.text("Waves");
+                ui.combo_simple_string("Spectrum", &mut spectrum, &SPECTRUM_MODELS);
                 ui.slider("Depth", 1.0, 50.0, &mut consts.sim.depth);
                 ui.slider("Gravity", 0.1, 100.0, &mut consts.sim.gravity);
                 ui.slider("Wind Speed", 0.1, 100.0, &mut consts.sim.wind_speed);
                 ui.slider("Wind Offset", -PI, PI, &mut consts.sim.wind_offset);
                 ui.slider("Fetch", 1000.0, 10000.0, &mut consts.sim.fetch);
                 ui.slider("Choppiness", 0.0, 1.0, &mut consts.sim.choppiness);
+                ui.combo_simple_string("Spreading", &mut spreading, &SPREADING_FUNCTIONS);
                 ui.slider("Swell", 0.001, 1.0, &mut consts.sim.swell);
+                ui.slider("Spread Exponent", 0.5, 30.0, &mut consts.sim.spread_exponent);
 
-                ui.text("Lengthscales");
-                ui.slider("Lengthscale 0", 1, consts.sim.size, &mut consts.sim.lengthscale0);
-                ui.slider("Cutoff Low 0*", 0.00000, 0.00001, &mut consts.sim.cutoff_low0);
-                ui.slider("Cutoff High 0", 0.01, 15.0, &mut consts.sim.cutoff_high0);
-                ui.slider("Lengthscale 0 Scale Factor", 0.0, 1.0, &mut consts.sim.lengthscale0_sf);
+                ui.text("Measured Spectrum");
+                ui.input_text("Path (csv, json or ndbc station)", &mut state.spectrum_path).build();
+                ui.input_int("Record", &mut state.spectrum_record).build();
+                state.spectrum_record = state.spectrum_record.max(0);
+                state.load_spectrum = ui.button("Load Spectrum");
 
-                ui.slider("Lengthscale 1", 1, consts.sim.size, &mut consts.sim.lengthscale1);
-                ui.slider("Cutoff Low 1", 0.00000, 15.0, &mut consts.sim.cutoff_low1);
-                ui.slider("Cutoff High 1", 0.01, 15.0, &mut consts.sim.cutoff_high1);
-                ui.slider("Lengthscale 1 Scale Factor", 0.0, 1.0, &mut consts.sim.lengthscale1_sf);
+                ui.text("Heightmap");
+                ui.input_text("Path (grayscale image or exr)", &mut state.heightmap_path).build();
+                ui.slider("Heightmap Amplitude", 0.0, 10.0, &mut consts.sim.heightmap_amplitude);
+                state.load_heightmap = ui.button("Load Heightmap");
+                ui.same_line();
+                state.clear_heightmap = ui.button("Clear Heightmap");
 
-                ui.slider("Lengthscale 2", 1, consts.sim.size, &mut consts.sim.lengthscale2);
-                ui.slider("Cutoff Low 2", 0.00000, 15.0, &mut consts.sim.cutoff_low2);
-                ui.slider("Cutoff High 2", 0.0, 15.0, &mut consts.sim.cutoff_high2);
-                ui.slider("Lengthscale 2 Scale Factor", 0.0, 1.0, &mut consts.sim.lengthscale2_sf);
+                // seabed heights are value * scale + offset, so the defaults put 0 at 45m deep
+                // and 1 at 5m above the still water level
+                ui.text("Bathymetry");
+                ui.input_text("Seabed Path (grayscale image or exr)", &mut state.bathymetry_path).build();
+                ui.slider("Seabed Origin X", -5000.0, 5000.0, &mut consts.sim.bathymetry_x);
+                ui.slider("Seabed Origin Z", -5000.0, 5000.0, &mut consts.sim.bathymetry_z);
+                ui.slider("Seabed Width", 10.0, 10000.0, &mut consts.sim.bathymetry_width);
+                ui.slider("Seabed Length", 10.0, 10000.0, &mut consts.sim.bathymetry_length);
+                ui.slider("Seabed Scale", 0.0, 200.0, &mut consts.sim.bathymetry_scale);
+                ui.slider("Seabed Offset", -200.0, 50.0, &mut consts.sim.bathymetry_offset);
+                ui.slider("Bottom Friction (m)", 0.0, 10.0, &mut consts.sim.bottom_friction);
+                state.load_bathymetry = ui.button("Load Bathymetry");
+                ui.same_line();
+                state.clear_bathymetry = ui.button("Clear Bathymetry");
+
+                ui.text("Swell Systems");
+                let mut removed = None;
+                for i in 0..consts.sim.swell_count as usize {
+                    let _id = ui.push_id_usize(i);
+                    let swell = &mut consts.sim.swells[i];
+                    ui.text(format!("Swell {}", i));
+                    ui.slider("Direction", -PI, PI, &mut swell.direction);
+                    ui.slider("Peak Period", 1.0, 25.0, &mut swell.peak_period);
+                    ui.slider("Significant Height", 0.0, 10.0, &mut swell.significant_height);
+                    ui.slider("Spread", 1.0, 100.0, &mut swell.spread);
+                    ui.slider("Peakedness", 1.0, 10.0, &mut swell.peakedness);
+                    if ui.button("Remove") {
+                        removed = Some(i);
+                    }
+                }
+                if let Some(i) = removed {
+                    consts.sim.swells.copy_within(i + 1.., i);
+                    consts.sim.swell_count -= 1;
+                }
+                if (consts.sim.swell_count as usize) < MAX_SWELLS && ui.button("Add Swell") {
+                    consts.sim.swells[consts.sim.swell_count as usize] = SwellSystem::default();
+                    consts.sim.swell_count += 1;
+                }
+
+
+                ui.text("Cascades");
+                let mut removed = None;
+                for (i, cascade) in cascades.iter_mut().enumerate() {
+                    let _id = ui.push_id_usize(i);
+                    ui.text(format!("Cascade {}", i));
+                    let mut size = (cascade.size / 64).ilog2() as usize;
+                    ui.combo_simple_string("Size", &mut size, &RESOLUTIONS);
+                    cascade.size = 64 << size;
+                    ui.slider("Lengthscale", 1.0, 1000.0, &mut cascade.lengthscale);
+                    ui.slider("Cutoff Low", 0.0, 15.0, &mut cascade.cutoff_low);
+                    ui.slider("Cutoff High", 0.0, 15.0, &mut cascade.cutoff_high);
+                    ui.slider("Scale Factor", 0.0, 1.0, &mut cascade.scale_factor);
+                    if cascades_len > 1 && ui.button("Remove") {
+                        removed = Some(i);
+                    }
+                }
+                if let Some(i) = removed {
+                    cascades.remove(i);
+                }
+                if cascades.len() < MAX_CASCADES && ui.button("Add Cascade") {
+                    cascades.push(CascadeParams::default());
+                }
 
                 ui.text("Foam");
                 ui.color_edit4("Foam Color", consts.shader.foam_color.as_mut());
-                ui.slider("Decay", 0.0, 0.3, &mut consts.sim.foam_decay);
+                ui.slider("Decay (per s)", 0.0, 18.0, &mut consts.sim.foam_decay);
                 ui.slider("Bias", 0.00, 2.0, &mut consts.sim.foam_bias);
                 ui.slider("Injection Threshold", -1.00, 1.0, &mut consts.sim.injection_threshold);
-                ui.slider("Injection Amount", 0.00, 2.0, &mut consts.sim.injection_amount);
+                ui.slider("Injection Amount (per s)", 0.00, 120.0, &mut consts.sim.injection_amount);
+                ui.slider("Wind Drift", 0.0, 0.1, &mut consts.sim.foam_drift);
+                ui.slider("Orbital Advection", 0.0, 1.0, &mut consts.sim.foam_orbital);
+                ui.slider("Old Foam Transfer", 0.0, 1.0, &mut consts.sim.old_foam_transfer);
+                ui.slider("Old Foam Decay (per s)", 0.0, 2.0, &mut consts.sim.old_foam_decay);
                 ui.text("Misc");
                 ui.slider("Instances per Axis",1, 10, &mut consts.sim.instances);
                 ui.slider("Instance micro Offset",0.9, 1.0, &mut consts.sim.instance_micro_offset);
                 ui.slider("Mesh Step", 0.0, 1.0, &mut consts.sim.mesh_step);
                 ui.slider("Integration Step*", 0.001, 0.02, &mut consts.sim.integration_step);
+                ui.checkbox("Spectral Velocity and Acceleration", &mut spectral_motion);
+                ui.text("Backend");
+                ui.checkbox("CPU Simulation", &mut state.cpu_backend);
+                state.compare_backends = ui.button("Compare CPU and GPU");
+                ui.combo_simple_string("FFT", &mut fft_algorithm, &FFT_ALGORITHMS);
+                ui.text("Surface Probe");
+                ui.checkbox("Probe Surface", &mut state.probe);
+                ui.input_float2("Probe Position (x, z)", &mut state.probe_position).build();
+                if let (true, Some(sample)) = (state.probe, &state.probe_sample) {
+                    ui.text(format!("Height {:.3} m", sample.position.y));
+                    ui.text(format!("Normal {:.3}", sample.normal.truncate()));
+                    ui.text(format!("Velocity {:.3} m/s", sample.velocity.truncate()));
+                    ui.text(format!("Acceleration {:.3} m/s^2", sample.acceleration.truncate()));
+                }
+                ui.text("Floating Bodies");
+                state.spawn_body = None;
+                for (label, shape) in [("Add Box", Shape::Box), ("Add Barrel", Shape::Barrel), ("Add Hull", Shape::Hull)] {
+                    if ui.button(label) {
+                        state.spawn_body = Some(shape);
+                    }
+                    ui.same_line();
+                }
+                state.clear_bodies = ui.button("Clear Bodies");
+                ui.slider("Hull Speed (m/s)", -2.0, 10.0, &mut state.vessel_speed);
+                ui.slider("Hull Turn Rate (rad/s)", -0.5, 0.5, &mut state.vessel_turn_rate);
+                ui.slider("Wake Amplitude", 0.0, 1.0, &mut consts.sim.wake_amplitude);
+                ui.slider("Wake Foam", 0.0, 4.0, &mut consts.sim.wake_foam);
+                ui.slider("Wake Lifetime (s)", 1.0, 120.0, &mut consts.sim.wake_lifetime);
+                ui.text("Ripples (right click the water)");
+                ui.slider("Impact Radius (m)", 0.1, 5.0, &mut state.impact_radius);
+                ui.slider("Impact Depth (m)", 0.0, 2.0, &mut state.impact_depth);
+                ui.slider("Impact Foam", 0.0, 4.0, &mut consts.sim.impact_foam);
+                state.clear_ripples = ui.button("Clear Ripples");
+                ui.text("Sea Spray");
+                ui.slider("Spray Threshold (foam per s)", 0.0, 120.0, &mut consts.sim.spray_threshold);
+                ui.slider("Spray Rate (per s)", 0.0, 30.0, &mut consts.sim.spray_rate);
+                ui.slider("Spray Speed (m/s)", 0.0, 15.0, &mut consts.sim.spray_speed);
+                ui.slider("Spindrift", 0.0, 1.0, &mut consts.sim.spray_wind);
+                ui.slider("Spray Lifetime (s)", 0.1, 10.0, &mut consts.sim.spray_lifetime);
+                ui.slider("Droplet Size (m)", 0.01, 1.0, &mut consts.sim.spray_size);
+                state.clear_spray = ui.button("Clear Spray");
+                ui.text("Breaking Waves");
+                ui.checkbox("Detect Breaking", &mut state.detect_breaking);
+                ui.slider("Breaking Threshold", -1.0, 1.0, &mut consts.sim.breaking_threshold);
+                if state.detect_breaking {
+                    let area: f32 = state.breaking_events.iter().map(|e| e.area).sum();
+                    ui.text(format!("{} regions, {:.1} m^2 breaking", state.breaking_events.len(), area));
+                    for event in state.breaking_events.iter().take(5) {
+                        ui.text(format!(
+                            "({:.1}, {:.1}) {:.1} m^2, intensity {:.2}",
+                            event.position.x, event.position.y, event.area, event.intensity
+                        ));
+                    }
+                }
+                // the spectrum is what the sliders ask for, the surface is what the cascades
+                // actually hold after cutoffs and the fft grid
+                ui.text("Sea State");
+                state.measure_sea_state = ui.button("Measure Sea State");
+                if let Some(report) = &state.sea_state {
+                    ui.text(format!("at {:.1} s, spectrum / surface", report.time));
+                    let (spectral, realised) = (&report.spectral, &report.realised);
+                    ui.text(format!("Hs {:.2} / {:.2} m", spectral.hs, realised.hs));
+                    ui.text(format!("Tp {:.2} / {:.2} s", spectral.tp, realised.tp));
+                    ui.text(format!("Tm01 {:.2} / {:.2} s", spectral.tm01, realised.tm01));
+                    ui.text(format!("Tz {:.2} / {:.2} s", spectral.tz, realised.tz));
+                    ui.text(format!("Bandwidth {:.2} / {:.2}", spectral.bandwidth, realised.bandwidth));
+                    ui.text(format!("Direction {:.2} / {:.2} rad", spectral.direction, realised.direction));
+                }
+                ui.input_text("Sea State Path (json)", &mut state.sea_state_path).build();
+                state.export_sea_state = ui.button("Export Sea State");
             }
             ui.separator();
             if ui.collapsing_header("Shader Parameters", TreeNodeFlags::DEFAULT_OPEN) {
@@ -382,6 +635,16 @@ pub fn build(ui: &Ui, consts: &mut Constants) -> bool {
             }
             focused = ui.is_window_focused();
             consts.shader.pbr = pbr_bool as u32;
+            consts.sim.spectral_motion = spectral_motion as u32;
+            consts.sim.spectrum = spectrum as u32;
+            consts.sim.spreading = spreading as u32;
+            state.fft_algorithm = match fft_algorithm {
+                0 => FftAlgorithm::CooleyTukey,
+                1 => FftAlgorithm::Stockham,
+                _ => FftAlgorithm::SharedMemory,
+            };
+            // resizing recreates most of the simulation so it is left to the engine
+            state.resolution = (resolution != current_resolution).then(|| 64 << resolution);
         });
     focused
 }
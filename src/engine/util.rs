@@ -1,8 +1,10 @@
+use glam::Vec4;
 use image::io::Reader;
 use image::GenericImageView;
 use std::fs::File;
 use std::io::Cursor;
 use std::io::Read;
+use std::sync::{Arc, Mutex};
 use wgpu::util::DeviceExt;
 use wgpu::Queue;
 
@@ -76,6 +78,7 @@ impl Texture {
             format,
             usage: wgpu::TextureUsages::STORAGE_BINDING
                 | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
                 | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[],
             label: Some(label),
@@ -102,6 +105,67 @@ impl Texture {
         }
     }
 
+    // one layer per cascade, the view covers every layer while layer_view gives a plain 2d view
+    // of a single layer for the compute passes
+    pub fn new_storage_array(
+        width: u32,
+        height: u32,
+        layers: u32,
+        format: wgpu::TextureFormat,
+        device: &wgpu::Device,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+            label: Some(label),
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[array_bind_group_descriptor(0, format)],
+            label: Some(label),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            }],
+            label: Some(label),
+        });
+
+        Self {
+            texture,
+            view,
+            layout,
+            bind_group,
+        }
+    }
+
+    pub fn layer_view(&self, layer: u32) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_array_layer: layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        })
+    }
+
     pub fn write(&self, queue: &Queue, data: &[u8], size: u32) {
         queue.write_texture(
             wgpu::ImageCopyTexture {
@@ -120,6 +184,74 @@ impl Texture {
         );
     }
 
+    // writes a width x width region at the top left of a layer, for cascades smaller than the array
+    pub fn write_layer(&self, queue: &Queue, data: &[u8], size: u32, width: u32, layer: u32) {
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: 0, y: 0, z: layer },
+                aspect: wgpu::TextureAspect::All,
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(size * width),
+                rows_per_image: Some(width),
+            },
+            wgpu::Extent3d {
+                width,
+                height: width,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    // blocking readback of a single layer of an rgba32float texture, only used for debugging and comparing against
+    // the cpu backend so stalling the gpu is fine. rows are padded to 256 bytes by wgpu
+    pub fn read(&self, device: &wgpu::Device, queue: &Queue, layer: u32) -> Result<Vec<Vec4>, wgpu::BufferAsyncError> {
+        let width = self.texture.width();
+        let height = self.texture.height();
+        let unpadded = width * 16;
+        let padded = unpadded.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            size: (padded * height) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+            label: Some("Readback"),
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: 0, y: 0, z: layer },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit([encoder.finish()]);
+
+        let floats = Readback::start(&buffer, (padded * height) as u64).wait(device, &buffer)?;
+        Ok(floats
+            .chunks(padded as usize / 4)
+            .flat_map(|row| row[..unpadded as usize / 4].chunks(4).map(Vec4::from_slice))
+            .collect())
+    }
+
     pub fn from_file(device: &wgpu::Device, queue: &Queue, label: &str, file: &str) -> Self {
         // cant include_bytes! as filepath is non static
         let mut file_data = Vec::new();
@@ -178,6 +310,50 @@ impl Texture {
     }
 }
 
+// the result of map_async, filled in by its callback once the buffer can be read
+type MapState = Arc<Mutex<Option<Result<(), wgpu::BufferAsyncError>>>>;
+
+// the first size bytes of a buffer being mapped for reading, as f32s. map_async only reports
+// failure through its callback, so the result is kept here and checked before the mapped range
+// is touched, which would otherwise panic
+pub struct Readback {
+    size: u64,
+    state: MapState,
+}
+
+impl Readback {
+    // the copy into buffer has to have been submitted already
+    pub fn start(buffer: &wgpu::Buffer, size: u64) -> Self {
+        let state = MapState::default();
+        let callback_state = state.clone();
+        buffer.slice(..size).map_async(wgpu::MapMode::Read, move |result| {
+            *callback_state.lock().unwrap() = Some(result);
+        });
+        Self { size, state }
+    }
+
+    // None until the gpu has finished with the buffer, then its contents, leaving it unmapped
+    pub fn poll(&self, device: &wgpu::Device, buffer: &wgpu::Buffer) -> Option<Result<Vec<f32>, wgpu::BufferAsyncError>> {
+        device.poll(wgpu::Maintain::Poll);
+        let result = self.state.lock().unwrap().take()?;
+        Some(result.map(|()| {
+            let bytes = buffer.slice(..self.size).get_mapped_range();
+            let floats = bytes
+                .chunks(4)
+                .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+                .collect();
+            drop(bytes);
+            buffer.unmap();
+            floats
+        }))
+    }
+
+    // blocks until the map has finished, a callback that never ran counts as failed
+    pub fn wait(self, device: &wgpu::Device, buffer: &wgpu::Buffer) -> Result<Vec<f32>, wgpu::BufferAsyncError> {
+        device.poll(wgpu::Maintain::Wait);
+        self.poll(device, buffer).unwrap_or(Err(wgpu::BufferAsyncError))
+    }
+}
 
 // debatably redundant as not app agnositc, but reduces LOC significantly
 pub fn bind_group_descriptor(
@@ -196,6 +372,22 @@ pub fn bind_group_descriptor(
     }
 }
 
+pub fn array_bind_group_descriptor(
+    binding: u32,
+    format: wgpu::TextureFormat,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::VERTEX_FRAGMENT,
+        ty: wgpu::BindingType::StorageTexture {
+            access: wgpu::StorageTextureAccess::ReadWrite,
+            format,
+            view_dimension: wgpu::TextureViewDimension::D2Array,
+        },
+        count: None,
+    }
+}
+
 pub fn sampled_bind_group_descriptor(binding: u32) -> wgpu::BindGroupLayoutEntry {
     wgpu::BindGroupLayoutEntry {
         binding,
@@ -208,3 +400,21 @@ pub fn sampled_bind_group_descriptor(binding: u32) -> wgpu::BindGroupLayoutEntry
         count: None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::fallback_device;
+
+    // 8 texels of rgba32float is 128 bytes a row, so every row of the copy is padded to 256
+    #[test]
+    fn read_strips_row_padding() {
+        let (device, queue) = fallback_device();
+        let size = 8;
+        let texture = Texture::new_storage_array(size, size, 2, wgpu::TextureFormat::Rgba32Float, &device, "Readback Test");
+        let texels: Vec<Vec4> = (0..size * size).map(|i| Vec4::new(i as f32, 1.0, -2.0, 0.5)).collect();
+        texture.write_layer(&queue, crate::cast_slice(&texels), 16, size, 1);
+
+        assert_eq!(texture.read(&device, &queue, 1).unwrap(), texels);
+    }
+}
@@ -0,0 +1,42 @@
+use crate::engine::util::Texture;
+use crate::{cast_slice, Result};
+use glam::Vec4;
+use image::imageops;
+use shared::BATHYMETRY_SIZE;
+
+// the seabed as a grayscale image, the same way heightmaps are read, so 0..1 for ordinary images
+// and metres for exr files. bathymetry_scale and bathymetry_offset turn values into heights and
+// the bathymetry extents place it in the world, past its edges the border values carry on
+pub struct Bathymetry {
+    pub texture: Texture,
+}
+
+impl Bathymetry {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let texture = Texture::new_storage(
+            BATHYMETRY_SIZE,
+            BATHYMETRY_SIZE,
+            wgpu::TextureFormat::Rgba32Float,
+            device,
+            "Bathymetry",
+        );
+        Self { texture }
+    }
+
+    pub fn load(&self, queue: &wgpu::Queue, path: &str) -> Result {
+        let image = image::open(path)?.to_luma32f();
+        if image.width() == 0 || image.height() == 0 {
+            return Err("bathymetry is empty".into());
+        }
+        let resized = imageops::resize(&image, BATHYMETRY_SIZE, BATHYMETRY_SIZE, imageops::FilterType::Triangle);
+        let data: Vec<Vec4> = resized.pixels().map(|p| Vec4::new(p[0], 0.0, 0.0, 0.0)).collect();
+        self.texture.write(queue, cast_slice(&data), 16);
+        Ok(())
+    }
+
+    // not read while consts.sim.bathymetry is off, zeroed anyway so a stale seabed isnt left behind
+    pub fn clear(&self, queue: &wgpu::Queue) {
+        let data = vec![Vec4::ZERO; (BATHYMETRY_SIZE * BATHYMETRY_SIZE) as usize];
+        self.texture.write(queue, cast_slice(&data), 16);
+    }
+}
@@ -0,0 +1,601 @@
+use super::wakes::Trail;
+use crate::cast_slice;
+use glam::{Mat3, Mat4, Quat, Vec2, Vec3, Vec4};
+use shared::{BodyInstance, SurfaceSample, MAX_BODIES};
+use std::f32::consts::{PI, TAU};
+use std::mem;
+use std::ops::Range;
+use wgpu::util::DeviceExt;
+
+// seawater, kg/m^3
+const WATER_DENSITY: f32 = 1025.0;
+// per second, stands in for the rotational drag the coarse samples miss
+const ANGULAR_DAMPING: f32 = 0.3;
+// edge length of the cells a hull is voxelised into, every axis gets at least MIN_CELLS
+const VOXEL_SIZE: f32 = 0.25;
+const MIN_CELLS: u32 = 4;
+// the surface is only read once per frame, but forces are integrated in substeps of at most
+// this long so stiff buoyancy on light bodies stays stable at low framerates
+const MAX_BODY_STEP: f32 = 1.0 / 120.0;
+const MAX_BODY_SUBSTEPS: u32 = 8;
+// slower than this a body slips into the water without a splash, m/s
+const SPLASH_SPEED: f32 = 1.0;
+// per second, how quickly a propelled hull closes on its set speed and turn rate
+const THRUST_RATE: f32 = 1.0;
+
+// indexes Bodies::shapes, so the order has to match SHAPES
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Shape {
+    Box,
+    Barrel,
+    Hull,
+}
+
+const SHAPES: [Shape; 3] = [Shape::Box, Shape::Barrel, Shape::Hull];
+
+// dimensions of the simple hull, it points along +z with a transom stern and a pointed bow
+const HULL_LENGTH: f32 = 6.0;
+const HULL_BEAM: f32 = 2.0;
+const HULL_DRAUGHT: f32 = 0.8;
+const HULL_FREEBOARD: f32 = 0.5;
+
+impl Shape {
+    // averaged over the enclosed volume, so hollow shapes are light
+    fn density(self) -> f32 {
+        match self {
+            Shape::Box => 500.0,
+            Shape::Barrel => 400.0,
+            Shape::Hull => 250.0,
+        }
+    }
+
+    // along each body axis, the hull is streamlined end on
+    fn drag_coefficients(self) -> Vec3 {
+        match self {
+            Shape::Box => Vec3::ONE,
+            Shape::Barrel => Vec3::new(0.8, 1.0, 1.0),
+            Shape::Hull => Vec3::new(1.0, 1.0, 0.05),
+        }
+    }
+
+    fn color(self) -> Vec4 {
+        match self {
+            Shape::Box => Vec4::new(0.55, 0.35, 0.2, 1.0),
+            Shape::Barrel => Vec4::new(0.7, 0.15, 0.1, 1.0),
+            Shape::Hull => Vec4::new(0.85, 0.85, 0.8, 1.0),
+        }
+    }
+
+    fn bounds(self) -> (Vec3, Vec3) {
+        match self {
+            Shape::Box => (Vec3::new(-1.0, -0.5, -1.0), Vec3::new(1.0, 0.5, 1.0)),
+            // lying on its side along x
+            Shape::Barrel => (Vec3::new(-0.6, -0.4, -0.4), Vec3::new(0.6, 0.4, 0.4)),
+            Shape::Hull => (
+                Vec3::new(-0.5 * HULL_BEAM, -HULL_DRAUGHT, -0.5 * HULL_LENGTH),
+                Vec3::new(0.5 * HULL_BEAM, HULL_FREEBOARD, 0.5 * HULL_LENGTH),
+            ),
+        }
+    }
+
+    // horizontal (length, beam), for wakes and splashes
+    pub fn footprint(self) -> (f32, f32) {
+        let (min, max) = self.bounds();
+        let size = max - min;
+        (size.x.max(size.z), size.x.min(size.z))
+    }
+
+    fn contains(self, p: Vec3) -> bool {
+        let (min, max) = self.bounds();
+        if p.cmplt(min).any() || p.cmpgt(max).any() {
+            return false;
+        }
+        match self {
+            Shape::Box => true,
+            Shape::Barrel => p.y * p.y + p.z * p.z <= max.y * max.y,
+            Shape::Hull => {
+                let b = hull_half_beam(p.z);
+                if p.y >= 0.0 {
+                    p.x.abs() <= b
+                } else {
+                    b > 0.0 && (p.x / b).powi(2) + (p.y / HULL_DRAUGHT).powi(2) <= 1.0
+                }
+            }
+        }
+    }
+
+    fn mesh(self) -> Vec<BodyVertex> {
+        let mut mesh = Mesh::default();
+        let (min, max) = self.bounds();
+        match self {
+            Shape::Box => {
+                let corner = |i: u32| {
+                    Vec3::new(
+                        if i & 1 == 0 { min.x } else { max.x },
+                        if i & 2 == 0 { min.y } else { max.y },
+                        if i & 4 == 0 { min.z } else { max.z },
+                    )
+                };
+                // corners of each face as bits of the corner index
+                let faces = [[0, 2, 6, 4], [1, 3, 7, 5], [0, 1, 5, 4], [2, 3, 7, 6], [0, 1, 3, 2], [4, 5, 7, 6]];
+                for face in faces {
+                    mesh.quad(corner(face[0]), corner(face[1]), corner(face[2]), corner(face[3]), Vec3::ZERO);
+                }
+            }
+            Shape::Barrel => {
+                let segments = 20;
+                let ring = |i: u32, x: f32| {
+                    let angle = i as f32 * TAU / segments as f32;
+                    Vec3::new(x, max.y * angle.cos(), max.y * angle.sin())
+                };
+                for i in 0..segments {
+                    mesh.quad(ring(i, min.x), ring(i + 1, min.x), ring(i + 1, max.x), ring(i, max.x), Vec3::ZERO);
+                    mesh.triangle(Vec3::new(min.x, 0.0, 0.0), ring(i, min.x), ring(i + 1, min.x), Vec3::ZERO);
+                    mesh.triangle(Vec3::new(max.x, 0.0, 0.0), ring(i, max.x), ring(i + 1, max.x), Vec3::ZERO);
+                }
+            }
+            Shape::Hull => {
+                let stations = 16;
+                let inside = Vec3::new(0.0, 0.5 * (HULL_FREEBOARD - HULL_DRAUGHT), 0.0);
+                let sections: Vec<Vec<Vec3>> = (0..=stations)
+                    .map(|s| hull_section(min.z + s as f32 / stations as f32 * HULL_LENGTH))
+                    .collect();
+                for (a, b) in sections.iter().zip(&sections[1..]) {
+                    for i in 0..a.len() - 1 {
+                        mesh.quad(a[i], a[i + 1], b[i + 1], b[i], inside);
+                    }
+                    // deck, between the first and last points of each section
+                    mesh.quad(a[0], a[a.len() - 1], b[b.len() - 1], b[0], inside);
+                }
+                let stern = &sections[0];
+                let centre = Vec3::new(0.0, 0.0, min.z);
+                for i in 0..stern.len() {
+                    mesh.triangle(centre, stern[i], stern[(i + 1) % stern.len()], inside);
+                }
+            }
+        }
+        mesh.vertices
+    }
+}
+
+// narrows towards the bow, full beam at the transom
+fn hull_half_beam(z: f32) -> f32 {
+    let u = (z / HULL_LENGTH + 0.5).clamp(0.0, 1.0);
+    0.5 * HULL_BEAM * (1.0 - u.powi(4)).sqrt()
+}
+
+// cross section of the hull at z, straight sides above the waterline and a half ellipse below,
+// running from the starboard deck edge round the keel to the port deck edge
+fn hull_section(z: f32) -> Vec<Vec3> {
+    let b = hull_half_beam(z);
+    let steps = 10;
+    let mut points = vec![Vec3::new(b, HULL_FREEBOARD, z)];
+    points.extend((0..=steps).map(|i| {
+        let angle = i as f32 / steps as f32 * PI;
+        Vec3::new(b * angle.cos(), -HULL_DRAUGHT * angle.sin(), z)
+    }));
+    points.push(Vec3::new(-b, HULL_FREEBOARD, z));
+    points
+}
+
+#[repr(C)]
+pub struct BodyVertex {
+    pos: Vec4,
+    normal: Vec4,
+}
+
+// flat shaded triangle soup, normals are turned to face away from a point inside the shape so
+// the winding of the generated faces doesnt matter
+#[derive(Default)]
+struct Mesh {
+    vertices: Vec<BodyVertex>,
+}
+
+impl Mesh {
+    fn triangle(&mut self, a: Vec3, b: Vec3, c: Vec3, inside: Vec3) {
+        let cross = (b - a).cross(c - a);
+        // degenerate where the hull closes at the bow
+        if cross.length_squared() < 1e-10 {
+            return;
+        }
+        let mut normal = cross.normalize();
+        if normal.dot((a + b + c) / 3.0 - inside) < 0.0 {
+            normal = -normal;
+        }
+        for p in [a, b, c] {
+            self.vertices.push(BodyVertex {
+                pos: p.extend(1.0),
+                normal: normal.extend(0.0),
+            });
+        }
+    }
+
+    fn quad(&mut self, a: Vec3, b: Vec3, c: Vec3, d: Vec3, inside: Vec3) {
+        self.triangle(a, b, c, inside);
+        self.triangle(a, c, d, inside);
+    }
+}
+
+// a voxel of the hull, positioned relative to the centre of mass
+struct Sample {
+    point: Vec3,
+    volume: f32,
+    height: f32,
+}
+
+// everything that only depends on the shape, worked out once
+struct ShapeData {
+    samples: Vec<Sample>,
+    mass: f32,
+    // principal moments about the centre of mass, products of inertia are ignored
+    inertia: Vec3,
+    // drag coefficient times frontal area along each body axis, shared out between the samples
+    sample_drag: Vec3,
+    vertices: Range<u32>,
+}
+
+impl ShapeData {
+    fn new(shape: Shape, first_vertex: u32, mesh: &mut Vec<BodyVertex>) -> Self {
+        let (min, max) = shape.bounds();
+        let cells = ((max - min) / VOXEL_SIZE).ceil().as_uvec3().max(glam::UVec3::splat(MIN_CELLS));
+        let cell = (max - min) / cells.as_vec3();
+        let volume = cell.x * cell.y * cell.z;
+
+        let mut points = vec![];
+        for x in 0..cells.x {
+            for y in 0..cells.y {
+                for z in 0..cells.z {
+                    let p = min + (glam::UVec3::new(x, y, z).as_vec3() + 0.5) * cell;
+                    if shape.contains(p) {
+                        points.push(p);
+                    }
+                }
+            }
+        }
+        let centre = points.iter().sum::<Vec3>() / points.len() as f32;
+        let cell_mass = shape.density() * volume;
+        let inertia = points.iter().fold(Vec3::ZERO, |sum, p| {
+            let r = *p - centre;
+            sum + cell_mass * Vec3::new(r.y * r.y + r.z * r.z, r.x * r.x + r.z * r.z, r.x * r.x + r.y * r.y)
+        });
+        let size = max - min;
+        let area = Vec3::new(size.y * size.z, size.x * size.z, size.x * size.y);
+
+        let mut vertices = shape.mesh();
+        for vertex in &mut vertices {
+            vertex.pos -= centre.extend(0.0);
+        }
+        let count = vertices.len() as u32;
+        mesh.extend(vertices);
+
+        Self {
+            mass: cell_mass * points.len() as f32,
+            inertia,
+            sample_drag: shape.drag_coefficients() * area / points.len() as f32,
+            samples: points
+                .into_iter()
+                .map(|p| Sample {
+                    point: p - centre,
+                    volume,
+                    height: cell.y,
+                })
+                .collect(),
+            vertices: first_vertex..first_vertex + count,
+        }
+    }
+}
+
+pub struct FloatingBody {
+    pub shape: Shape,
+    // of the centre of mass
+    pub position: Vec3,
+    pub orientation: Quat,
+    pub velocity: Vec3,
+    pub angular_velocity: Vec3,
+    pub trail: Trail,
+    wetted: bool,
+}
+
+// a body hitting the water, as the crater it leaves for Ripples
+pub struct Splash {
+    pub position: Vec2,
+    pub radius: f32,
+    pub depth: f32,
+}
+
+impl FloatingBody {
+    // water is the surface above each sample at the start of the frame. propulsion is the
+    // (speed, turn rate) a hull steers towards, as long as some of it is in the water
+    fn integrate(&mut self, shape: &ShapeData, water: &[SurfaceSample], dt: f32, gravity: f32, propulsion: Option<Vec2>) {
+        let mut force = Vec3::new(0.0, -gravity * shape.mass, 0.0);
+        let mut torque = Vec3::ZERO;
+        self.wetted = false;
+        for (sample, water) in shape.samples.iter().zip(water) {
+            let r = self.orientation * sample.point;
+            let p = self.position + r;
+            // linear across the height of the cell
+            let submerged = ((water.position.y - p.y) / sample.height + 0.5).clamp(0.0, 1.0);
+            if submerged == 0.0 {
+                continue;
+            }
+            self.wetted = true;
+            let buoyancy = Vec3::Y * WATER_DENSITY * gravity * sample.volume * submerged;
+            let relative = self.velocity + self.angular_velocity.cross(r) - water.velocity.truncate();
+            // quadratic drag worked out along the body axes
+            let local = self.orientation.inverse() * relative;
+            let drag = self.orientation
+                * (-0.5 * WATER_DENSITY * submerged * relative.length() * shape.sample_drag * local);
+            // froude-krylov, the pressure gradient accelerating the water around the sample
+            // pushes on the body as well
+            let inertia = WATER_DENSITY * sample.volume * submerged * water.acceleration.truncate();
+            force += buoyancy + drag + inertia;
+            torque += r.cross(buoyancy + drag + inertia);
+        }
+        if let (true, Some(propulsion)) = (self.wetted, propulsion) {
+            let heading = (self.orientation * Vec3::Z).with_y(0.0).normalize_or_zero();
+            force += heading * shape.mass * THRUST_RATE * (propulsion.x - self.velocity.dot(heading));
+            torque.y += shape.inertia.y * THRUST_RATE * (propulsion.y - self.angular_velocity.y);
+        }
+
+        // semi implicit euler
+        self.velocity += force / shape.mass * dt;
+        self.position += self.velocity * dt;
+        let rotation = Mat3::from_quat(self.orientation);
+        let inverse_inertia = rotation * Mat3::from_diagonal(shape.inertia.recip()) * rotation.transpose();
+        self.angular_velocity += inverse_inertia * torque * dt;
+        self.angular_velocity *= (-ANGULAR_DAMPING * dt).exp();
+        let w = self.angular_velocity;
+        let spin = Quat::from_xyzw(w.x, w.y, w.z, 0.0) * self.orientation * (0.5 * dt);
+        self.orientation = (self.orientation + spin).normalize();
+    }
+
+    // one frame in substeps of at most MAX_BODY_STEP, returning a splash if the body hit the water
+    // faster than SPLASH_SPEED
+    fn step(&mut self, shape: &ShapeData, water: &[SurfaceSample], dt: f32, gravity: f32, propulsion: Option<Vec2>) -> Option<Splash> {
+        let substeps = (dt / MAX_BODY_STEP).ceil().min(MAX_BODY_SUBSTEPS as f32) as u32;
+        let step = dt / substeps as f32;
+        let (was_wetted, entry_speed) = (self.wetted, -self.velocity.y);
+        for _ in 0..substeps {
+            self.integrate(shape, water, step, gravity, propulsion);
+        }
+        (self.wetted && !was_wetted && entry_speed > SPLASH_SPEED).then(|| {
+            let (_, beam) = self.shape.footprint();
+            // deep enough to throw the water as high as the body fell, but no deeper than wide
+            Splash {
+                position: Vec2::new(self.position.x, self.position.z),
+                radius: 0.5 * beam,
+                depth: (entry_speed * entry_speed / (2.0 * gravity)).min(0.5 * beam),
+            }
+        })
+    }
+}
+
+// rigid bodies floating on the surface. every sample of every body is read back through one
+// surface query per frame, so adding bodies costs cpu time rather than gpu passes
+pub struct Bodies {
+    pub bodies: Vec<FloatingBody>,
+    shapes: Vec<ShapeData>,
+    // set for every hull, zero for both leaves them drifting
+    pub vessel_speed: f32,
+    pub vessel_turn_rate: f32,
+    pub vtx_buf: wgpu::Buffer,
+    pub instance_buf: wgpu::Buffer,
+    pub layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Bodies {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let mut vertices = vec![];
+        let shapes = SHAPES
+            .iter()
+            .map(|shape| ShapeData::new(*shape, vertices.len() as u32, &mut vertices))
+            .collect();
+        let vtx_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            contents: cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+            label: Some("Body Vertices"),
+        });
+        let instance_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            size: (MAX_BODIES * mem::size_of::<BodyInstance>()) as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            label: Some("Body Instances"),
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("Bodies Layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: instance_buf.as_entire_binding(),
+            }],
+            label: Some("Bodies"),
+        });
+
+        Self {
+            bodies: vec![],
+            shapes,
+            vessel_speed: 0.0,
+            vessel_turn_rate: 0.0,
+            vtx_buf,
+            instance_buf,
+            layout,
+            bind_group,
+        }
+    }
+
+    // dropped slightly tilted so they visibly right themselves
+    pub fn spawn(&mut self, shape: Shape, position: Vec3) {
+        if self.bodies.len() >= MAX_BODIES {
+            log::warn!("can't have more than {} floating bodies", MAX_BODIES);
+            return;
+        }
+        self.bodies.push(FloatingBody {
+            shape,
+            position,
+            orientation: Quat::from_rotation_z(0.2) * Quat::from_rotation_x(0.1),
+            velocity: Vec3::ZERO,
+            angular_velocity: Vec3::ZERO,
+            trail: Trail::default(),
+            wetted: false,
+        });
+    }
+
+    pub fn sample_count(&self) -> usize {
+        self.bodies.iter().map(|body| self.shapes[body.shape as usize].samples.len()).sum()
+    }
+
+    // world (x, z) of every sample of every body in order, for the surface query
+    pub fn sample_positions(&self) -> Vec<Vec2> {
+        self.bodies
+            .iter()
+            .flat_map(|body| {
+                self.shapes[body.shape as usize]
+                    .samples
+                    .iter()
+                    .map(|sample| {
+                        let p = body.position + body.orientation * sample.point;
+                        Vec2::new(p.x, p.z)
+                    })
+            })
+            .collect()
+    }
+
+    // surface holds the results of querying sample_positions. returns the splashes of bodies
+    // that hit the water this frame
+    pub fn step(&mut self, surface: &[SurfaceSample], dt: f32, time: f32, gravity: f32) -> Vec<Splash> {
+        let mut splashes = vec![];
+        // paused or running backwards, rigid bodies cant be rewound
+        if dt <= 0.0 {
+            return splashes;
+        }
+        let vessel = Vec2::new(self.vessel_speed, self.vessel_turn_rate);
+        let mut start = 0;
+        for body in &mut self.bodies {
+            let shape = &self.shapes[body.shape as usize];
+            let water = &surface[start..start + shape.samples.len()];
+            start += shape.samples.len();
+            let propulsion = (body.shape == Shape::Hull && vessel != Vec2::ZERO).then_some(vessel);
+            splashes.extend(body.step(shape, water, dt, gravity, propulsion));
+            let horizontal = Vec2::new(body.velocity.x, body.velocity.z);
+            body.trail.record(Vec2::new(body.position.x, body.position.z), horizontal.length(), time);
+        }
+        splashes
+    }
+
+    pub fn upload(&self, queue: &wgpu::Queue) {
+        let instances: Vec<BodyInstance> = self
+            .bodies
+            .iter()
+            .map(|body| BodyInstance {
+                model: Mat4::from_rotation_translation(body.orientation, body.position),
+                color: body.shape.color(),
+            })
+            .collect();
+        queue.write_buffer(&self.instance_buf, 0, cast_slice(&instances));
+    }
+
+    // vertex range and instance of every body
+    pub fn draws(&self) -> impl Iterator<Item = (Range<u32>, u32)> + '_ {
+        self.bodies
+            .iter()
+            .enumerate()
+            .map(|(i, body)| (self.shapes[body.shape as usize].vertices.clone(), i as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAVITY: f32 = 9.81;
+    const FRAME: f32 = 1.0 / 60.0;
+
+    fn body(shape: Shape, height: f32, velocity: f32) -> FloatingBody {
+        FloatingBody {
+            shape,
+            position: Vec3::new(0.0, height, 0.0),
+            orientation: Quat::IDENTITY,
+            velocity: Vec3::new(0.0, velocity, 0.0),
+            angular_velocity: Vec3::ZERO,
+            trail: Trail::default(),
+            wetted: false,
+        }
+    }
+
+    // still water at y = 0 under every sample
+    fn flat(shape: &ShapeData) -> Vec<SurfaceSample> {
+        let sample = SurfaceSample {
+            position: Vec4::ZERO,
+            normal: Vec4::Y,
+            velocity: Vec4::ZERO,
+            acceleration: Vec4::ZERO,
+        };
+        vec![sample; shape.samples.len()]
+    }
+
+    // steps frames until the body first gets wet, returning every splash along the way
+    fn fall(body: &mut FloatingBody, shape: &ShapeData) -> Vec<Splash> {
+        let water = flat(shape);
+        let mut splashes = vec![];
+        for _ in 0..600 {
+            splashes.extend(body.step(shape, &water, FRAME, GRAVITY, None));
+            if body.wetted {
+                return splashes;
+            }
+        }
+        panic!("never reached the water");
+    }
+
+    // a box half as dense as water floats half under, level and still. only drag damps the
+    // heave, and quadratic drag does little once it is slow, so it is given a simulated 20 minutes
+    #[test]
+    fn box_settles_at_half_draught() {
+        let shape = ShapeData::new(Shape::Box, 0, &mut vec![]);
+        let water = flat(&shape);
+        let (min, max) = Shape::Box.bounds();
+        let draught = (max.y - min.y) * Shape::Box.density() / WATER_DENSITY;
+        let floating = -min.y - draught;
+
+        let mut body = body(Shape::Box, floating + 0.3, 0.0);
+        for _ in 0..60 * 60 * 20 {
+            body.step(&shape, &water, FRAME, GRAVITY, None);
+        }
+        assert!((body.position.y - floating).abs() < 3e-3, "floating at {}, expected {}", body.position.y, floating);
+        assert!(body.velocity.length() < 1e-2, "still moving at {}", body.velocity);
+        assert!(body.angular_velocity.length() < 1e-3, "still turning at {}", body.angular_velocity);
+        assert!(Vec2::new(body.position.x, body.position.z).length() < 1e-3, "drifted to {}", body.position);
+    }
+
+    #[test]
+    fn fast_entry_splashes() {
+        let shape = ShapeData::new(Shape::Box, 0, &mut vec![]);
+        let mut body = body(Shape::Box, 0.55, -3.0);
+        let splashes = fall(&mut body, &shape);
+        assert_eq!(splashes.len(), 1);
+        assert!(splashes[0].depth > 0.0 && splashes[0].radius > 0.0);
+        // already wet, so sinking further doesnt splash again
+        let water = flat(&shape);
+        assert!(body.step(&shape, &water, FRAME, GRAVITY, None).is_none());
+    }
+
+    #[test]
+    fn slow_entry_doesnt_splash() {
+        let shape = ShapeData::new(Shape::Box, 0, &mut vec![]);
+        let mut body = body(Shape::Box, 0.505, -0.5);
+        assert!(fall(&mut body, &shape).is_empty());
+        assert!(-body.velocity.y < SPLASH_SPEED);
+    }
+}
@@ -0,0 +1,219 @@
+use super::cascade::CascadeMaps;
+use super::compute::ComputePass;
+use crate::engine::scene::Scene;
+use crate::engine::util::Readback;
+use crate::WG_SIZE;
+use glam::{IVec2, Vec2, Vec4};
+use shared::{Constants, BREAKING_BLOCK};
+use std::collections::VecDeque;
+use std::mem;
+
+// regions of fewer texels than this are dropped as noise
+const MIN_TEXELS: f32 = 4.0;
+
+// a connected patch of breaking surface as of time. position is the middle of the patch in the
+// tile nearest the world origin, the pattern repeats every tile so the same patch is also under
+// every other instance. area is in square metres and intensity is the mean of how far the
+// jacobian is under the threshold
+#[derive(Clone, Copy, Debug)]
+pub struct BreakingEvent {
+    pub position: Vec2,
+    pub area: f32,
+    pub intensity: f32,
+    pub time: f32,
+}
+
+// finds where waves are breaking each frame. sim::breaking reduces the mesh to blocks on the
+// gpu, so only (size / BREAKING_BLOCK)^2 texels are read back, then blocks that touch are joined
+// into regions here. the buffers are kept between frames and read back without waiting, the same
+// as SurfaceQuery, so events are a frame or so behind the surface
+pub struct BreakingDetector {
+    pass: ComputePass,
+    layout: wgpu::BindGroupLayout,
+    buffers: Option<BlockBuffers>,
+    // the readback in flight and the constants of the frame it was taken from
+    pending: Option<(Readback, Constants)>,
+    // everything found by the last detection to finish, largest first
+    pub events: Vec<BreakingEvent>,
+}
+
+// sized for side x side blocks, remade when the mesh resolution changes
+struct BlockBuffers {
+    side: u32,
+    blocks: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl BreakingDetector {
+    pub fn new(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        consts_layout: &wgpu::BindGroupLayout,
+        maps_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("Breaking Layout"),
+        });
+        let pass = ComputePass::new(
+            &[consts_layout, maps_layout, &layout],
+            &[],
+            device,
+            shader,
+            "Breaking Detection",
+            "sim::breaking::main",
+        );
+        Self {
+            pass,
+            layout,
+            buffers: None,
+            pending: None,
+            events: vec![],
+        }
+    }
+
+    // collects the last detection if the gpu has finished it and starts another on the last
+    // submitted frame, only one is in flight at a time
+    pub fn detect(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene, maps: &CascadeMaps) {
+        self.collect(device);
+        if self.pending.is_some() {
+            return;
+        }
+        let side = scene.consts.sim.size / BREAKING_BLOCK;
+        if self.buffers.as_ref().is_none_or(|b| b.side != side) {
+            self.buffers = Some(BlockBuffers::new(device, &self.layout, side));
+        }
+        let buffers = self.buffers.as_ref().unwrap();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        self.pass.compute(
+            &mut encoder,
+            "Breaking Detection",
+            &[&scene.consts_bind_group, &maps.bind_group, &buffers.bind_group],
+            side.div_ceil(WG_SIZE),
+            side.div_ceil(WG_SIZE),
+        );
+        encoder.copy_buffer_to_buffer(&buffers.blocks, 0, &buffers.readback, 0, buffers.readback.size());
+        queue.submit([encoder.finish()]);
+        self.pending = Some((Readback::start(&buffers.readback, buffers.readback.size()), scene.consts));
+    }
+
+    fn collect(&mut self, device: &wgpu::Device) {
+        let (Some((readback, consts)), Some(buffers)) = (&self.pending, &self.buffers) else {
+            return;
+        };
+        let Some(result) = readback.poll(device, &buffers.readback) else {
+            return;
+        };
+        match result {
+            Ok(floats) => {
+                let blocks: Vec<Vec4> = floats.chunks(4).map(Vec4::from_slice).collect();
+                self.events = Self::regions(&blocks, buffers.side, consts);
+            }
+            Err(e) => {
+                log::error!("failed to read back breaking blocks: {}", e);
+                self.events.clear();
+            }
+        }
+        self.pending = None;
+    }
+
+    // flood fills the blocks with any breaking texels, wrapping as the tile does. block corners
+    // are unwrapped relative to where the fill started so a region across the edge of the tile
+    // stays in one piece, and its middle is wrapped back into the tile at the end
+    fn regions(blocks: &[Vec4], side: u32, consts: &Constants) -> Vec<BreakingEvent> {
+        let sim = &consts.sim;
+        let side = side as i32;
+        let block_width = BREAKING_BLOCK as f32 * sim.mesh_step;
+        let width = sim.size as f32 * sim.mesh_step;
+        let index = |p: IVec2| (p.y.rem_euclid(side) * side + p.x.rem_euclid(side)) as usize;
+
+        let mut visited = vec![false; blocks.len()];
+        let mut events = vec![];
+        for start in 0..blocks.len() {
+            if visited[start] || blocks[start].x == 0.0 {
+                continue;
+            }
+            visited[start] = true;
+            let mut sum = Vec4::ZERO;
+            let mut queue = VecDeque::from([IVec2::new(start as i32 % side, start as i32 / side)]);
+            while let Some(p) = queue.pop_front() {
+                let block = blocks[index(p)];
+                let corner = p.as_vec2() * block_width;
+                sum += Vec4::new(block.x, block.y, block.z + corner.x * block.x, block.w + corner.y * block.x);
+                for step in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+                    let next = p + step;
+                    let i = index(next);
+                    if !visited[i] && blocks[i].x > 0.0 {
+                        visited[i] = true;
+                        queue.push_back(next);
+                    }
+                }
+            }
+            if sum.x < MIN_TEXELS {
+                continue;
+            }
+            let middle = Vec2::new(sum.z, sum.w) / sum.x;
+            let middle = middle - width * (middle / width).floor();
+            events.push(BreakingEvent {
+                position: Self::tile_corner(consts) + middle,
+                area: sum.x * sim.mesh_step * sim.mesh_step,
+                intensity: sum.y / sum.x,
+                time: consts.time,
+            });
+        }
+        events.sort_by(|a, b| b.area.total_cmp(&a.area));
+        events
+    }
+
+    // world position of the corner of the middle instance, before displacement. see main_vs
+    fn tile_corner(consts: &Constants) -> Vec2 {
+        let sim = &consts.sim;
+        let width = sim.size as f32 * sim.mesh_step;
+        let middle = (sim.instances / 2) as f32;
+        let corner = middle * width * sim.instance_micro_offset - 0.5 * width * sim.instances as f32;
+        Vec2::splat(corner)
+    }
+}
+
+impl BlockBuffers {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, side: u32) -> Self {
+        let size = (side * side) as u64 * mem::size_of::<Vec4>() as u64;
+        let blocks = device.create_buffer(&wgpu::BufferDescriptor {
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+            label: Some("Breaking Blocks"),
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+            label: Some("Breaking Readback"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: blocks.as_entire_binding(),
+            }],
+            label: Some("Breaking"),
+        });
+        Self {
+            side,
+            blocks,
+            readback,
+            bind_group,
+        }
+    }
+}
@@ -1,59 +1,71 @@
-use crate::engine::util::{bind_group_descriptor, Texture};
-use shared::Constants;
+use crate::engine::util::{array_bind_group_descriptor, bind_group_descriptor, Texture};
 
 pub struct Cascade {
+    pub size: u32,
+    // (h0(k), conj(h0(-k))) once the conjugates are packed, kept for the sea state statistics
+    pub initial_spectrum: Texture,
     pub bind_group: wgpu::BindGroup,
     pub layout: wgpu::BindGroupLayout,
-    pub h_displacement: Texture,
-    pub h_slope: Texture,
-    pub v_displacement: Texture,
-    pub jacobian: Texture,
+    pub deltas_layout: wgpu::BindGroupLayout,
+    pub deltas_bind_group: wgpu::BindGroup,
+}
+
+// h_displacement, v_displacement, h_slope and jacobian
+pub const DELTA_CHANNELS: u32 = 4;
+// h_velocity, v_motion and h_acceleration, only transformed while spectral_motion is on
+pub const MOTION_CHANNELS: u32 = 3;
+
+// displacement, normal and foam of every cascade are layers of one texture array each, so the
+// renderer binds a single group however many cascades there are
+pub struct CascadeMaps {
+    pub displacement_map: Texture,
+    pub normal_map: Texture,
+    pub foam_map: Texture,
+    // last frame's foam, copied before process_deltas so advection can read neighbouring texels
+    // while the foam map is being written
+    pub foam_history: Texture,
+    // rate of change of the displacement and of that, from the spectrum or differenced between
+    // frames depending on spectral_motion
+    pub velocity_map: Texture,
+    pub acceleration_map: Texture,
+    // the evolved spectra of every cascade, DELTA_CHANNELS layers each, so they can all go
+    // through one fft
+    pub deltas: Texture,
+    // the same for velocity and acceleration, MOTION_CHANNELS layers each
+    pub motion_deltas: Texture,
+    pub layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
 }
 
 impl Cascade {
-    pub fn new(device: &wgpu::Device, consts: &Constants, index: &str) -> Self {
+    pub fn new(device: &wgpu::Device, size: u32, index: u32, maps: &CascadeMaps) -> Self {
         let wave_texture = Texture::new_storage(
-            consts.sim.size,
-            consts.sim.size,
+            size,
+            size,
             wgpu::TextureFormat::Rgba32Float,
             device,
             &format!("Waves {}", index),
         );
         let initial_spectrum_texture = Texture::new_storage(
-            consts.sim.size,
-            consts.sim.size,
+            size,
+            size,
             wgpu::TextureFormat::Rgba32Float,
             device,
             &format!("Initial Spectrum {}", index),
         );
         let evolved_spectrum_texture = Texture::new_storage(
-            consts.sim.size,
-            consts.sim.size,
+            size,
+            size,
             wgpu::TextureFormat::Rgba32Float,
             device,
             &format!("Evolved Spectrum {}", index),
         );
-        let displacement_map = Texture::new_storage(
-            consts.sim.size,
-            consts.sim.size,
-            wgpu::TextureFormat::Rgba32Float,
-            device,
-            &format!("Displacement Map {}", index),
-        );
-        let normal_map = Texture::new_storage(
-            consts.sim.size,
-            consts.sim.size,
-            wgpu::TextureFormat::Rgba32Float,
-            device,
-            &format!("Normal Map{}", index),
-        );
-        let foam_map = Texture::new_storage(
-            consts.sim.size,
-            consts.sim.size,
-            wgpu::TextureFormat::Rgba32Float,
-            device,
-            &format!("Foam {}", index),
-        );
+        let displacement_map = maps.displacement_map.layer_view(index);
+        let normal_map = maps.normal_map.layer_view(index);
+        let foam_map = maps.foam_map.layer_view(index);
+        let foam_history = maps.foam_history.layer_view(index);
+        let velocity_map = maps.velocity_map.layer_view(index);
+        let acceleration_map = maps.acceleration_map.layer_view(index);
 
         let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
@@ -63,6 +75,9 @@ impl Cascade {
                 bind_group_descriptor(3, wgpu::TextureFormat::Rgba32Float),
                 bind_group_descriptor(4, wgpu::TextureFormat::Rgba32Float),
                 bind_group_descriptor(5, wgpu::TextureFormat::Rgba32Float),
+                bind_group_descriptor(6, wgpu::TextureFormat::Rgba32Float),
+                bind_group_descriptor(7, wgpu::TextureFormat::Rgba32Float),
+                bind_group_descriptor(8, wgpu::TextureFormat::Rgba32Float),
             ],
             label: Some("Storage Textures Layout"),
         });
@@ -83,56 +98,184 @@ impl Cascade {
                 },
                 wgpu::BindGroupEntry {
                     binding: 3,
-                    resource: wgpu::BindingResource::TextureView(&displacement_map.view),
+                    resource: wgpu::BindingResource::TextureView(&displacement_map),
                 },
                 wgpu::BindGroupEntry {
                     binding: 4,
-                    resource: wgpu::BindingResource::TextureView(&normal_map.view),
+                    resource: wgpu::BindingResource::TextureView(&normal_map),
                 },
                 wgpu::BindGroupEntry {
                     binding: 5,
-                    resource: wgpu::BindingResource::TextureView(&foam_map.view),
+                    resource: wgpu::BindingResource::TextureView(&foam_map),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::TextureView(&foam_history),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::TextureView(&velocity_map),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::TextureView(&acceleration_map),
                 },
             ],
             label: Some(&format!("Storage Textures {}", index)),
         });
 
-        let h_displacement = Texture::new_storage(
-            consts.sim.size,
-            consts.sim.size,
+        // this cascade's layers of the deltas array, the fft works on the whole array but the
+        // evolve and process passes only touch one cascade. its motion layers follow as 4..7
+        let deltas = (0..DELTA_CHANNELS)
+            .map(|channel| maps.deltas.layer_view(index * DELTA_CHANNELS + channel))
+            .chain((0..MOTION_CHANNELS).map(|channel| maps.motion_deltas.layer_view(index * MOTION_CHANNELS + channel)))
+            .collect::<Vec<_>>();
+        let deltas_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &(0..DELTA_CHANNELS + MOTION_CHANNELS)
+                .map(|binding| bind_group_descriptor(binding, wgpu::TextureFormat::Rgba32Float))
+                .collect::<Vec<_>>(),
+            label: Some("Deltas Layout"),
+        });
+        let deltas_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &deltas_layout,
+            entries: &deltas
+                .iter()
+                .enumerate()
+                .map(|(binding, view)| wgpu::BindGroupEntry {
+                    binding: binding as u32,
+                    resource: wgpu::BindingResource::TextureView(view),
+                })
+                .collect::<Vec<_>>(),
+            label: Some(&format!("Deltas {}", index)),
+        });
+
+        Self {
+            size,
+            initial_spectrum: initial_spectrum_texture,
+            layout,
+            bind_group,
+            deltas_layout,
+            deltas_bind_group,
+        }
+    }
+}
+
+impl CascadeMaps {
+    // layers are sized for the largest cascade, smaller cascades only fill the top left
+    pub fn new(device: &wgpu::Device, size: u32, count: u32) -> Self {
+        let displacement_map = Texture::new_storage_array(
+            size,
+            size,
+            count,
             wgpu::TextureFormat::Rgba32Float,
             device,
-            &format!("h_displacement {}", index),
+            "Displacement Maps",
         );
-        let h_slope = Texture::new_storage(
-            consts.sim.size,
-            consts.sim.size,
+        let normal_map = Texture::new_storage_array(
+            size,
+            size,
+            count,
             wgpu::TextureFormat::Rgba32Float,
             device,
-            &format!("h_slope {}", index),
+            "Normal Maps",
         );
-        let jacobian = Texture::new_storage(
-            consts.sim.size,
-            consts.sim.size,
+        let foam_map = Texture::new_storage_array(
+            size,
+            size,
+            count,
             wgpu::TextureFormat::Rgba32Float,
             device,
-            &format!("jacobian {}", index),
+            "Foam Maps",
         );
-        let v_displacement = Texture::new_storage(
-            consts.sim.size,
-            consts.sim.size,
+        let foam_history = Texture::new_storage_array(
+            size,
+            size,
+            count,
             wgpu::TextureFormat::Rgba32Float,
             device,
-            &format!("v_displacment {}", index),
+            "Foam History",
         );
+        let velocity_map = Texture::new_storage_array(
+            size,
+            size,
+            count,
+            wgpu::TextureFormat::Rgba32Float,
+            device,
+            "Velocity Maps",
+        );
+        let acceleration_map = Texture::new_storage_array(
+            size,
+            size,
+            count,
+            wgpu::TextureFormat::Rgba32Float,
+            device,
+            "Acceleration Maps",
+        );
+        let deltas = Texture::new_storage_array(
+            size,
+            size,
+            count * DELTA_CHANNELS,
+            wgpu::TextureFormat::Rgba32Float,
+            device,
+            "Deltas",
+        );
+        let motion_deltas = Texture::new_storage_array(
+            size,
+            size,
+            count * MOTION_CHANNELS,
+            wgpu::TextureFormat::Rgba32Float,
+            device,
+            "Motion Deltas",
+        );
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                array_bind_group_descriptor(0, wgpu::TextureFormat::Rgba32Float),
+                array_bind_group_descriptor(1, wgpu::TextureFormat::Rgba32Float),
+                array_bind_group_descriptor(2, wgpu::TextureFormat::Rgba32Float),
+                array_bind_group_descriptor(3, wgpu::TextureFormat::Rgba32Float),
+                array_bind_group_descriptor(4, wgpu::TextureFormat::Rgba32Float),
+            ],
+            label: Some("Cascade Maps Layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&displacement_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&normal_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&foam_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&velocity_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&acceleration_map.view),
+                },
+            ],
+            label: Some("Cascade Maps"),
+        });
 
         Self {
+            displacement_map,
+            normal_map,
+            foam_map,
+            foam_history,
+            velocity_map,
+            acceleration_map,
+            deltas,
+            motion_deltas,
             layout,
             bind_group,
-            h_slope,
-            h_displacement,
-            v_displacement,
-            jacobian,
         }
     }
 }
@@ -34,6 +34,19 @@ impl ComputePass {
         bind_groups: &[&wgpu::BindGroup],
         x: u32,
         y: u32,
+    ) {
+        self.compute_with_constants(encoder, label, bind_groups, &[], x, y);
+    }
+
+    // push constants are only set when there are any, the pipeline needs a matching range
+    pub fn compute_with_constants<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        label: &str,
+        bind_groups: &[&wgpu::BindGroup],
+        push_constants: &[u8],
+        x: u32,
+        y: u32,
     ) {
         let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
             timestamp_writes: None,
@@ -44,6 +57,9 @@ impl ComputePass {
         for (i, bind_group) in bind_groups.iter().enumerate() {
             pass.set_bind_group(i as _, *bind_group, &[]);
         }
+        if !push_constants.is_empty() {
+            pass.set_push_constants(0, push_constants);
+        }
         pass.dispatch_workgroups(x, y, 1);
     }
 }
@@ -0,0 +1,168 @@
+use glam::{Vec2, Vec4};
+use rayon::prelude::*;
+use std::f32::consts::PI;
+
+// same transform as sim::fft, every texel holds 2 complex signals (xy and zw) which are
+// transformed together. the butterfly texture uses exp(-2 pi i k / N) twiddles so this does too,
+// the (-1)^(x + y) in permute then shifts the centred spectrum back
+pub fn ifft2d(data: &mut [Vec4], size: usize) {
+    transform2d(data, size);
+
+    // permute
+    data.par_iter_mut().enumerate().for_each(|(i, texel)| {
+        let sign = checkerboard(i, size);
+        *texel = Vec4::new(sign * texel.x, sign * texel.z, 0.0, 1.0);
+    });
+}
+
+// sim::fft forward transform, conj(fft(conj(x))) on the shifted field then normalised
+pub fn fft2d(data: &mut [Vec4], size: usize) {
+    data.par_iter_mut().enumerate().for_each(|(i, texel)| {
+        *texel = conjugate(*texel) * checkerboard(i, size);
+    });
+    transform2d(data, size);
+    let normalisation = (size * size) as f32;
+    data.par_iter_mut().for_each(|texel| *texel = conjugate(*texel) / normalisation);
+}
+
+fn transform2d(data: &mut [Vec4], size: usize) {
+    let twiddles = twiddles(size);
+    data.par_chunks_mut(size).for_each(|row| fft(row, &twiddles));
+
+    let mut transposed = transpose(data, size);
+    transposed.par_chunks_mut(size).for_each(|column| fft(column, &twiddles));
+    data.copy_from_slice(&transpose(&transposed, size));
+}
+
+fn checkerboard(i: usize, size: usize) -> f32 {
+    match (i % size + i / size) % 2 {
+        0 => 1.0,
+        _ => -1.0,
+    }
+}
+
+fn conjugate(value: Vec4) -> Vec4 {
+    Vec4::new(value.x, -value.y, value.z, -value.w)
+}
+
+fn twiddles(size: usize) -> Vec<Vec2> {
+    (0..size / 2)
+        .map(|k| {
+            let exp = -2.0 * PI * k as f32 / size as f32;
+            Vec2::new(exp.cos(), exp.sin())
+        })
+        .collect()
+}
+
+// iterative radix 2 cooley tukey, bit reversed input then log2(N) butterfly stages
+fn fft(signal: &mut [Vec4], twiddles: &[Vec2]) {
+    let size = signal.len();
+    let bits = size.ilog2();
+    for i in 0..size {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if i < j {
+            signal.swap(i, j);
+        }
+    }
+
+    let mut span = 1;
+    while span < size {
+        let stride = size / (2 * span);
+        for start in (0..size).step_by(2 * span) {
+            for i in 0..span {
+                let twiddle = twiddles[i * stride];
+                let top = signal[start + i];
+                let bottom = signal[start + i + span];
+                let b0 = complex_mult(twiddle, Vec2::new(bottom.x, bottom.y));
+                let b1 = complex_mult(twiddle, Vec2::new(bottom.z, bottom.w));
+                let b = Vec4::new(b0.x, b0.y, b1.x, b1.y);
+                signal[start + i] = top + b;
+                signal[start + i + span] = top - b;
+            }
+        }
+        span *= 2;
+    }
+}
+
+fn transpose(data: &[Vec4], size: usize) -> Vec<Vec4> {
+    (0..size * size)
+        .into_par_iter()
+        .map(|i| data[(i % size) * size + i / size])
+        .collect()
+}
+
+pub fn complex_mult(a: Vec2, b: Vec2) -> Vec2 {
+    Vec2::new(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 16;
+
+    // texel of the centred spectrum holding wavenumber (x, y) in cycles per tile
+    fn bin(x: i32, y: i32) -> usize {
+        let half = SIZE as i32 / 2;
+        ((y + half) * SIZE as i32 + x + half) as usize
+    }
+
+    // a lone wavenumber comes back as a single real wave, xy into x and zw into y. a real
+    // amplitude is a cosine and an imaginary one a sine, which pins the sign of the twiddles
+    #[test]
+    fn ifft_of_impulse_is_a_wave() {
+        let mut data = vec![Vec4::ZERO; SIZE * SIZE];
+        data[bin(0, 0)] = Vec4::new(0.5, 0.0, 0.0, 0.0);
+        data[bin(1, 0)] = Vec4::new(1.0, 0.0, 0.0, 0.0);
+        data[bin(0, 2)] = Vec4::new(0.0, 0.0, 0.0, 1.0);
+        ifft2d(&mut data, SIZE);
+        for (i, texel) in data.iter().enumerate() {
+            let (x, y) = ((i % SIZE) as f32, (i / SIZE) as f32);
+            let cos = 0.5 + (2.0 * PI * x / SIZE as f32).cos();
+            let sin = (2.0 * PI * 2.0 * y / SIZE as f32).sin();
+            assert!((texel.x - cos).abs() < 1e-4, "x at {} is {}, expected {}", i, texel.x, cos);
+            assert!((texel.y - sin).abs() < 1e-4, "y at {} is {}, expected {}", i, texel.y, sin);
+        }
+    }
+
+    #[test]
+    fn fft_then_ifft_round_trips() {
+        // two arbitrary real fields, one in each complex signal
+        let field = |i: usize, seed: f32| ((i as f32 * 12.9898 + seed).sin() * 43758.547).fract();
+        let input: Vec<Vec4> = (0..SIZE * SIZE)
+            .map(|i| Vec4::new(field(i, 0.0), 0.0, field(i, 7.0), 0.0))
+            .collect();
+        let mut data = input.clone();
+        fft2d(&mut data, SIZE);
+        ifft2d(&mut data, SIZE);
+        for (i, (out, original)) in data.iter().zip(&input).enumerate() {
+            assert!((out.x - original.x).abs() < 1e-4, "x at {} is {}, expected {}", i, out.x, original.x);
+            assert!((out.y - original.z).abs() < 1e-4, "y at {} is {}, expected {}", i, out.y, original.z);
+        }
+    }
+
+    // cos(3 kx) splits evenly between +3 and -3 with nothing anywhere else, so |xy|^2 summed over
+    // the spectrum is the variance of the field, which statistics relies on. sin(3 kx) in zw is
+    // imaginary with +3 positive, the inverse of ifft_of_impulse_is_a_wave
+    #[test]
+    fn fft_of_cosine_is_two_bins() {
+        let mut data: Vec<Vec4> = (0..SIZE * SIZE)
+            .map(|i| {
+                let phase = 2.0 * PI * 3.0 * (i % SIZE) as f32 / SIZE as f32;
+                Vec4::new(phase.cos(), 0.0, phase.sin(), 0.0)
+            })
+            .collect();
+        fft2d(&mut data, SIZE);
+        for (i, texel) in data.iter().enumerate() {
+            let (cos, sin) = match i {
+                i if i == bin(3, 0) => (0.5, 0.5),
+                i if i == bin(-3, 0) => (0.5, -0.5),
+                _ => (0.0, 0.0),
+            };
+            let expected = Vec4::new(cos, 0.0, 0.0, sin);
+            assert!((*texel - expected).abs().max_element() < 1e-5, "{} at {}, expected {}", texel, i, expected);
+        }
+        let power: f32 = data.iter().map(|t| t.x * t.x + t.y * t.y).sum();
+        assert!((power - 0.5).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,330 @@
+use super::{cascade::CascadeMaps, simdata::SimData};
+use crate::cast_slice;
+use fft::complex_mult;
+use glam::{Vec2, Vec3, Vec4};
+use rayon::prelude::*;
+use shared::{CascadeParams, Constants, SimConstants, MAX_FOAM_STEP, MAX_FOAM_SUBSTEPS};
+
+pub mod fft;
+pub mod spectrum;
+
+// plain rust version of the compute passes in Simulation, slow but it doesnt need a gpu so it
+// acts as a reference for checking that shader changes havent broken the physics
+pub struct CpuSimulation {
+    pub cascades: Vec<CpuCascade>,
+}
+
+pub struct CpuCascade {
+    pub size: usize,
+    pub waves: Vec<Vec4>,
+    pub spectrum: Vec<Vec4>,
+    pub displacement: Vec<Vec4>,
+    pub normal: Vec<Vec4>,
+    pub foam: Vec<Vec4>,
+    pub velocity: Vec<Vec4>,
+    pub acceleration: Vec<Vec4>,
+}
+
+impl CpuCascade {
+    fn new(size: usize) -> Self {
+        Self {
+            size,
+            waves: vec![Vec4::ZERO; size * size],
+            spectrum: vec![Vec4::ZERO; size * size],
+            displacement: vec![Vec4::ZERO; size * size],
+            normal: vec![Vec4::ZERO; size * size],
+            foam: vec![Vec4::ZERO; size * size],
+            velocity: vec![Vec4::ZERO; size * size],
+            acceleration: vec![Vec4::ZERO; size * size],
+        }
+    }
+}
+
+impl CpuSimulation {
+    pub fn new(params: &[CascadeParams]) -> Self {
+        Self {
+            cascades: params.iter().map(|p| CpuCascade::new(p.size as usize)).collect(),
+        }
+    }
+
+    // initial spectrum and conjugate packing
+    pub fn compute_initial(&mut self, consts: &Constants, params: &[CascadeParams], simdata: &SimData) {
+        // noise is generated for the largest cascade so smaller ones index into the top left
+        let stride = simdata.size as usize;
+        for (index, (cascade, params)) in self.cascades.iter_mut().zip(params).enumerate() {
+            let size = cascade.size;
+            // sim::Simulation::set_heightmap, heights are already resampled to the first cascade
+            let heightmap = (consts.sim.spectrum == 6 && index == 0).then(|| {
+                let mut spectrum = simdata.heights.clone();
+                spectrum.resize(size * size, Vec4::ZERO);
+                fft::fft2d(&mut spectrum, size);
+                spectrum
+            });
+            let (waves, h0): (Vec<Vec4>, Vec<Vec2>) = (0..size * size)
+                .into_par_iter()
+                .map(|i| {
+                    let (x, y) = (i % size, i / size);
+                    spectrum::initial_spectrum(
+                        x as u32,
+                        y as u32,
+                        params,
+                        consts,
+                        simdata.gaussian_noise[y * stride + x],
+                        &simdata.measured,
+                        heightmap.as_ref().map(|h| h[i]),
+                    )
+                })
+                .unzip();
+            cascade.waves = waves;
+            cascade.spectrum = (0..size * size)
+                .into_par_iter()
+                .map(|i| {
+                    let (x, y) = (i % size, i / size);
+                    let h0c = h0[((size - y) % size) * size + (size - x) % size];
+                    Vec4::new(h0[i].x, h0[i].y, h0c.x, -h0c.y)
+                })
+                .collect();
+        }
+    }
+
+    // evolve spectra, ifft and process deltas for every cascade
+    pub fn compute(&mut self, consts: &Constants, params: &[CascadeParams]) {
+        for (cascade, params) in self.cascades.iter_mut().zip(params) {
+            let size = cascade.size;
+            let mut h_displacement = vec![Vec4::ZERO; size * size];
+            let mut v_displacement = vec![Vec4::ZERO; size * size];
+            let mut h_slope = vec![Vec4::ZERO; size * size];
+            let mut jacobian = vec![Vec4::ZERO; size * size];
+            let mut motion = vec![[Vec4::ZERO; 3]; size * size];
+            (
+                &mut h_displacement[..],
+                &mut v_displacement[..],
+                &mut h_slope[..],
+                &mut jacobian[..],
+                &mut motion[..],
+            )
+                .into_par_iter()
+                .zip(cascade.waves.par_iter().zip(&cascade.spectrum))
+                .for_each(|((hd, vd, hs, j, m), (wave, spectrum))| {
+                    (*hd, *vd, *hs, *j) = evolve(*wave, *spectrum, consts.time);
+                    *m = evolve_motion(*wave, *spectrum, consts.time);
+                });
+
+            fft::ifft2d(&mut h_displacement, size);
+            fft::ifft2d(&mut v_displacement, size);
+            fft::ifft2d(&mut h_slope, size);
+            fft::ifft2d(&mut jacobian, size);
+            let spectral_motion = consts.sim.spectral_motion != 0;
+            let [h_velocity, v_motion, h_acceleration] = [0, 1, 2].map(|channel| {
+                let mut deltas: Vec<Vec4> = motion.iter().map(|m| m[channel]).collect();
+                if spectral_motion {
+                    fft::ifft2d(&mut deltas, size);
+                }
+                deltas
+            });
+
+            let history = cascade.foam.clone();
+            (
+                &mut cascade.displacement[..],
+                &mut cascade.normal[..],
+                &mut cascade.foam[..],
+                &mut cascade.velocity[..],
+                &mut cascade.acceleration[..],
+            )
+                .into_par_iter()
+                .enumerate()
+                .for_each(|(i, (displacement, normal, foam, velocity, acceleration))| {
+                    let previous = *displacement;
+                    let folding;
+                    (*displacement, *normal, folding) = process_deltas(
+                        h_displacement[i],
+                        v_displacement[i],
+                        h_slope[i],
+                        jacobian[i],
+                        consts,
+                    );
+                    if spectral_motion {
+                        (*velocity, *acceleration) =
+                            process_motion(h_velocity[i], v_motion[i], h_acceleration[i], consts);
+                    } else {
+                        let previous_velocity = *velocity;
+                        *velocity = surface_velocity(*displacement, previous, consts.deltatime);
+                        *acceleration = surface_velocity(*velocity, previous_velocity, consts.deltatime);
+                    }
+                    let offset = foam_offset(*displacement, previous, params, &consts.sim, consts.deltatime);
+                    let p = Vec2::new((i % size) as f32, (i / size) as f32) - offset;
+                    let advected = wrapped_bilinear(&history, p, size);
+                    *foam = accumulate_foam(advected, history[i].y, folding, consts.deltatime, &consts.sim);
+                });
+        }
+    }
+
+    // copies the maps into the gpu cascades so the renderer doesnt need to know which backend ran
+    pub fn upload(&self, queue: &wgpu::Queue, maps: &CascadeMaps) {
+        for (i, cpu) in self.cascades.iter().enumerate() {
+            let (size, layer) = (cpu.size as u32, i as u32);
+            maps.displacement_map.write_layer(queue, cast_slice(&cpu.displacement), 16, size, layer);
+            maps.normal_map.write_layer(queue, cast_slice(&cpu.normal), 16, size, layer);
+            maps.foam_map.write_layer(queue, cast_slice(&cpu.foam), 16, size, layer);
+            maps.velocity_map.write_layer(queue, cast_slice(&cpu.velocity), 16, size, layer);
+            maps.acceleration_map.write_layer(queue, cast_slice(&cpu.acceleration), 16, size, layer);
+        }
+    }
+}
+
+// sim::evolve_spectra::main
+fn evolve(wave: Vec4, spectrum: Vec4, time: f32) -> (Vec4, Vec4, Vec4, Vec4) {
+    let h0 = Vec2::new(spectrum.x, spectrum.y);
+    let h0c = Vec2::new(spectrum.z, spectrum.w);
+    let phase = wave.w * time;
+    let exponent = Vec2::new(phase.cos(), phase.sin());
+    let negative_exponent = Vec2::new(exponent.x, -exponent.y);
+
+    let h = complex_mult(h0, exponent) + complex_mult(h0c, negative_exponent);
+    let ih = Vec2::new(-h.y, h.x);
+
+    let dx = -ih * wave.x * wave.z;
+    let dy = h;
+    let dz = -ih * wave.y * wave.z;
+
+    let nx = ih * wave.x;
+    let nz = ih * wave.y;
+
+    let j_xx = -h * wave.x * wave.x * wave.z;
+    let j_zz = -h * wave.y * wave.y * wave.z;
+    let j_xz = -h * wave.x * wave.y * wave.z;
+
+    (
+        Vec4::new(dx.x, dx.y, dz.x, dz.y),
+        Vec4::new(dy.x, dy.y, j_xz.x, j_xz.y),
+        Vec4::new(nx.x, nx.y, nz.x, nz.y),
+        Vec4::new(j_xx.x, j_xx.y, j_zz.x, j_zz.y),
+    )
+}
+
+// sim::evolve_spectra::main, the velocity and acceleration terms
+fn evolve_motion(wave: Vec4, spectrum: Vec4, time: f32) -> [Vec4; 3] {
+    let h0 = Vec2::new(spectrum.x, spectrum.y);
+    let h0c = Vec2::new(spectrum.z, spectrum.w);
+    let phase = wave.w * time;
+    let exponent = Vec2::new(phase.cos(), phase.sin());
+    let negative_exponent = Vec2::new(exponent.x, -exponent.y);
+
+    let omega = wave.w;
+    let h = complex_mult(h0, exponent) + complex_mult(h0c, negative_exponent);
+    let ht = complex_mult(h0, exponent) - complex_mult(h0c, negative_exponent);
+    let iht = Vec2::new(-ht.y, ht.x) * omega;
+    let htt = -h * omega * omega;
+    let ihtt = Vec2::new(-htt.y, htt.x);
+
+    let vx = -iht * wave.x * wave.z;
+    let vy = iht;
+    let vz = -iht * wave.y * wave.z;
+    let ax = -ihtt * wave.x * wave.z;
+    let ay = htt;
+    let az = -ihtt * wave.y * wave.z;
+
+    [
+        Vec4::new(vx.x, vx.y, vz.x, vz.y),
+        Vec4::new(vy.x, vy.y, ay.x, ay.y),
+        Vec4::new(ax.x, ax.y, az.x, az.y),
+    ]
+}
+
+// sim::process_deltas::main, velocity and acceleration while spectral_motion is on
+fn process_motion(h_velocity: Vec4, v_motion: Vec4, h_acceleration: Vec4, consts: &Constants) -> (Vec4, Vec4) {
+    let choppiness = consts.sim.choppiness;
+    (
+        Vec4::new(h_velocity.x * choppiness, v_motion.x, h_velocity.y * choppiness, 0.0),
+        Vec4::new(h_acceleration.x * choppiness, v_motion.y, h_acceleration.y * choppiness, 0.0),
+    )
+}
+
+// sim::process_deltas::main, up to the foam which needs the neighbouring texels
+fn process_deltas(
+    h_displacement: Vec4,
+    v_displacement: Vec4,
+    h_slope: Vec4,
+    jacobian: Vec4,
+    consts: &Constants,
+) -> (Vec4, Vec4, f32) {
+    let displacement = Vec4::new(
+        h_displacement.x * consts.sim.choppiness,
+        v_displacement.x,
+        h_displacement.y * consts.sim.choppiness,
+        1.0,
+    );
+    let normal = Vec3::new(-h_slope.x, 1.0, -h_slope.y).normalize().extend(1.0);
+
+    let jxx = 1.0 + consts.sim.choppiness * jacobian.x;
+    let jzz = 1.0 + consts.sim.choppiness * jacobian.y;
+    let jxz = consts.sim.choppiness * v_displacement.y;
+
+    let jacobian = consts.sim.foam_bias - (jxx * jzz - jxz * jxz);
+    (displacement, normal, jacobian)
+}
+
+// sim::process_deltas::surface_velocity
+fn surface_velocity(displacement: Vec4, previous: Vec4, dt: f32) -> Vec4 {
+    if dt != 0.0 {
+        ((displacement - previous) / dt).truncate().extend(0.0)
+    } else {
+        Vec4::ZERO
+    }
+}
+
+// sim::process_deltas::foam_offset
+fn foam_offset(displacement: Vec4, previous: Vec4, params: &CascadeParams, consts: &SimConstants, dt: f32) -> Vec2 {
+    let wind = Vec2::new(consts.wind_offset.cos(), consts.wind_offset.sin());
+    let drift = wind * consts.wind_speed * consts.foam_drift * dt;
+    let orbital = if dt != 0.0 {
+        Vec2::new(displacement.x - previous.x, displacement.z - previous.z) * consts.foam_orbital
+    } else {
+        Vec2::ZERO
+    };
+    (drift + orbital) * params.size as f32 / params.lengthscale
+}
+
+// sim::process_deltas::wrapped_bilinear
+fn wrapped_bilinear(map: &[Vec4], p: Vec2, size: usize) -> Vec4 {
+    let size_f = size as f32;
+    let p = p - size_f * (p / size_f).floor();
+    let base = p.floor();
+    let t = p - base;
+    let x0 = base.x as usize % size;
+    let y0 = base.y as usize % size;
+    let x1 = (x0 + 1) % size;
+    let y1 = (y0 + 1) % size;
+    let top = map[y0 * size + x0].lerp(map[y0 * size + x1], t.x);
+    let bottom = map[y1 * size + x0].lerp(map[y1 * size + x1], t.x);
+    top.lerp(bottom, t.y)
+}
+
+// sim::process_deltas::accumulate_foam
+fn accumulate_foam(foam: Vec4, previous_source: f32, jacobian: f32, dt: f32, consts: &SimConstants) -> Vec4 {
+    let source = if jacobian >= consts.injection_threshold {
+        jacobian * consts.injection_amount
+    } else {
+        0.0
+    };
+    let dt = dt.abs();
+    let substeps = (dt / MAX_FOAM_STEP).ceil().clamp(1.0, MAX_FOAM_SUBSTEPS as f32) as u32;
+    let step = dt / substeps as f32;
+    let decay = (-consts.foam_decay * step).exp();
+    let old_decay = (-consts.old_foam_decay * step).exp();
+    let weight = if consts.foam_decay > 0.0 {
+        (1.0 - decay) / consts.foam_decay
+    } else {
+        step
+    };
+
+    let mut fresh = foam.z;
+    let mut old = foam.w;
+    for i in 0..substeps {
+        let t = (i as f32 + 0.5) / substeps as f32;
+        let decayed = (fresh * (1.0 - decay)).max(0.0);
+        fresh = fresh * decay + (previous_source + (source - previous_source) * t) * weight;
+        old = old * old_decay + decayed * consts.old_foam_transfer;
+    }
+    Vec4::new(fresh + old, source, fresh, old)
+}
@@ -0,0 +1,325 @@
+use glam::{Vec2, Vec4};
+use shared::{CascadeParams, Constants, SimConstants, MEASURED_DIRECTIONS, MEASURED_FREQUENCIES};
+use std::f32::consts::PI;
+
+// line for line port of shaders/src/sim/initial_spectra.rs, any change to the shader needs to be
+// mirrored here or the cpu backend stops being a useful reference
+
+// returns the wave texel (k.x, k.y, 1 / |k|, omega) and h0 for texel (x, y). heightmap is the
+// heightmap spectrum texel, only given to the first cascade when the heightmap model is selected
+pub fn initial_spectrum(
+    x: u32,
+    y: u32,
+    params: &CascadeParams,
+    consts: &Constants,
+    gaussian: Vec4,
+    measured: &[Vec4],
+    heightmap: Option<Vec4>,
+) -> (Vec4, Vec2) {
+    let dk: f32 = 2.0 * PI / params.lengthscale;
+    let n = x as f32 - 0.5 * params.size as f32;
+    let m = y as f32 - 0.5 * params.size as f32;
+    let k: Vec2 = Vec2::new(n, m) * dk;
+    let k_length = k.length();
+    let in_band = k_length <= params.cutoff_high && k_length >= params.cutoff_low;
+    let painted = heightmap.is_some() && k_length > 0.0;
+
+    if in_band || painted {
+        let theta = angle(k, consts.sim.wind_offset);
+        let omega = dispersion_relation(k_length, &consts.sim);
+        let domega_dk = dispersion_derivative(k_length, &consts.sim);
+        let wind_sea = match consts.sim.spectrum {
+            5 => measured_spectrum(omega, angle(k, 0.0), &consts.sim, measured),
+            6 => 0.0,
+            _ => {
+                let omega_peak = peak_frequency(&consts.sim);
+                let energy = frequency_spectrum(omega, omega_peak, k_length, domega_dk, &consts.sim);
+                let spread = final_spread(omega, omega_peak, theta, k_length, dk, consts);
+                energy * spread
+            }
+        };
+        let swells = swell_spectra(k, omega, &consts.sim);
+        let spectrum = if in_band {
+            2.0 * (wind_sea + swells) * domega_dk.abs() * dk * dk / k_length
+        } else {
+            0.0
+        };
+        let mut h0 = 1.0 / 2.0_f32.sqrt() * Vec2::new(gaussian.x, gaussian.y) * spectrum.sqrt();
+        if let (true, Some(heightmap)) = (painted, heightmap) {
+            h0 += heightmap_spectrum(k, heightmap, &consts.sim);
+        }
+        (Vec4::new(k.x, k.y, 1.0 / k_length, omega), h0)
+    } else {
+        (Vec4::new(k.x, k.y, 0.0, 1.0), Vec2::ZERO)
+    }
+}
+
+fn heightmap_spectrum(k: Vec2, spectrum: Vec4, consts: &SimConstants) -> Vec2 {
+    let facing = k.dot(Vec2::new(consts.wind_offset.cos(), consts.wind_offset.sin()));
+    let weight = if facing > 0.0 {
+        1.0
+    } else if facing == 0.0 {
+        0.5
+    } else {
+        0.0
+    };
+    Vec2::new(spectrum.x, spectrum.y) * weight * consts.heightmap_amplitude
+}
+
+pub fn dispersion_relation(k: f32, consts: &SimConstants) -> f32 {
+    (consts.gravity * k * (k * consts.depth).min(20.0).tanh()).sqrt()
+}
+
+fn dispersion_derivative(k: f32, consts: &SimConstants) -> f32 {
+    let tanh = (consts.depth * k).min(20.0).tanh();
+    let sech = 1.0 / (consts.depth * k).cosh();
+    (consts.gravity * (tanh + consts.depth * k * sech * sech)) / (2.0 * (consts.gravity * k * tanh).sqrt())
+}
+
+fn angle(k: Vec2, offset: f32) -> f32 {
+    let mut angle: f32 = (k.y).atan2(k.x) - offset;
+    angle = fmod(angle + PI, 2.0 * PI);
+    if angle < 0.0 {
+        angle += 2.0 * PI;
+    }
+    angle - PI
+}
+
+fn fmod(a: f32, b: f32) -> f32 {
+    a - b * (a / b).floor()
+}
+
+fn frequency_spectrum(omega: f32, omega_p: f32, k: f32, domega_dk: f32, consts: &SimConstants) -> f32 {
+    match consts.spectrum {
+        1 => jonswap(omega, omega_p, consts),
+        2 => pierson_moskowitz(omega, omega_p, consts),
+        3 => phillips(omega, omega_p, consts),
+        4 => elfouhaily(k, omega, consts) / domega_dk.abs(),
+        _ => jonswap(omega, omega_p, consts) * depth_attenuation(omega, consts),
+    }
+}
+
+fn peak_frequency(consts: &SimConstants) -> f32 {
+    match consts.spectrum {
+        2 => 0.855 * consts.gravity / consts.wind_speed,
+        3 => 0.8_f32.powf(0.25) * consts.gravity / consts.wind_speed,
+        4 => inverse_wave_age(consts) * consts.gravity / consts.wind_speed,
+        _ => 22.0 * ((consts.gravity * consts.gravity) / (consts.wind_speed * consts.fetch)).powf(1.0 / 3.0),
+    }
+}
+
+fn jonswap(omega: f32, omega_p: f32, consts: &SimConstants) -> f32 {
+    let sigma = if omega <= omega_p { 0.07 } else { 0.09 };
+    let alpha = 0.076 * ((consts.wind_speed * consts.wind_speed) / (consts.fetch * consts.gravity)).powf(0.22);
+    let r = (-(omega - omega_p) * (omega - omega_p) / (2.0 * omega_p * omega_p * sigma * sigma)).exp();
+    alpha * consts.gravity * consts.gravity / (omega * omega * omega * omega * omega)
+        * (-consts.beta * (omega_p / omega).powf(4.0)).exp()
+        * consts.gamma.powf(r)
+}
+
+fn pierson_moskowitz(omega: f32, omega_p: f32, consts: &SimConstants) -> f32 {
+    let alpha = 0.0081;
+    alpha * consts.gravity * consts.gravity / omega.powf(5.0) * (-consts.beta * (omega_p / omega).powf(4.0)).exp()
+}
+
+fn phillips(omega: f32, omega_p: f32, consts: &SimConstants) -> f32 {
+    let alpha = 0.0081;
+    let omega_0 = omega_p / 0.8_f32.powf(0.25);
+    alpha * consts.gravity * consts.gravity / omega.powf(5.0) * (-(omega_0 / omega).powf(4.0)).exp()
+}
+
+fn elfouhaily(k: f32, omega: f32, consts: &SimConstants) -> f32 {
+    let u = consts.wind_speed;
+    let omega_c = inverse_wave_age(consts);
+    let k_p = consts.gravity * omega_c * omega_c / (u * u);
+    let c_p = (consts.gravity / k_p).sqrt();
+    let c = omega / k;
+
+    let gamma = if omega_c <= 1.0 {
+        1.7
+    } else {
+        1.7 + 6.0 * omega_c.log10()
+    };
+    let sigma = 0.08 * (1.0 + 4.0 / (omega_c * omega_c * omega_c));
+    let r = (-((k / k_p).sqrt() - 1.0).powf(2.0) / (2.0 * sigma * sigma)).exp();
+    let l_pm = (-1.25 * (k_p / k) * (k_p / k)).exp();
+    let j_p = gamma.powf(r);
+
+    let alpha_p = 0.006 * omega_c.sqrt();
+    let f_p = l_pm * j_p * (-omega_c / 10.0_f32.sqrt() * ((k / k_p).sqrt() - 1.0)).exp();
+    let b_l = 0.5 * alpha_p * (c_p / c) * f_p;
+
+    let c_m = 0.23;
+    let k_m = 370.0;
+    let z_0 = 3.7e-5 * u * u / consts.gravity * (u / c_p).powf(0.9);
+    let u_star = 0.41 * u / (10.0 / z_0).ln();
+    let alpha_m = if u_star <= c_m {
+        0.01 * (1.0 + (u_star / c_m).ln())
+    } else {
+        0.01 * (1.0 + 3.0 * (u_star / c_m).ln())
+    };
+    let f_m = l_pm * j_p * (-0.25 * (k / k_m - 1.0) * (k / k_m - 1.0)).exp();
+    let b_h = (0.5 * alpha_m * (c_m / c) * f_m).max(0.0);
+
+    (b_l + b_h) / (k * k * k)
+}
+
+fn inverse_wave_age(consts: &SimConstants) -> f32 {
+    let x = consts.gravity * consts.fetch / (consts.wind_speed * consts.wind_speed);
+    0.84 * ((x / 22000.0).powf(0.4)).tanh().powf(-0.75)
+}
+
+fn measured_spectrum(omega: f32, theta: f32, consts: &SimConstants, measured: &[Vec4]) -> f32 {
+    let f = omega / (2.0 * PI);
+    if f < consts.measured_freq_min || f > consts.measured_freq_max {
+        return 0.0;
+    }
+    let row = (f - consts.measured_freq_min) / (consts.measured_freq_max - consts.measured_freq_min)
+        * (MEASURED_FREQUENCIES - 1) as f32;
+    let col = fmod(theta, 2.0 * PI) / (2.0 * PI) * MEASURED_DIRECTIONS as f32;
+
+    let row0 = (row.floor() as u32).min(MEASURED_FREQUENCIES - 1);
+    let row1 = (row0 + 1).min(MEASURED_FREQUENCIES - 1);
+    let col0 = (col.floor() as u32) % MEASURED_DIRECTIONS;
+    let col1 = (col0 + 1) % MEASURED_DIRECTIONS;
+    let tr = row - row.floor();
+    let tc = col - col.floor();
+
+    let at = |c: u32, r: u32| measured[(r * MEASURED_DIRECTIONS + c) as usize].x;
+    let low = at(col0, row0) * (1.0 - tc) + at(col1, row0) * tc;
+    let high = at(col0, row1) * (1.0 - tc) + at(col1, row1) * tc;
+    (low * (1.0 - tr) + high * tr) / (2.0 * PI)
+}
+
+fn swell_spectra(k: Vec2, omega: f32, consts: &SimConstants) -> f32 {
+    let mut sum = 0.0;
+    for swell in &consts.swells[..consts.swell_count as usize] {
+        let omega_p = 2.0 * PI / swell.peak_period;
+        let theta = angle(k, swell.direction);
+        sum += swell_spectrum(omega, omega_p, swell.significant_height, swell.peakedness) * cos_2s(theta, swell.spread);
+    }
+    sum
+}
+
+fn swell_spectrum(omega: f32, omega_p: f32, hs: f32, gamma: f32) -> f32 {
+    let sigma = if omega <= omega_p { 0.07 } else { 0.09 };
+    let r = (-(omega - omega_p) * (omega - omega_p) / (2.0 * omega_p * omega_p * sigma * sigma)).exp();
+    let pm = 5.0 / 16.0 * hs * hs * omega_p.powf(4.0) / omega.powf(5.0)
+        * (-1.25 * (omega_p / omega).powf(4.0)).exp();
+    pm * gamma.powf(r) * (1.0 - 0.287 * gamma.ln())
+}
+
+fn depth_attenuation(omega: f32, consts: &SimConstants) -> f32 {
+    let omega_h = omega * (consts.depth / consts.gravity).sqrt();
+    if omega_h <= 1.0 {
+        0.5 * omega_h * omega_h
+    } else if omega_h < 2.0 {
+        1.0 - 0.5 * (2.0 - omega_h) * (2.0 - omega_h)
+    } else {
+        1.0
+    }
+}
+
+fn donelan_banner(omega: f32, omega_p: f32, theta: f32) -> f32 {
+    let k = omega / omega_p;
+    let beta_s = if k < 0.95 {
+        2.61 * k.abs().powf(1.3)
+    } else if k <= 1.6 {
+        2.28 * k.abs().powf(-1.3)
+    } else {
+        10.0_f32.powf(-0.4 + 0.8393 * (-0.567 * (k * k).ln()).exp())
+    };
+    let sech = 1.0 / (beta_s * theta).cosh();
+    beta_s / (2.0 * (beta_s * PI).tanh()) * sech * sech
+}
+
+fn directional_spread(omega: f32, omega_p: f32, theta: f32, consts: &Constants) -> f32 {
+    donelan_banner(omega, omega_p, theta) * d_epsilon(omega, omega_p, theta, consts)
+}
+
+fn final_spread(omega: f32, omega_p: f32, theta: f32, k: f32, dk: f32, consts: &Constants) -> f32 {
+    match consts.sim.spreading {
+        1 => cos_2s(theta, hasselmann_exponent(omega, omega_p, &consts.sim)),
+        2 => longuet_higgins(theta, consts.sim.spread_exponent),
+        3 => positive_cosine_squared(theta),
+        4 => unidirectional(theta, k, dk),
+        _ => directional_spread(omega, omega_p, theta, consts) * integral(omega_p, omega, consts),
+    }
+}
+
+fn cos_2s(theta: f32, s: f32) -> f32 {
+    let q = ((2.0 * s - 1.0) * 2.0_f32.ln() + 2.0 * ln_gamma(s + 1.0) - ln_gamma(2.0 * s + 1.0)).exp() / PI;
+    q * (theta / 2.0).cos().abs().powf(2.0 * s)
+}
+
+fn hasselmann_exponent(omega: f32, omega_p: f32, consts: &SimConstants) -> f32 {
+    let ratio = omega / omega_p;
+    if ratio < 1.05 {
+        6.97 * ratio.powf(4.06)
+    } else {
+        let mu = -2.33 - 1.45 * (consts.wind_speed * omega_p / consts.gravity - 1.17);
+        9.77 * ratio.powf(mu)
+    }
+}
+
+fn longuet_higgins(theta: f32, s: f32) -> f32 {
+    if theta.abs() >= 0.5 * PI {
+        return 0.0;
+    }
+    let n = (ln_gamma(s + 1.0) - ln_gamma(s + 0.5)).exp() / PI.sqrt();
+    n * theta.cos().powf(2.0 * s)
+}
+
+fn positive_cosine_squared(theta: f32) -> f32 {
+    if theta.abs() >= 0.5 * PI {
+        return 0.0;
+    }
+    2.0 / PI * theta.cos() * theta.cos()
+}
+
+fn unidirectional(theta: f32, k: f32, dk: f32) -> f32 {
+    let width = dk / k;
+    if theta.abs() < 0.5 * width {
+        1.0 / width
+    } else {
+        0.0
+    }
+}
+
+fn ln_gamma(x: f32) -> f32 {
+    let mut z = x;
+    let mut shift = 0.0;
+    while z < 7.0 {
+        shift -= z.ln();
+        z += 1.0;
+    }
+    let z2 = z * z;
+    shift + (z - 0.5) * z.ln() - z + 0.5 * (2.0 * PI).ln()
+        + 1.0 / (12.0 * z) - 1.0 / (360.0 * z * z2) + 1.0 / (1260.0 * z * z2 * z2)
+}
+
+fn integral(omega_p: f32, omega: f32, consts: &Constants) -> f32 {
+    let mut sum = 0.0;
+    let steps = 2.0 * PI / consts.sim.integration_step;
+    for i in 0..steps as usize {
+        let angle = i as f32 * consts.sim.integration_step - PI;
+        sum += directional_spread(omega, omega_p, angle, consts) * consts.sim.integration_step;
+    }
+    1.0 / sum
+}
+
+fn d_epsilon(omega: f32, omega_p: f32, theta: f32, consts: &Constants) -> f32 {
+    let s = 16.0 * (omega_p / omega).tanh() * consts.sim.swell * consts.sim.swell;
+    normalisation_factor(s) * (theta / 2.0).cos().abs().powf(2.0 * s)
+}
+
+fn normalisation_factor(s: f32) -> f32 {
+    let s2 = s * s;
+    let s3 = s2 * s;
+    let s4 = s3 * s;
+    if s < 5.0 {
+        -0.000564 * s4 + 0.00776 * s3 - 0.044 * s2 + 0.192 * s + 0.163
+    } else {
+        -4.80e-08 * s4 + 1.07e-05 * s3 - 9.53e-04 * s2 + 5.90e-02 * s + 3.93e-01
+    }
+}
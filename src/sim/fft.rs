@@ -1,30 +1,49 @@
-use super::SimData;
-use crate::{cast_slice, engine::{scene::Scene, util::Texture}, WG_SIZE};
-use shared::FFTData;
+use crate::{cast_slice, engine::util::{bind_group_descriptor, Texture}, WG_SIZE};
+use shared::{FFTData, SharedFFTData, StockhamData, MAX_SHARED_FFT_SIZE};
+use std::collections::HashMap;
 use std::mem;
 
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum FftAlgorithm {
+    #[default]
+    CooleyTukey,
+    Stockham,
+    SharedMemory,
+}
+
 pub struct FourierTransform {
+    pub algorithm: FftAlgorithm,
     h_ifft: PipelineFFT,
     v_ifft: PipelineFFT,
     permute: PipelineFFT,
+    precompute: PipelineFFT,
+    h_stockham: PipelineFFT,
+    v_stockham: PipelineFFT,
+    h_shared: PipelineFFT,
+    v_shared: PipelineFFT,
+    // same layers as the deltas array, sized for the largest cascade as smaller ffts only touch
+    // the top left
     pingpong1: Texture,
+    // one butterfly texture per cascade size, created the first time a size is used
+    butterflies: HashMap<u32, Texture>,
 }
 
 impl FourierTransform {
-    pub fn new(
-        device: &wgpu::Device,
-        shader: &wgpu::ShaderModule,
-        scene: &Scene,
-        simdata: &SimData,
-    ) -> Self {
-        let pingpong1 = Texture::new_storage(
-            scene.consts.sim.size,
-            scene.consts.sim.size,
+    pub fn new(device: &wgpu::Device, shader: &wgpu::ShaderModule, size: u32, layers: u32) -> Self {
+        let pingpong1 = Texture::new_storage_array(
+            size,
+            size,
+            layers,
             wgpu::TextureFormat::Rgba32Float,
             device,
             "PingPong 1",
         );
-        let bind_group_layouts = &[&simdata.layout, &pingpong1.layout, &pingpong1.layout]; // layout is same for 0 and 1
+        // butterflies are plain 2d textures, the pingpongs are arrays of every channel
+        let butterfly_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[bind_group_descriptor(0, wgpu::TextureFormat::Rgba32Float)],
+            label: Some("Butterfly Layout"),
+        });
+        let bind_group_layouts = &[&butterfly_layout, &pingpong1.layout, &pingpong1.layout];
         let push_constant_ranges = &[wgpu::PushConstantRange {
             stages: wgpu::ShaderStages::COMPUTE,
             range: 0..mem::size_of::<FFTData>() as u32,
@@ -54,55 +73,192 @@ impl FourierTransform {
             "Permute",
             "sim::fft::permute",
         );
+        let precompute = PipelineFFT::new(
+            &[&butterfly_layout],
+            &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..mem::size_of::<u32>() as u32,
+            }],
+            device,
+            shader,
+            "Precompute Butterfly",
+            "sim::fft::precompute_butterfly",
+        );
+        // stockham doesnt need a butterfly texture so only the pingpongs are bound
+        let stockham_push_constant_ranges = &[wgpu::PushConstantRange {
+            stages: wgpu::ShaderStages::COMPUTE,
+            range: 0..mem::size_of::<StockhamData>() as u32,
+        }];
+        let h_stockham = PipelineFFT::new(
+            &[&pingpong1.layout, &pingpong1.layout],
+            stockham_push_constant_ranges,
+            device,
+            shader,
+            "H-Step Stockham",
+            "sim::fft::hstep_stockham",
+        );
+        let v_stockham = PipelineFFT::new(
+            &[&pingpong1.layout, &pingpong1.layout],
+            stockham_push_constant_ranges,
+            device,
+            shader,
+            "V-Step Stockham",
+            "sim::fft::vstep_stockham",
+        );
+        // in place, so only the texture being transformed is bound
+        let shared_push_constant_ranges = &[wgpu::PushConstantRange {
+            stages: wgpu::ShaderStages::COMPUTE,
+            range: 0..mem::size_of::<SharedFFTData>() as u32,
+        }];
+        let h_shared = PipelineFFT::new(
+            &[&pingpong1.layout],
+            shared_push_constant_ranges,
+            device,
+            shader,
+            "H-Step Shared",
+            "sim::fft::hstep_shared",
+        );
+        let v_shared = PipelineFFT::new(
+            &[&pingpong1.layout],
+            shared_push_constant_ranges,
+            device,
+            shader,
+            "V-Step Shared",
+            "sim::fft::vstep_shared",
+        );
 
         Self {
+            algorithm: FftAlgorithm::default(),
             h_ifft,
             v_ifft,
             permute,
+            precompute,
+            h_stockham,
+            v_stockham,
+            h_shared,
+            v_shared,
             pingpong1,
+            butterflies: HashMap::new(),
         }
     }
 
+    // ping pong texture has to match the deltas array
+    pub fn resize(&mut self, device: &wgpu::Device, size: u32, layers: u32) {
+        self.pingpong1 = Texture::new_storage_array(
+            size,
+            size,
+            layers,
+            wgpu::TextureFormat::Rgba32Float,
+            device,
+            "PingPong 1",
+        );
+    }
+
+    // creates and precomputes the butterfly texture of any size that doesnt have one yet
+    pub fn prepare(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, sizes: &[u32]) {
+        for &size in sizes {
+            if self.butterflies.contains_key(&size) {
+                continue;
+            }
+            let butterfly = Texture::new_storage(
+                size.ilog2(),
+                size,
+                wgpu::TextureFormat::Rgba32Float,
+                device,
+                &format!("Butterfly {}", size),
+            );
+            self.precompute.compute(
+                encoder,
+                &[&butterfly.bind_group],
+                cast_slice(&[size]),
+                &format!("Precompute Butterfly {}", size),
+                [size.ilog2(), size / WG_SIZE, 1],
+            );
+            self.butterflies.insert(size, butterfly);
+        }
+    }
+
+    // transforms `layers` layers of the array starting at `layer` at once, they all have to be
+    // the same size. takes a centred spectrum and leaves the real part of both packed signals in
+    // x and y
     pub fn ifft2d<'a>(
         &'a self,
         encoder: &'a mut wgpu::CommandEncoder,
-        scene: &Scene,
-        simdata: &SimData,
         pingpong0: &Texture,
-        index: u32,
+        size: u32,
+        layer: u32,
+        layers: u32,
+    ) {
+        self.transform(encoder, pingpong0, Batch { size, layer, layers }, 0);
+    }
+
+    // spatial -> spectral, each texel is 2 complex signals (xy and zw) and comes out as their
+    // centred spectra in the same layout as the initial spectrum. normalised, so ifft2d gives the
+    // real part of the input back
+    pub fn fft2d<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        pingpong0: &Texture,
+        size: u32,
+        layer: u32,
+        layers: u32,
+    ) {
+        self.transform(encoder, pingpong0, Batch { size, layer, layers }, 1);
+    }
+
+    fn transform<'a>(&'a self, encoder: &'a mut wgpu::CommandEncoder, pingpong0: &Texture, batch: Batch, forward: u32) {
+        match self.algorithm {
+            FftAlgorithm::CooleyTukey => self.cooley_tukey(encoder, pingpong0, batch, forward),
+            FftAlgorithm::Stockham => self.stockham(encoder, pingpong0, batch, forward),
+            // rows larger than workgroup memory cant be done in one dispatch
+            FftAlgorithm::SharedMemory if batch.size as usize > MAX_SHARED_FFT_SIZE => {
+                self.stockham(encoder, pingpong0, batch, forward)
+            }
+            FftAlgorithm::SharedMemory => self.shared_memory(encoder, pingpong0, batch, forward),
+        }
+    }
+
+    // radix 2, log2(size) dispatches per direction then a permute
+    fn cooley_tukey<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        pingpong0: &Texture,
+        Batch { size, layer, layers }: Batch,
+        forward: u32,
     ) {
         let bind_groups = &[
-            &simdata.bind_group,
+            &self.butterflies[&size].bind_group,
             &pingpong0.bind_group,
             &self.pingpong1.bind_group,
         ];
-        let wg_size = scene.consts.sim.size / WG_SIZE;
+        let wg_size = size / WG_SIZE;
         let mut data = FFTData {
             stage: 0,
             pingpong: 0,
+            layer,
+            size,
+            forward,
         };
 
-        for stage in 0..scene.consts.sim.size.ilog2() {
+        for stage in 0..size.ilog2() {
             data.stage = stage;
             self.h_ifft.compute(
                 encoder,
                 bind_groups,
                 cast_slice(&[data]),
-                &format!("H-Step {}, {}", stage, index),
-                wg_size,
-                wg_size,
+                &format!("H-Step {}, {}", stage, layer),
+                [wg_size, wg_size, layers],
             );
             data.pingpong = (data.pingpong + 1) % 2;
         }
-        for stage in 0..scene.consts.sim.size.ilog2() {
+        for stage in 0..size.ilog2() {
             data.stage = stage;
             self.v_ifft.compute(
                 encoder,
                 bind_groups,
                 cast_slice(&[data]),
-                &format!("V-Step {}, {}", stage, index),
-                wg_size,
-                wg_size,
+                &format!("V-Step {}, {}", stage, layer),
+                [wg_size, wg_size, layers],
             );
             data.pingpong = (data.pingpong + 1) % 2;
         }
@@ -111,11 +267,101 @@ impl FourierTransform {
             encoder,
             bind_groups,
             cast_slice(&[data]),
-            &format!("Permute {}", index),
-            wg_size,
-            wg_size,
+            &format!("Permute {}", layer),
+            [wg_size, wg_size, layers],
         );
     }
+
+    // radix 4 with a leading radix 2 stage for odd powers of 2. both directions have the same
+    // number of stages so the result always ends up back in pingpong0
+    fn stockham<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        pingpong0: &Texture,
+        Batch { size, layer, layers }: Batch,
+        forward: u32,
+    ) {
+        let bind_groups = &[&pingpong0.bind_group, &self.pingpong1.bind_group];
+        let logsize = size.ilog2();
+        let radices = (logsize % 2 == 1)
+            .then_some(2)
+            .into_iter()
+            .chain(std::iter::repeat_n(4, logsize as usize / 2))
+            .collect::<Vec<u32>>();
+        let wg_size = size.div_ceil(WG_SIZE);
+        let mut data = StockhamData {
+            size,
+            span: 1,
+            radix: 0,
+            pingpong: 0,
+            layer,
+            forward,
+        };
+
+        for &radix in &radices {
+            data.radix = radix;
+            self.h_stockham.compute(
+                encoder,
+                bind_groups,
+                cast_slice(&[data]),
+                &format!("H-Step Stockham {}, {}", data.span, layer),
+                [(size / radix).div_ceil(WG_SIZE), wg_size, layers],
+            );
+            data.span *= radix;
+            data.pingpong = (data.pingpong + 1) % 2;
+        }
+        data.span = 1;
+        for &radix in &radices {
+            data.radix = radix;
+            self.v_stockham.compute(
+                encoder,
+                bind_groups,
+                cast_slice(&[data]),
+                &format!("V-Step Stockham {}, {}", data.span, layer),
+                [wg_size, (size / radix).div_ceil(WG_SIZE), layers],
+            );
+            data.span *= radix;
+            data.pingpong = (data.pingpong + 1) % 2;
+        }
+    }
+
+    // one workgroup per row then one per column, every stage happens in workgroup memory
+    fn shared_memory<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        pingpong0: &Texture,
+        Batch { size, layer, layers }: Batch,
+        forward: u32,
+    ) {
+        let bind_groups = &[&pingpong0.bind_group];
+        let data = SharedFFTData {
+            size,
+            layer,
+            forward,
+        };
+        self.h_shared.compute(
+            encoder,
+            bind_groups,
+            cast_slice(&[data]),
+            &format!("H-Step Shared {}", layer),
+            [size, layers, 1],
+        );
+        self.v_shared.compute(
+            encoder,
+            bind_groups,
+            cast_slice(&[data]),
+            &format!("V-Step Shared {}", layer),
+            [size, layers, 1],
+        );
+    }
+}
+
+// layers of the array transformed together
+#[derive(Clone, Copy)]
+struct Batch {
+    size: u32,
+    layer: u32,
+    layers: u32,
 }
 
 // abstraction of the pipeline is just to make code significantly nicer
@@ -153,8 +399,7 @@ impl PipelineFFT {
         bind_groups: &[&wgpu::BindGroup],
         push_constants: &[u8],
         label: &str,
-        x: u32,
-        y: u32,
+        workgroups: [u32; 3],
     ) {
         let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
             timestamp_writes: None,
@@ -165,6 +410,62 @@ impl PipelineFFT {
             pass.set_bind_group(i as _, *bind_group, &[]);
         }
         pass.set_push_constants(0, push_constants);
-        pass.dispatch_workgroups(x, y, 1);
+        let [x, y, z] = workgroups;
+        pass.dispatch_workgroups(x, y, z);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{create_shader, fallback_device};
+    use crate::sim::cpu;
+    use glam::Vec4;
+
+    // the fields are under 1 and the spectrum is normalised, so an absolute error does for both
+    // directions, the same as the cpu round trip
+    const TOLERANCE: f32 = 1e-4;
+
+    // two real fields in layer 1, so the layer offset is used too. forwards has to give the
+    // same spectrum as the cpu and back again the fields, real parts in x and y
+    fn assert_round_trips(algorithm: FftAlgorithm, size: u32) {
+        let (device, queue) = fallback_device();
+        let shader = create_shader(&device);
+        let mut fft = FourierTransform::new(&device, &shader, size, 2);
+        fft.algorithm = algorithm;
+        let pingpong0 = Texture::new_storage_array(size, size, 2, wgpu::TextureFormat::Rgba32Float, &device, "PingPong 0");
+        let field = |i: u32, seed: f32| ((i as f32 * 12.9898 + seed).sin() * 43758.547).fract();
+        let input: Vec<Vec4> = (0..size * size)
+            .map(|i| Vec4::new(field(i, 0.0), 0.0, field(i, 7.0), 0.0))
+            .collect();
+        pingpong0.write_layer(&queue, cast_slice(&input), 16, size, 1);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        fft.prepare(&device, &mut encoder, &[size]);
+        fft.fft2d(&mut encoder, &pingpong0, size, 1, 1);
+        queue.submit([encoder.finish()]);
+        let mut expected = input.clone();
+        cpu::fft::fft2d(&mut expected, size as usize);
+        for (i, (gpu, cpu)) in pingpong0.read(&device, &queue, 1).unwrap().iter().zip(&expected).enumerate() {
+            let error = (*gpu - *cpu).abs().max_element();
+            assert!(error < TOLERANCE, "{:?} at {}: forward texel {} is {}, expected {}", algorithm, size, i, gpu, cpu);
+        }
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        fft.ifft2d(&mut encoder, &pingpong0, size, 1, 1);
+        queue.submit([encoder.finish()]);
+        for (i, (out, original)) in pingpong0.read(&device, &queue, 1).unwrap().iter().zip(&input).enumerate() {
+            let error = (out.x - original.x).abs().max((out.y - original.z).abs());
+            assert!(error < TOLERANCE, "{:?} at {}: round trip texel {} is {}, expected {}", algorithm, size, i, out, original);
+        }
+    }
+
+    #[test]
+    fn gpu_fft_round_trips() {
+        for algorithm in [FftAlgorithm::CooleyTukey, FftAlgorithm::Stockham, FftAlgorithm::SharedMemory] {
+            for size in [128, 256] {
+                assert_round_trips(algorithm, size);
+            }
+        }
     }
 }
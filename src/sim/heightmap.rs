@@ -0,0 +1,31 @@
+use crate::Result;
+use glam::Vec4;
+use image::{imageops, ImageBuffer, Luma};
+
+// an artist authored heightfield, grayscale images are read as 0..1 and exr files keep their
+// values so they can be authored in metres. the image is treated as one tile of the first
+// cascade, so the lengthscale of that cascade sets its physical size
+pub struct Heightmap {
+    image: ImageBuffer<Luma<f32>, Vec<f32>>,
+}
+
+impl Heightmap {
+    pub fn load(path: &str) -> Result<Self> {
+        let image = image::open(path)?.to_luma32f();
+        if image.width() == 0 || image.height() == 0 {
+            return Err("heightmap is empty".into());
+        }
+        Ok(Self { image })
+    }
+
+    // resized to the fft resolution with the mean removed, as the k = 0 texel of the spectrum is
+    // never simulated. heights go in x so the forward fft packs the spectrum into xy
+    pub fn resample(&self, size: u32) -> Vec<Vec4> {
+        let resized = imageops::resize(&self.image, size, size, imageops::FilterType::Triangle);
+        let mean = resized.pixels().map(|p| p[0]).sum::<f32>() / (size * size) as f32;
+        resized
+            .pixels()
+            .map(|p| Vec4::new(p[0] - mean, 0.0, 0.0, 0.0))
+            .collect()
+    }
+}
@@ -0,0 +1,440 @@
+use crate::Result;
+use glam::Vec4;
+use serde::Deserialize;
+use shared::{MEASURED_DIRECTIONS, MEASURED_FREQUENCIES};
+use std::f32::consts::{PI, TAU};
+use std::fs;
+use std::path::Path;
+
+// A directional spectrum S(f, theta) loaded from recorded data. Frequencies are in Hz, directions
+// are in radians measured anticlockwise from +x in the direction the waves travel (same as the
+// wind offset), and density is in m^2 / Hz / rad stored frequency major.
+pub struct MeasuredSpectrum {
+    pub frequencies: Vec<f32>,
+    pub directions: Vec<f32>,
+    pub density: Vec<f32>,
+}
+
+// layout of the json table, densities are per degree to match the csv
+#[derive(Deserialize)]
+struct Table {
+    frequencies: Vec<f32>,
+    directions: Vec<f32>,
+    density: Vec<Vec<f32>>,
+}
+
+impl MeasuredSpectrum {
+    // picks the reader based on extension, anything that isnt a csv or json file is treated as
+    // the path to an ndbc station without extension
+    pub fn load(path: &str, record: usize) -> Result<Self> {
+        let spectrum = match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("csv") => Self::from_csv(path)?,
+            Some("json") => Self::from_json(path)?,
+            _ => Self::from_ndbc(path, record)?,
+        };
+        if spectrum.frequencies.len() < 2 || spectrum.directions.is_empty() {
+            return Err("spectrum needs at least 2 frequencies and 1 direction".into());
+        }
+        Ok(spectrum)
+    }
+
+    // reads the realtime ndbc files for a station, e.g. "data/41001" loads 41001.data_spec,
+    // 41001.swdir, 41001.swdir2, 41001.swr1 and 41001.swr2. the direction of each frequency bin is
+    // reconstructed from the first 2 fourier coefficients as
+    // D(theta) = 1 / pi * (0.5 + r1 cos(theta - alpha1) + r2 cos(2 (theta - alpha2)))
+    pub fn from_ndbc(station: &str, record: usize) -> Result<Self> {
+        let (frequencies, energy) = read_ndbc(&format!("{}.data_spec", station), record)?;
+        let (_, alpha1) = read_ndbc(&format!("{}.swdir", station), record)?;
+        let (_, alpha2) = read_ndbc(&format!("{}.swdir2", station), record)?;
+        let (_, r1) = read_ndbc(&format!("{}.swr1", station), record)?;
+        let (_, r2) = read_ndbc(&format!("{}.swr2", station), record)?;
+        Self::from_ndbc_coefficients(frequencies, &energy, [&alpha1, &alpha2, &r1, &r2])
+    }
+
+    // one record of every ndbc file, coefficients are alpha1, alpha2, r1 and r2 in that order
+    fn from_ndbc_coefficients(frequencies: Vec<f32>, energy: &[f32], coefficients: [&[f32]; 4]) -> Result<Self> {
+        let [alpha1, alpha2, r1, r2] = coefficients;
+        let bins = frequencies.len();
+        if [alpha1.len(), alpha2.len(), r1.len(), r2.len()].iter().any(|&l| l != bins) {
+            return Err("ndbc files have a different number of frequency bins".into());
+        }
+
+        let directions: Vec<f32> = (0..MEASURED_DIRECTIONS)
+            .map(|i| i as f32 * TAU / MEASURED_DIRECTIONS as f32)
+            .collect();
+        let mut density = vec![];
+        for f in 0..bins {
+            // coefficients are sometimes stored scaled by 100, 999 marks a missing value
+            let scale = |r: f32| match r {
+                r if r >= 999.0 => 0.0,
+                r if r > 1.0 => r * 0.01,
+                r => r,
+            };
+            let spread: Vec<f32> = directions
+                .iter()
+                .map(|theta| {
+                    // ndbc directions are the compass bearing the waves come from
+                    let bearing = (1.5 * PI - theta).rem_euclid(TAU);
+                    let d = 0.5
+                        + scale(r1[f]) * (bearing - alpha1[f].to_radians()).cos()
+                        + scale(r2[f]) * (2.0 * (bearing - alpha2[f].to_radians())).cos();
+                    d.max(0.0) / PI
+                })
+                .collect();
+            // clamping negative lobes breaks the normalisation so it is redone numerically
+            let sum: f32 = spread.iter().sum::<f32>() * TAU / MEASURED_DIRECTIONS as f32;
+            for d in spread {
+                density.push(if sum > 0.0 { energy[f] * d / sum } else { 0.0 });
+            }
+        }
+
+        Self::sorted(frequencies, directions, density)
+    }
+
+    // first row is the directions in degrees (first cell ignored), every other row is a
+    // frequency followed by the density for each direction in m^2 / Hz / deg
+    pub fn from_csv(path: &str) -> Result<Self> {
+        Self::parse_csv(&fs::read_to_string(path)?)
+    }
+
+    fn parse_csv(text: &str) -> Result<Self> {
+        let mut rows = text.lines().filter(|l| !l.trim().is_empty());
+        let header = rows.next().ok_or("empty csv")?;
+        let directions = header
+            .split(',')
+            .skip(1)
+            .map(|c| c.trim().parse::<f32>().map(f32::to_radians))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut frequencies = vec![];
+        let mut density = vec![];
+        for row in rows {
+            let cells = row
+                .split(',')
+                .map(|c| c.trim().parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()?;
+            if cells.len() != directions.len() + 1 {
+                return Err(format!("csv row has {} cells, expected {}", cells.len(), directions.len() + 1).into());
+            }
+            frequencies.push(cells[0]);
+            density.extend(cells[1..].iter().map(|d| d * 180.0 / PI));
+        }
+        Self::sorted(frequencies, directions, density)
+    }
+
+    pub fn from_json(path: &str) -> Result<Self> {
+        Self::parse_json(&fs::read_to_string(path)?)
+    }
+
+    fn parse_json(text: &str) -> Result<Self> {
+        let table: Table = serde_json::from_str(text)?;
+        if table.density.len() != table.frequencies.len()
+            || table.density.iter().any(|row| row.len() != table.directions.len())
+        {
+            return Err("json density table doesnt match frequencies and directions".into());
+        }
+        let directions = table.directions.iter().map(|d| d.to_radians()).collect();
+        let density = table.density.concat().iter().map(|d| d * 180.0 / PI).collect();
+        Self::sorted(table.frequencies, directions, density)
+    }
+
+    // tables can list frequencies in any order and directions in any order or range, sort them
+    // into increasing frequencies and directions in [0, 2pi) so the resampling can search and
+    // interpolate between neighbours. two rows at the same frequency would divide by zero there,
+    // so they are an error along with anything that isnt a number
+    fn sorted(frequencies: Vec<f32>, directions: Vec<f32>, density: Vec<f32>) -> Result<Self> {
+        if frequencies.iter().chain(&directions).any(|x| !x.is_finite()) {
+            return Err("spectrum has a frequency or direction that isnt a number".into());
+        }
+        let dirs = directions.len();
+        let mut rows: Vec<usize> = (0..frequencies.len()).collect();
+        rows.sort_by(|a, b| frequencies[*a].total_cmp(&frequencies[*b]));
+        if let Some(pair) = rows.windows(2).find(|pair| frequencies[pair[0]] == frequencies[pair[1]]) {
+            return Err(format!("spectrum has frequency {} twice", frequencies[pair[0]]).into());
+        }
+        let mut order: Vec<usize> = (0..dirs).collect();
+        let wrapped: Vec<f32> = directions.iter().map(|d| d.rem_euclid(TAU)).collect();
+        order.sort_by(|a, b| wrapped[*a].total_cmp(&wrapped[*b]));
+
+        let mut sorted = Vec::with_capacity(density.len());
+        for &f in &rows {
+            sorted.extend(order.iter().map(|&i| density[f * dirs + i]));
+        }
+        Ok(Self {
+            frequencies: rows.iter().map(|&f| frequencies[f]).collect(),
+            directions: order.iter().map(|&i| wrapped[i]).collect(),
+            density: sorted,
+        })
+    }
+
+    pub fn frequency_range(&self) -> (f32, f32) {
+        (self.frequencies[0], self.frequencies[self.frequencies.len() - 1])
+    }
+
+    // resamples onto the fixed MEASURED_FREQUENCIES x MEASURED_DIRECTIONS grid used by the
+    // initial spectrum shader, frequencies are spread evenly over the measured range and
+    // directions evenly over [0, 2pi)
+    pub fn resample(&self) -> Vec<Vec4> {
+        let (f_min, f_max) = self.frequency_range();
+        let mut data = vec![];
+        for row in 0..MEASURED_FREQUENCIES {
+            let f = f_min + (f_max - f_min) * row as f32 / (MEASURED_FREQUENCIES - 1) as f32;
+            for col in 0..MEASURED_DIRECTIONS {
+                let theta = col as f32 * TAU / MEASURED_DIRECTIONS as f32;
+                data.push(Vec4::new(self.sample(f, theta), 0.0, 0.0, 1.0));
+            }
+        }
+        data
+    }
+
+    // linear in frequency, linear and periodic in direction
+    fn sample(&self, f: f32, theta: f32) -> f32 {
+        let dirs = self.directions.len();
+        let upper = self.frequencies.partition_point(|&x| x < f).clamp(1, self.frequencies.len() - 1);
+        let (f0, f1) = (self.frequencies[upper - 1], self.frequencies[upper]);
+        let tf = ((f - f0) / (f1 - f0)).clamp(0.0, 1.0);
+
+        let next = self.directions.partition_point(|&x| x <= theta) % dirs;
+        let prev = (next + dirs - 1) % dirs;
+        let span = (self.directions[next] - self.directions[prev]).rem_euclid(TAU);
+        let td = if span > 0.0 {
+            (theta - self.directions[prev]).rem_euclid(TAU) / span
+        } else {
+            0.0
+        };
+
+        let at = |row: usize, col: usize| self.density[row * dirs + col];
+        let lerp = |a: f32, b: f32, t: f32| a * (1.0 - t) + b * t;
+        lerp(
+            lerp(at(upper - 1, prev), at(upper - 1, next), td),
+            lerp(at(upper, prev), at(upper, next), td),
+            tf,
+        )
+    }
+}
+
+fn read_ndbc(path: &str, record: usize) -> Result<(Vec<f32>, Vec<f32>)> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse_ndbc(&text, record).map_err(|e| format!("{}: {}", path, e).into())
+}
+
+// one record of an ndbc spectral file, returning the frequency bins and values. handles both
+// the historical layout (frequencies in the header) and the realtime layout where every value is
+// followed by its frequency in brackets
+fn parse_ndbc(text: &str, record: usize) -> Result<(Vec<f32>, Vec<f32>)> {
+    let mut header_frequencies = vec![];
+    let mut records = vec![];
+    for line in text.lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.len() <= 5 {
+            continue;
+        }
+        if tokens[0].starts_with('#') || tokens[0].starts_with("YY") {
+            let parsed: Vec<f32> = tokens[5..].iter().filter_map(|t| t.parse().ok()).collect();
+            if parsed.len() == tokens.len() - 5 {
+                header_frequencies = parsed;
+            }
+            continue;
+        }
+        records.push(tokens);
+    }
+    let tokens = records
+        .get(record)
+        .ok_or(format!("record {} not found", record))?;
+
+    if tokens.iter().any(|t| t.starts_with('(')) {
+        let mut frequencies = vec![];
+        let mut values = vec![];
+        for pair in tokens.windows(2) {
+            if pair[1].starts_with('(') {
+                values.push(pair[0].parse()?);
+                frequencies.push(pair[1].trim_matches(|c| c == '(' || c == ')').parse()?);
+            }
+        }
+        Ok((frequencies, values))
+    } else {
+        let values = tokens[5..].iter().map(|t| t.parse()).collect::<Result<Vec<f32>, _>>()?;
+        if values.len() != header_frequencies.len() {
+            return Err("missing frequency header".into());
+        }
+        Ok((header_frequencies, values))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCE: f32 = 1e-4;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() <= TOLERANCE * b.abs().max(1.0)
+    }
+
+    // direction of the column with the most energy at frequency row f
+    fn peak_direction(spectrum: &MeasuredSpectrum, f: usize) -> f32 {
+        let dirs = spectrum.directions.len();
+        let row = &spectrum.density[f * dirs..(f + 1) * dirs];
+        let peak = (0..dirs).max_by(|a, b| row[*a].total_cmp(&row[*b])).unwrap();
+        spectrum.directions[peak]
+    }
+
+    #[test]
+    fn csv_sorts_directions_and_converts_per_degree() {
+        let spectrum = MeasuredSpectrum::parse_csv(
+            "f, 270, 90, 0, 180\n\
+             0.1, 4, 2, 1, 3\n\
+             \n\
+             0.2, 8, 6, 5, 7\n",
+        )
+        .unwrap();
+        assert_eq!(spectrum.frequencies, vec![0.1, 0.2]);
+        let expected = [0.0, 0.5 * PI, PI, 1.5 * PI];
+        assert!(spectrum.directions.iter().zip(expected).all(|(a, b)| close(*a, b)));
+        let per_degree = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0];
+        assert!(spectrum.density.iter().zip(per_degree).all(|(a, b)| close(*a, b * 180.0 / PI)));
+    }
+
+    #[test]
+    fn csv_sorts_frequencies() {
+        let spectrum = MeasuredSpectrum::parse_csv(
+            "f, 0, 90\n\
+             0.3, 5, 6\n\
+             0.1, 1, 2\n\
+             0.2, 3, 4\n",
+        )
+        .unwrap();
+        assert_eq!(spectrum.frequencies, vec![0.1, 0.2, 0.3]);
+        let per_degree = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        assert!(spectrum.density.iter().zip(per_degree).all(|(a, b)| close(*a, b * 180.0 / PI)));
+    }
+
+    #[test]
+    fn csv_rejects_duplicate_frequencies() {
+        assert!(MeasuredSpectrum::parse_csv("f, 0, 90\n0.2, 1, 2\n0.1, 3, 4\n0.2, 5, 6\n").is_err());
+    }
+
+    #[test]
+    fn csv_rejects_nan_frequencies() {
+        assert!(MeasuredSpectrum::parse_csv("f, 0, 90\n0.1, 1, 2\nNaN, 3, 4\n").is_err());
+    }
+
+    #[test]
+    fn json_rejects_duplicate_frequencies() {
+        let text = r#"{"frequencies": [0.1, 0.1], "directions": [0], "density": [[1], [2]]}"#;
+        assert!(MeasuredSpectrum::parse_json(text).is_err());
+    }
+
+    #[test]
+    fn csv_rejects_short_rows() {
+        assert!(MeasuredSpectrum::parse_csv("f, 0, 90\n0.1, 1\n").is_err());
+    }
+
+    #[test]
+    fn json_wraps_negative_directions() {
+        let spectrum = MeasuredSpectrum::parse_json(
+            r#"{"frequencies": [0.1, 0.3], "directions": [-90, 0], "density": [[1, 2], [3, 4]]}"#,
+        )
+        .unwrap();
+        assert!(close(spectrum.directions[0], 0.0) && close(spectrum.directions[1], 1.5 * PI));
+        let per_degree = [2.0, 1.0, 4.0, 3.0];
+        assert!(spectrum.density.iter().zip(per_degree).all(|(a, b)| close(*a, b * 180.0 / PI)));
+    }
+
+    #[test]
+    fn json_rejects_mismatched_table() {
+        let text = r#"{"frequencies": [0.1, 0.3], "directions": [0, 90], "density": [[1, 2]]}"#;
+        assert!(MeasuredSpectrum::parse_json(text).is_err());
+    }
+
+    #[test]
+    fn ndbc_realtime_layout() {
+        let text = "#YY  MM DD hh mm Sep_Freq  < spec_1 (freq_1) spec_2 (freq_2) >\n\
+                    2024 01 15 12 00 9.999 0.100 (0.033) 0.200 (0.038)\n\
+                    2024 01 15 11 00 0.120 0.300 (0.033) 0.400 (0.038)\n";
+        let (frequencies, values) = parse_ndbc(text, 1).unwrap();
+        assert_eq!(frequencies, vec![0.033, 0.038]);
+        assert_eq!(values, vec![0.3, 0.4]);
+        assert!(parse_ndbc(text, 2).is_err());
+    }
+
+    #[test]
+    fn ndbc_historical_layout() {
+        let text = "#YY  MM DD hh mm  .0200  .0325  .0375\n\
+                    2023 06 01 00 00   0.00   1.50   2.25\n";
+        let (frequencies, values) = parse_ndbc(text, 0).unwrap();
+        assert_eq!(frequencies, vec![0.02, 0.0325, 0.0375]);
+        assert_eq!(values, vec![0.0, 1.5, 2.25]);
+        assert!(parse_ndbc("2023 06 01 00 00 0.00 1.50\n", 0).is_err());
+    }
+
+    // waves from the west travel along +x, waves from the north along -z
+    #[test]
+    fn ndbc_direction_is_where_waves_travel() {
+        let spectrum = MeasuredSpectrum::from_ndbc_coefficients(
+            vec![0.1, 0.2],
+            &[1.0, 1.0],
+            [&[270.0, 0.0], &[270.0, 0.0], &[0.8, 0.8], &[0.5, 0.5]],
+        )
+        .unwrap();
+        assert!(close(peak_direction(&spectrum, 0), 0.0));
+        assert!(close(peak_direction(&spectrum, 1), 1.5 * PI));
+    }
+
+    // 999 is missing so the bin is spread evenly, and r stored as a percentage is scaled back
+    #[test]
+    fn ndbc_missing_and_percentage_coefficients() {
+        let percent = MeasuredSpectrum::from_ndbc_coefficients(
+            vec![0.1, 0.2],
+            &[2.0, 2.0],
+            [&[90.0, 90.0], &[90.0, 90.0], &[999.0, 80.0], &[999.0, 50.0]],
+        )
+        .unwrap();
+        let fraction = MeasuredSpectrum::from_ndbc_coefficients(
+            vec![0.1, 0.2],
+            &[2.0, 2.0],
+            [&[90.0, 90.0], &[90.0, 90.0], &[0.0, 0.8], &[0.0, 0.5]],
+        )
+        .unwrap();
+        let dirs = MEASURED_DIRECTIONS as usize;
+        assert!(percent.density[..dirs].iter().all(|d| close(*d, 2.0 / TAU)));
+        assert!(percent.density.iter().zip(&fraction.density).all(|(a, b)| close(*a, *b)));
+    }
+
+    // each frequency bin integrates over direction back to the energy of the bin
+    #[test]
+    fn ndbc_spread_keeps_energy() {
+        let energy = [0.5, 3.0];
+        let spectrum = MeasuredSpectrum::from_ndbc_coefficients(
+            vec![0.1, 0.2],
+            &energy,
+            [&[45.0, 200.0], &[10.0, 180.0], &[0.9, 0.3], &[0.9, 0.6]],
+        )
+        .unwrap();
+        let dirs = MEASURED_DIRECTIONS as usize;
+        for (f, energy) in energy.iter().enumerate() {
+            let sum: f32 = spectrum.density[f * dirs..(f + 1) * dirs].iter().sum();
+            assert!(close(sum * TAU / MEASURED_DIRECTIONS as f32, *energy));
+        }
+    }
+
+    #[test]
+    fn ndbc_rejects_mismatched_bins() {
+        let coefficients: [&[f32]; 4] = [&[0.0], &[0.0, 0.0], &[0.0, 0.0], &[0.0, 0.0]];
+        assert!(MeasuredSpectrum::from_ndbc_coefficients(vec![0.1, 0.2], &[1.0, 1.0], coefficients).is_err());
+    }
+
+    // linear between frequencies, periodic between the last direction and the first
+    #[test]
+    fn resample_interpolates() {
+        let spectrum = MeasuredSpectrum::sorted(vec![0.1, 0.3], vec![0.0, PI], vec![1.0, 3.0, 5.0, 7.0]).unwrap();
+        let data = spectrum.resample();
+        let dirs = MEASURED_DIRECTIONS as usize;
+        let at = |row: usize, degrees: usize| data[row * dirs + degrees * dirs / 360].x;
+        assert_eq!(data.len(), MEASURED_FREQUENCIES as usize * dirs);
+        assert!(close(at(0, 0), 1.0) && close(at(0, 180), 3.0));
+        assert!(close(at(0, 90), 2.0) && close(at(0, 270), 2.0));
+        let last = MEASURED_FREQUENCIES as usize - 1;
+        assert!(close(at(last, 0), 5.0) && close(at(last, 270), 6.0));
+    }
+}
@@ -1,27 +1,64 @@
 use super::sim::compute::ComputePass;
 use super::sim::fft::FourierTransform;
 use simdata::SimData;
-use crate::cast_slice;
+use crate::{cast_slice, WG_SIZE};
+use std::mem;
+use glam::{Vec2, Vec4};
 use crate::engine::scene::Scene;
-use cascade::Cascade;
+use cascade::{Cascade, CascadeMaps, DELTA_CHANNELS, MOTION_CHANNELS};
+use cpu::CpuSimulation;
+use heightmap::Heightmap;
+use query::SurfaceQuery;
+use bodies::Bodies;
+use wakes::Wakes;
+use ripples::Ripples;
+use bathymetry::Bathymetry;
+use breaking::BreakingDetector;
+use spray::Spray;
+use statistics::{RealisedCascade, SeaStateReport};
+use shared::SurfaceSample;
 
 pub mod compute;
 pub mod fft;
 pub mod cascade;
 pub mod simdata;
+pub mod measured;
+pub mod heightmap;
+pub mod query;
+pub mod bodies;
+pub mod wakes;
+pub mod ripples;
+pub mod bathymetry;
+pub mod breaking;
+pub mod spray;
+pub mod statistics;
+pub mod cpu;
 
 
 pub struct Simulation {
     pub simdata: SimData,
-    pub cascade0: Cascade,
-    pub cascade1: Cascade,
-    pub cascade2: Cascade,
-    pub butterfly_precompute_pass: ComputePass,
+    pub cascades: Vec<Cascade>,
+    pub maps: CascadeMaps,
     pub initial_spectra_pass: ComputePass,
     pub conjugates_pass: ComputePass,
     pub evolve_spectra_pass: ComputePass,
     pub process_deltas_pass: ComputePass,
     pub fft: FourierTransform,
+    pub surface_query: SurfaceQuery,
+    pub bodies: Bodies,
+    pub wakes: Wakes,
+    pub ripples: Ripples,
+    pub bathymetry: Bathymetry,
+    pub breaking: BreakingDetector,
+    pub spray: Spray,
+    // the surface under the probe as of the last collected query
+    pub probe_sample: Option<SurfaceSample>,
+    // how many of the positions in the query in flight are body samples, the probe comes after
+    queried_samples: usize,
+    // deltatime since the bodies were last stepped
+    unstepped: f32,
+    // only created while the cpu backend is selected
+    pub cpu: Option<CpuSimulation>,
 }
 
 impl Simulation {
@@ -31,49 +68,34 @@ impl Simulation {
         shader: &wgpu::ShaderModule,
         scene: &Scene,
     ) -> Self {
-        let simdata = SimData::new(device, &scene.consts);
-        
-        let cascade0 = Cascade::new(device, &scene.consts, "0");
-        let cascade1 = Cascade::new(device, &scene.consts, "1");
-        let cascade2 = Cascade::new(device, &scene.consts, "2");
+        let simdata = SimData::new(device, &scene.consts, Self::max_size(scene));
+
+        let (maps, cascades) = Self::create_cascades(device, scene);
+        // layouts are identical for every cascade
+        let cascade_layout = &cascades[0].layout;
 
         let push_constant_ranges = &[wgpu::PushConstantRange {
             stages: wgpu::ShaderStages::COMPUTE,
             range: 0..std::mem::size_of::<u32>() as u32,
         }];
         let initial_spectra_pass = ComputePass::new(
-            &[&scene.consts_layout, &simdata.layout, &cascade0.layout],
+            &[&scene.consts_layout, &simdata.layout, cascade_layout],
             push_constant_ranges,
             device,
             shader,
             "Initial Spectra",
             "sim::initial_spectra::main",
         );
-        let butterfly_precompute_pass = ComputePass::new(
-            &[&scene.consts_layout, &simdata.layout],
-            &[],
-            device,
-            shader,
-            "Precompute Butterfly",
-            "sim::fft::precompute_butterfly",
-        );
         let conjugates_pass = ComputePass::new(
-            &[&scene.consts_layout, &simdata.layout, &cascade0.layout],
-            &[],
+            &[&scene.consts_layout, &simdata.layout, cascade_layout],
+            push_constant_ranges,
             device,
             shader,
             "Pack Conjugates",
             "sim::initial_spectra::pack_conjugates",
         );
         let evolve_spectra_pass = ComputePass::new(
-            &[
-                &scene.consts_layout,
-                &cascade0.layout,
-                &cascade0.h_displacement.layout,
-                &cascade0.v_displacement.layout,
-                &cascade0.h_slope.layout,
-                &cascade0.jacobian.layout,
-            ],
+            &[&scene.consts_layout, cascade_layout, &cascades[0].deltas_layout],
             &[],
             device,
             shader,
@@ -81,105 +103,291 @@ impl Simulation {
             "sim::evolve_spectra::main",
         );
         let process_deltas_pass = ComputePass::new(
-            &[
-                &scene.consts_layout,
-                &cascade0.h_displacement.layout,
-                &cascade0.v_displacement.layout,
-                &cascade0.h_slope.layout,
-                &cascade0.jacobian.layout,
-                &cascade0.layout,
-            ],
-            &[],
+            &[&scene.consts_layout, &cascades[0].deltas_layout, cascade_layout],
+            push_constant_ranges,
             device,
             shader,
             "Process Deltas",
             "sim::process_deltas::main",
         );
-        let fft = FourierTransform::new(device, shader, scene, &simdata);
+        let bathymetry = Bathymetry::new(device);
+        let ripples = Ripples::new(device);
+        let wakes = Wakes::new(device, &ripples.buffer);
+        let surface_query = SurfaceQuery::new(
+            device,
+            shader,
+            &scene.consts_layout,
+            &maps.layout,
+            &bathymetry.texture.layout,
+            &wakes.layout,
+        );
+        let breaking = BreakingDetector::new(device, shader, &scene.consts_layout, &maps.layout);
+        let spray = Spray::new(device, shader, &scene.consts_layout, &maps.layout, &bathymetry.texture.layout);
+        let bodies = Bodies::new(device);
+        let mut fft = FourierTransform::new(device, shader, Self::max_size(scene), Self::layers(scene));
 
-        simdata
-            .gaussian_tex
-            .write(queue, cast_slice(&simdata.gaussian_noise.clone()), 16);
+        simdata.upload(queue);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        fft.prepare(device, &mut encoder, &Self::sizes(scene));
+        queue.submit([encoder.finish()]);
 
         Self {
-            cascade0,
-            cascade1,
-            cascade2,
+            cascades,
+            maps,
             simdata,
             initial_spectra_pass,
-            butterfly_precompute_pass,
             conjugates_pass,
             evolve_spectra_pass,
             process_deltas_pass,
             fft,
+            surface_query,
+            bodies,
+            wakes,
+            ripples,
+            bathymetry,
+            breaking,
+            spray,
+            probe_sample: None,
+            queried_samples: 0,
+            unstepped: 0.0,
+            cpu: None,
         }
     }
-    pub fn compute_cascade<'a>(
-        &'a self,
-        encoder: &'a mut wgpu::CommandEncoder,
-        cascade: &Cascade,
-        scene: &mut Scene,
-        workgroup_size: u32,
-        index: u32,
-    ) {
-        self.evolve_spectra_pass.compute(
-            encoder,
-            "Evolve Spectra",
-            &[
-                &scene.consts_bind_group,
-                &cascade.bind_group,
-                &cascade.h_displacement.bind_group,
-                &cascade.v_displacement.bind_group,
-                &cascade.h_slope.bind_group,
-                &cascade.jacobian.bind_group,
-            ],
-            workgroup_size,
-            workgroup_size,
-        );
-        self.fft.ifft2d(
-            encoder,
-            scene,
-            &self.simdata,
-            &cascade.h_displacement,
-            index,
-        );
-        self.fft.ifft2d(
-            encoder,
-            scene,
-            &self.simdata,
-            &cascade.v_displacement,
-            index,
-        );
-        self.fft.ifft2d(
-            encoder,
-            scene,
-            &self.simdata,
-            &cascade.h_slope,
-            index,
-        );
-        self.fft.ifft2d(
-            encoder,
-            scene,
-            &self.simdata,
-            &cascade.jacobian,
-            index,
-        );
 
-        self.process_deltas_pass.compute(
-            encoder,
-            "Process Deltas",
-            &[
-                &scene.consts_bind_group,
-                &cascade.h_displacement.bind_group,
-                &cascade.v_displacement.bind_group,
-                &cascade.h_slope.bind_group,
-                &cascade.jacobian.bind_group,
-                &cascade.bind_group,
-            ],
-            workgroup_size,
-            workgroup_size,
+    fn sizes(scene: &Scene) -> Vec<u32> {
+        scene.cascades.iter().map(|c| c.size).collect()
+    }
+
+    fn layers(scene: &Scene) -> u32 {
+        scene.cascades.len() as u32 * DELTA_CHANNELS
+    }
+
+    fn max_size(scene: &Scene) -> u32 {
+        Self::sizes(scene).into_iter().max().unwrap_or(scene.consts.sim.size)
+    }
+
+    fn create_cascades(device: &wgpu::Device, scene: &Scene) -> (CascadeMaps, Vec<Cascade>) {
+        let count = scene.cascades.len() as u32;
+        let maps = CascadeMaps::new(device, Self::max_size(scene), count);
+        let cascades = scene
+            .cascades
+            .iter()
+            .enumerate()
+            .map(|(i, params)| Cascade::new(device, params.size, i as u32, &maps))
+            .collect();
+        (maps, cascades)
+    }
+
+    // whether cascades have been added, removed or resized since the textures were created
+    pub fn outdated(&self, scene: &Scene) -> bool {
+        self.cascades.iter().map(|c| c.size).ne(Self::sizes(scene))
+    }
+
+    // called when cascades are added, removed or resized. pipelines dont depend on the number or
+    // size of cascades so only the textures and bind groups are recreated
+    pub fn rebuild_cascades(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene) {
+        let size = Self::max_size(scene);
+        let heightmap = self.simdata.heightmap.take();
+        if size != self.simdata.size {
+            let measured = mem::take(&mut self.simdata.measured);
+            self.simdata = SimData::new(device, &scene.consts, size);
+            self.simdata.measured = measured;
+            self.simdata.upload(queue);
+        }
+        self.fft.resize(device, size, Self::layers(scene));
+        (self.maps, self.cascades) = Self::create_cascades(device, scene);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        self.fft.prepare(device, &mut encoder, &Self::sizes(scene));
+        queue.submit([encoder.finish()]);
+        // the first cascade may have been resized, so the spectrum is always redone
+        if heightmap.is_some() {
+            self.set_heightmap(device, queue, heightmap);
+        }
+
+        if self.cpu.is_some() {
+            self.cpu = Some(CpuSimulation::new(&scene.cascades));
+        }
+    }
+
+    // the heightmap is one tile of the first cascade, so it is transformed at that resolution and
+    // initial_spectra reads the spectrum back texel for texel
+    pub fn set_heightmap(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, heightmap: Option<Heightmap>) {
+        let size = self.cascades.first().map_or(self.simdata.size, |c| c.size);
+        self.simdata.write_heightmap(queue, heightmap, size);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        self.fft.fft2d(&mut encoder, &self.simdata.heightmap_tex, size, 0, 1);
+        queue.submit([encoder.finish()]);
+    }
+
+    // evolves every cascade, then all of their deltas go through the fft together. cascades of
+    // the same size next to each other share one batch, so normally its a single fft chain
+    pub fn compute<'a>(&'a self, encoder: &'a mut wgpu::CommandEncoder, scene: &Scene) {
+        for cascade in &self.cascades {
+            self.evolve_spectra_pass.compute(
+                encoder,
+                "Evolve Spectra",
+                &[
+                    &scene.consts_bind_group,
+                    &cascade.bind_group,
+                    &cascade.deltas_bind_group,
+                ],
+                cascade.size / WG_SIZE,
+                cascade.size / WG_SIZE,
+            );
+        }
+
+        let mut layer = 0;
+        for batch in self.cascades.chunk_by(|a, b| a.size == b.size) {
+            let layers = batch.len() as u32 * DELTA_CHANNELS;
+            self.fft.ifft2d(encoder, &self.maps.deltas, batch[0].size, layer, layers);
+            layer += layers;
+        }
+        // fewer layers than the deltas, so the fft is already big enough
+        if scene.consts.sim.spectral_motion != 0 {
+            let mut layer = 0;
+            for batch in self.cascades.chunk_by(|a, b| a.size == b.size) {
+                let layers = batch.len() as u32 * MOTION_CHANNELS;
+                self.fft.ifft2d(encoder, &self.maps.motion_deltas, batch[0].size, layer, layers);
+                layer += layers;
+            }
+        }
+
+        let foam = &self.maps.foam_map.texture;
+        encoder.copy_texture_to_texture(
+            foam.as_image_copy(),
+            self.maps.foam_history.texture.as_image_copy(),
+            foam.size(),
         );
+        for (i, cascade) in self.cascades.iter().enumerate() {
+            self.process_deltas_pass.compute_with_constants(
+                encoder,
+                "Process Deltas",
+                &[
+                    &scene.consts_bind_group,
+                    &cascade.deltas_bind_group,
+                    &cascade.bind_group,
+                ],
+                cast_slice(&[i as u32]),
+                cascade.size / WG_SIZE,
+                cascade.size / WG_SIZE,
+            );
+        }
+    }
+
+    // after the maps are written by either backend
+    pub fn compute_spray(&self, encoder: &mut wgpu::CommandEncoder, scene: &Scene) {
+        self.spray.compute(encoder, scene, &self.maps, &self.bathymetry.texture.bind_group);
+    }
+
+    // reads the water under every sample of every floating body and steps them, called once the
+    // frame is submitted so the maps are complete. the query made here is collected next frame,
+    // so bodies integrate against the surface as it was a frame ago, the same as when it was read
+    // straight back. their trails and splashes then become the wakes and ripples drawn and queried
+    // next frame, so bodies ride on each others wakes and bob in the rings they make.
+    // the probe rides along at the end of the same query, its sample is left in probe_sample
+    pub fn step_bodies(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene, probe: Option<Vec2>) {
+        let consts = &scene.consts;
+        // time passed while waiting on the gpu isnt lost, the next step covers it
+        self.unstepped += consts.deltatime;
+        if let Some(surface) = self.surface_query.collect(device) {
+            let (bodies, probe) = surface.split_at(self.queried_samples);
+            self.probe_sample = probe.first().copied();
+            // bodies spawned or cleared since the query was made wait for the next one
+            if !self.bodies.bodies.is_empty() && bodies.len() == self.bodies.sample_count() {
+                let splashes = self.bodies.step(bodies, self.unstepped, consts.time, consts.sim.gravity);
+                for splash in splashes {
+                    let foam = consts.sim.impact_foam;
+                    self.ripples.impact(splash.position, splash.radius, splash.depth, foam, consts.time);
+                }
+                self.bodies.upload(queue);
+            }
+            self.unstepped = 0.0;
+        }
+        if probe.is_none() {
+            self.probe_sample = None;
+        }
+
+        let mut positions = self.bodies.sample_positions();
+        let samples = positions.len();
+        positions.extend(probe);
+        if !positions.is_empty() && self.surface_query.is_idle() {
+            self.surface_query.request(
+                device,
+                queue,
+                scene,
+                &self.maps,
+                [&self.bathymetry.texture.bind_group, &self.wakes.bind_group],
+                &positions,
+            );
+            self.queried_samples = samples;
+        }
+        if positions.is_empty() {
+            self.unstepped = 0.0;
+        }
+        // still written with no bodies so clearing them clears their wakes
+        self.wakes.upload(queue, &self.bodies, consts.time);
+        self.ripples.upload(queue, consts.time);
+    }
+
+    // starts finding the breaking regions of the last submitted frame. they land in
+    // breaking.events once the gpu is done, a frame or so later
+    pub fn detect_breaking(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene) {
+        self.breaking.detect(device, queue, scene, &self.maps);
+    }
+
+    // sea state statistics of the spectrum that was asked for against those of the surface as of
+    // the last submitted frame. reads every cascade back so its only run on request
+    pub fn sea_state(&self, device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene) -> Result<SeaStateReport, wgpu::BufferAsyncError> {
+        let spectra = self
+            .cascades
+            .iter()
+            .map(|cascade| cascade.initial_spectrum.read(device, queue, 0))
+            .collect::<Result<Vec<_>, _>>()?;
+        let realised = self
+            .cascades
+            .iter()
+            .enumerate()
+            .map(|(i, cascade)| {
+                // layers are sized for the largest cascade, the same as compare
+                let layer = i as u32;
+                let size = cascade.size as usize;
+                let crop = |texels: Vec<Vec4>| -> Vec<Vec4> {
+                    texels
+                        .chunks(self.simdata.size as usize)
+                        .take(size)
+                        .flat_map(|row| &row[..size])
+                        .copied()
+                        .collect()
+                };
+                Ok(RealisedCascade {
+                    displacement: crop(self.maps.displacement_map.read(device, queue, layer)?),
+                    velocity: crop(self.maps.velocity_map.read(device, queue, layer)?),
+                    acceleration: crop(self.maps.acceleration_map.read(device, queue, layer)?),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(SeaStateReport {
+            time: scene.consts.time,
+            spectral: statistics::spectral(&spectra, &scene.cascades, &scene.consts.sim),
+            realised: statistics::realised(&realised, &scene.cascades, &scene.consts.sim),
+        })
     }
+
+    // the initial spectrum of every cascade, rerun whenever the constants change
+    pub fn compute_initial_spectra(&self, encoder: &mut wgpu::CommandEncoder, scene: &Scene) {
+        for (i, cascade) in self.cascades.iter().enumerate() {
+            self.compute_initial(
+                encoder,
+                &[&scene.consts_bind_group, &self.simdata.bind_group, &cascade.bind_group],
+                i as u32,
+                cascade.size / WG_SIZE,
+                cascade.size / WG_SIZE,
+            );
+        }
+    }
+
     // creating a proper abstraction for a compute pass would just involve recreating a
     // computepipeline struct from scratch, so instead as this isnt a true "engine" i have just
     // special cased a computepass that requires push constants
@@ -213,6 +421,115 @@ impl Simulation {
         for (i, bind_group) in bind_groups.iter().enumerate() {
             pass.set_bind_group(i as _, *bind_group, &[]);
         }
+        pass.set_push_constants(0, cast_slice(&[pc]));
         pass.dispatch_workgroups(x, y, 1);
     }
+
+    // runs the cpu backend from scratch with the current constants and reads back what the gpu
+    // produced, returning the largest absolute difference in the displacement and normal maps of
+    // each cascade. foam accumulates over frames so it cant be compared without sharing history
+    pub fn compare(&self, device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene) -> Result<Vec<(f32, f32)>, wgpu::BufferAsyncError> {
+        let mut cpu = CpuSimulation::new(&scene.cascades);
+        cpu.compute_initial(&scene.consts, &scene.cascades, &self.simdata);
+        cpu.compute(&scene.consts, &scene.cascades);
+
+        let max_diff = |a: &[Vec4], b: &[Vec4]| {
+            a.iter()
+                .zip(b)
+                .map(|(a, b)| (*a - *b).abs().max_element())
+                .fold(0.0, f32::max)
+        };
+        cpu.cascades
+            .iter()
+            .enumerate()
+            .map(|(i, cpu)| {
+                // layers are sized for the largest cascade so only the top left is compared
+                let layer = i as u32;
+                let crop = |texels: Vec<Vec4>| -> Vec<Vec4> {
+                    texels
+                        .chunks(self.simdata.size as usize)
+                        .take(cpu.size)
+                        .flat_map(|row| &row[..cpu.size])
+                        .copied()
+                        .collect()
+                };
+                Ok((
+                    max_diff(&cpu.displacement, &crop(self.maps.displacement_map.read(device, queue, layer)?)),
+                    max_diff(&cpu.normal, &crop(self.maps.normal_map.read(device, queue, layer)?)),
+                ))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{create_shader, fallback_device};
+    use fft::FftAlgorithm;
+    use shared::MAX_SHARED_FFT_SIZE;
+
+    // largest difference allowed in any displacement (m) or normal component between the
+    // backends. both are f32 but the gpu fft sums in a different order and its sin and cos are
+    // less exact, which shows most in the long waves of the first cascade
+    const TOLERANCE: f32 = 1e-3;
+
+    // runs the whole gpu pipeline with every cascade at size and compares it against the cpu
+    // backend from the same constants
+    fn assert_backends_match(algorithm: FftAlgorithm, size: u32) {
+        let (device, queue) = fallback_device();
+        let shader = create_shader(&device);
+        let mut scene = Scene::new(&device, 1.0);
+        scene.set_resolution(size);
+        for cascade in &mut scene.cascades {
+            cascade.size = size;
+        }
+        // what Scene::update_redraw would fill in, some way into the simulation so every wave
+        // has moved
+        scene.consts.time = 12.5;
+        scene.consts.deltatime = 1.0 / 60.0;
+        scene.consts.sim.cascade_count = scene.cascades.len() as u32;
+        scene.write(&queue);
+
+        let mut simulation = Simulation::new(&device, &queue, &shader, &scene);
+        simulation.fft.algorithm = algorithm;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        simulation.compute_initial_spectra(&mut encoder, &scene);
+        simulation.compute(&mut encoder, &scene);
+        queue.submit([encoder.finish()]);
+
+        for (i, (displacement, normal)) in simulation.compare(&device, &queue, &scene).unwrap().into_iter().enumerate() {
+            assert!(
+                displacement < TOLERANCE,
+                "{:?} at {}: cascade {} displacement error {}",
+                algorithm, size, i, displacement
+            );
+            assert!(normal < TOLERANCE, "{:?} at {}: cascade {} normal error {}", algorithm, size, i, normal);
+        }
+    }
+
+    #[test]
+    fn cooley_tukey_matches_cpu() {
+        for size in [128, 256] {
+            assert_backends_match(FftAlgorithm::CooleyTukey, size);
+        }
+    }
+
+    // 128 is an odd power of two so it starts with a radix 2 stage, 256 is radix 4 throughout,
+    // between them both parities of the ping pong are checked
+    #[test]
+    fn stockham_matches_cpu() {
+        for size in [128, 256] {
+            assert_backends_match(FftAlgorithm::Stockham, size);
+        }
+    }
+
+    // every stage of a row in workgroup memory, up to the largest resolution the ui offers, which
+    // is the most that fits in one dispatch
+    #[test]
+    fn shared_memory_matches_cpu() {
+        for size in [128, 256, MAX_SHARED_FFT_SIZE as u32] {
+            assert_backends_match(FftAlgorithm::SharedMemory, size);
+        }
+    }
 }
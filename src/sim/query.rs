@@ -0,0 +1,187 @@
+use super::compute::ComputePass;
+use super::cascade::CascadeMaps;
+use crate::cast_slice;
+use crate::engine::scene::Scene;
+use crate::engine::util::Readback;
+use glam::{Vec2, Vec4};
+use shared::SurfaceSample;
+use std::mem;
+
+// threads per workgroup of sim::query::main
+const QUERY_WG_SIZE: u32 = 64;
+
+// reads the surface back at arbitrary world positions, for anything on the cpu that needs to know
+// where the water is. the buffers are kept between frames and the readback is mapped without
+// waiting, so the results of a request are collected a frame or so later instead of stalling
+// the cpu on the gpu. batch positions into one request per frame
+pub struct SurfaceQuery {
+    pass: ComputePass,
+    layout: wgpu::BindGroupLayout,
+    buffers: Option<QueryBuffers>,
+    // the readback of the request in flight
+    pending: Option<Readback>,
+}
+
+// sized for capacity positions, regrown when a request doesnt fit
+struct QueryBuffers {
+    capacity: usize,
+    positions: wgpu::Buffer,
+    samples: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl SurfaceQuery {
+    pub fn new(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        consts_layout: &wgpu::BindGroupLayout,
+        maps_layout: &wgpu::BindGroupLayout,
+        bathymetry_layout: &wgpu::BindGroupLayout,
+        wakes_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let buffer_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[buffer_entry(0, true), buffer_entry(1, false)],
+            label: Some("Surface Query Layout"),
+        });
+        let pass = ComputePass::new(
+            &[consts_layout, maps_layout, &layout, bathymetry_layout, wakes_layout],
+            &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..mem::size_of::<u32>() as u32,
+            }],
+            device,
+            shader,
+            "Surface Query",
+            "sim::query::main",
+        );
+        Self {
+            pass,
+            layout,
+            buffers: None,
+            pending: None,
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_none()
+    }
+
+    // positions are world (x, z) and sampled from the maps as they are when the queue next runs,
+    // so call this after the frame is submitted. ignored while the last request is in flight.
+    // surface is the bathymetry and wakes bind groups, which main_vs draws the water with too
+    pub fn request(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+        maps: &CascadeMaps,
+        surface: [&wgpu::BindGroup; 2],
+        positions: &[Vec2],
+    ) {
+        if positions.is_empty() || !self.is_idle() {
+            return;
+        }
+        if self.buffers.as_ref().is_none_or(|b| b.capacity < positions.len()) {
+            self.buffers = Some(QueryBuffers::new(device, &self.layout, positions.len().next_power_of_two()));
+        }
+        let buffers = self.buffers.as_ref().unwrap();
+        let positions: Vec<Vec4> = positions.iter().map(|p| Vec4::new(p.x, 0.0, p.y, 0.0)).collect();
+        queue.write_buffer(&buffers.positions, 0, cast_slice(&positions));
+
+        let count = positions.len() as u32;
+        let size = (positions.len() * mem::size_of::<SurfaceSample>()) as u64;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        self.pass.compute_with_constants(
+            &mut encoder,
+            "Surface Query",
+            &[&scene.consts_bind_group, &maps.bind_group, &buffers.bind_group, surface[0], surface[1]],
+            cast_slice(&[count]),
+            count.div_ceil(QUERY_WG_SIZE),
+            1,
+        );
+        encoder.copy_buffer_to_buffer(&buffers.samples, 0, &buffers.readback, 0, size);
+        queue.submit([encoder.finish()]);
+        self.pending = Some(Readback::start(&buffers.readback, size));
+    }
+
+    // the samples of the last request in the order the positions were given, once the gpu has
+    // finished with it. None while its still in flight or if nothing was requested
+    pub fn collect(&mut self, device: &wgpu::Device) -> Option<Vec<SurfaceSample>> {
+        let readback = &self.buffers.as_ref()?.readback;
+        let result = self.pending.as_ref()?.poll(device, readback)?;
+        self.pending = None;
+        match result {
+            Ok(floats) => Some(
+                floats
+                    .chunks(16)
+                    .map(|f| SurfaceSample {
+                        position: Vec4::from_slice(&f[0..4]),
+                        normal: Vec4::from_slice(&f[4..8]),
+                        velocity: Vec4::from_slice(&f[8..12]),
+                        acceleration: Vec4::from_slice(&f[12..16]),
+                    })
+                    .collect(),
+            ),
+            Err(e) => {
+                log::error!("failed to read back the surface query: {}", e);
+                None
+            }
+        }
+    }
+}
+
+impl QueryBuffers {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, capacity: usize) -> Self {
+        let positions = device.create_buffer(&wgpu::BufferDescriptor {
+            size: (capacity * mem::size_of::<Vec4>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+            label: Some("Query Positions"),
+        });
+        let size = (capacity * mem::size_of::<SurfaceSample>()) as u64;
+        let samples = device.create_buffer(&wgpu::BufferDescriptor {
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+            label: Some("Query Samples"),
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+            label: Some("Query Readback"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: samples.as_entire_binding(),
+                },
+            ],
+            label: Some("Surface Query"),
+        });
+        Self {
+            capacity,
+            positions,
+            samples,
+            readback,
+            bind_group,
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use crate::cast_slice;
+use glam::{Vec2, Vec4};
+use shared::{Impact, IMPACT_LIFETIME, MAX_IMPACTS};
+use std::mem;
+
+// live impacts, the rings are analytic so the gpu only needs where and when they happened.
+// drawn through the wake bind group, see Wakes
+pub struct Ripples {
+    impacts: Vec<Impact>,
+    pub buffer: wgpu::Buffer,
+}
+
+impl Ripples {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            size: (MAX_IMPACTS * mem::size_of::<Impact>()) as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            label: Some("Impacts"),
+        });
+        Self {
+            impacts: vec![],
+            buffer,
+        }
+    }
+
+    // leaves a crater of radius and depth in metres that spreads out as rings, with foam
+    // thrown up over it. the oldest impact is replaced once MAX_IMPACTS are live
+    pub fn impact(&mut self, position: Vec2, radius: f32, depth: f32, foam: f32, time: f32) {
+        if self.impacts.len() >= MAX_IMPACTS {
+            self.impacts.remove(0);
+        }
+        self.impacts.push(Impact {
+            origin: Vec4::new(position.x, position.y, time, 0.0),
+            radius: radius.max(0.01),
+            depth,
+            foam,
+            _padding: 0,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.impacts.clear();
+    }
+
+    // impacts are only dropped once they have fully faded, so scrubbing back replays them
+    pub fn upload(&mut self, queue: &wgpu::Queue, time: f32) {
+        self.impacts.retain(|impact| time - impact.origin.z <= IMPACT_LIFETIME);
+        let mut impacts = [Impact::default(); MAX_IMPACTS];
+        impacts[..self.impacts.len()].copy_from_slice(&self.impacts);
+        queue.write_buffer(&self.buffer, 0, cast_slice(&impacts));
+    }
+}
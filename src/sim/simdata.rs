@@ -1,37 +1,61 @@
 use glam::{Vec2, Vec4};
-use crate::engine::util::{bind_group_descriptor, Texture};
-use shared::Constants;
+use crate::engine::util::{array_bind_group_descriptor, bind_group_descriptor, Texture};
+use shared::{Constants, MEASURED_DIRECTIONS, MEASURED_FREQUENCIES};
+use crate::cast_slice;
+use super::heightmap::Heightmap;
+use super::measured::MeasuredSpectrum;
 
 pub struct SimData {
+    pub size: u32,
     pub gaussian_noise: Vec<Vec4>,
     pub gaussian_tex: Texture,
+    pub measured_tex: Texture,
+    // kept on the cpu as well for the cpu backend
+    pub measured: Vec<Vec4>,
+    // heights before the fft, the texture holds their spectrum once Simulation::set_heightmap runs
+    pub heightmap_tex: Texture,
+    pub heightmap: Option<Heightmap>,
+    pub heights: Vec<Vec4>,
     pub layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
 }
 
 impl SimData {
-    pub fn new(device: &wgpu::Device, consts: &Constants) -> Self {
+    // noise is generated at the size of the largest cascade, smaller cascades read the top left
+    pub fn new(device: &wgpu::Device, consts: &Constants, size: u32) -> Self {
         let gaussian_tex = Texture::new_storage(
-            consts.sim.size,
-            consts.sim.size,
+            size,
+            size,
             wgpu::TextureFormat::Rgba32Float,
             device,
             "Gaussian",
         );
-        let gaussian_noise = Self::guassian_noise(consts);
+        let gaussian_noise = Self::guassian_noise(consts, size);
 
-        let butterfly_tex = Texture::new_storage(
-            consts.sim.size.ilog2(),
-            consts.sim.size,
+        // only read when the measured spectrum model is selected
+        let measured_tex = Texture::new_storage(
+            MEASURED_DIRECTIONS,
+            MEASURED_FREQUENCIES,
             wgpu::TextureFormat::Rgba32Float,
             device,
-            "Butterfly",
+            "Measured Spectrum",
+        );
+
+        // an array so it can go through the fft, which only works on array textures
+        let heightmap_tex = Texture::new_storage_array(
+            size,
+            size,
+            1,
+            wgpu::TextureFormat::Rgba32Float,
+            device,
+            "Heightmap",
         );
 
         let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
                 bind_group_descriptor(0, wgpu::TextureFormat::Rgba32Float),
                 bind_group_descriptor(1, wgpu::TextureFormat::Rgba32Float),
+                array_bind_group_descriptor(2, wgpu::TextureFormat::Rgba32Float),
             ],
             label: Some("Sim Data Layout"),
         });
@@ -44,24 +68,56 @@ impl SimData {
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&butterfly_tex.view),
+                    resource: wgpu::BindingResource::TextureView(&measured_tex.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&heightmap_tex.view),
                 },
             ],
             label: Some("Sim Data Textures"),
         });
 
         Self {
+            size,
             gaussian_tex,
             gaussian_noise,
+            measured_tex,
+            measured: vec![Vec4::ZERO; (MEASURED_DIRECTIONS * MEASURED_FREQUENCIES) as usize],
+            heightmap_tex,
+            heightmap: None,
+            heights: vec![],
             bind_group,
             layout,
         }
     }
 
-    fn guassian_noise(consts: &Constants) -> Vec<Vec4> {
+    // textures start empty so the cpu side copies are written after creation
+    pub fn upload(&self, queue: &wgpu::Queue) {
+        self.gaussian_tex.write(queue, cast_slice(&self.gaussian_noise), 16);
+        self.measured_tex.write(queue, cast_slice(&self.measured), 16);
+    }
+
+    pub fn write_measured(&mut self, queue: &wgpu::Queue, spectrum: &MeasuredSpectrum) {
+        self.measured = spectrum.resample();
+        self.measured_tex.write(queue, cast_slice(&self.measured), 16);
+    }
+
+    // resamples to size x size in the top left of the texture, an empty heightmap is written as
+    // zeros so the stale spectrum doesnt linger
+    pub fn write_heightmap(&mut self, queue: &wgpu::Queue, heightmap: Option<Heightmap>, size: u32) {
+        self.heights = match &heightmap {
+            Some(heightmap) => heightmap.resample(size),
+            None => vec![Vec4::ZERO; (size * size) as usize],
+        };
+        self.heightmap = heightmap;
+        self.heightmap_tex.write_layer(queue, cast_slice(&self.heights), 16, size, 0);
+    }
+
+    fn guassian_noise(consts: &Constants, size: u32) -> Vec<Vec4> {
         let mut rng = Xoshiro256plus::new(consts.sim.seed as _);
         let mut data = vec![];
-        for _ in 0..(consts.sim.size * consts.sim.size) {
+        for _ in 0..(size * size) {
             let gaussian_pair =
                 Self::gaussian_number(
                     rng.next() as _,
@@ -0,0 +1,101 @@
+use super::cascade::CascadeMaps;
+use super::compute::ComputePass;
+use crate::engine::scene::Scene;
+use shared::{Particle, MAX_PARTICLES};
+use std::mem;
+
+// threads per workgroup of spray::spray_cs
+const SPRAY_WG_SIZE: u32 = 64;
+
+// spray and spindrift thrown off breaking crests. the particles never leave the gpu, spray_cs
+// emits and moves them after the cascades are processed and spray_vs draws them from the same
+// buffer
+pub struct Spray {
+    pass: ComputePass,
+    buffer: wgpu::Buffer,
+    pub layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Spray {
+    pub fn new(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        consts_layout: &wgpu::BindGroupLayout,
+        maps_layout: &wgpu::BindGroupLayout,
+        bathymetry_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        // zeroed, which is every particle dead
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            size: (MAX_PARTICLES as usize * mem::size_of::<Particle>()) as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            label: Some("Spray Particles"),
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("Spray Layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("Spray"),
+        });
+        let pass = ComputePass::new(
+            &[consts_layout, maps_layout, &layout, bathymetry_layout],
+            &[],
+            device,
+            shader,
+            "Spray",
+            "spray::spray_cs",
+        );
+        Self {
+            pass,
+            buffer,
+            layout,
+            bind_group,
+        }
+    }
+
+    // crests are found through the bathymetry the same way main_vs draws them, so surf throws
+    // spray and refracted crests throw it where they are
+    pub fn compute(&self, encoder: &mut wgpu::CommandEncoder, scene: &Scene, maps: &CascadeMaps, bathymetry: &wgpu::BindGroup) {
+        self.pass.compute(
+            encoder,
+            "Spray",
+            &[&scene.consts_bind_group, &maps.bind_group, &self.bind_group, bathymetry],
+            MAX_PARTICLES.div_ceil(SPRAY_WG_SIZE),
+            1,
+        );
+    }
+
+    pub fn clear(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.clear_buffer(&self.buffer, 0, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::{surf_injection, SimConstants};
+
+    // break_waves gives surf of x - tanh(x) for a wave x times the breaker limit, which is
+    // clamped to 1 by twice the limit, so a shore with nothing else breaking has to reach this
+    #[test]
+    fn broken_surf_alone_throws_spray() {
+        let sim = SimConstants::default();
+        assert!(surf_injection(1.0, &sim) > sim.spray_threshold);
+        assert_eq!(surf_injection(0.0, &sim), 0.0);
+    }
+}
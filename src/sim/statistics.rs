@@ -0,0 +1,258 @@
+use super::cpu::{fft, spectrum::dispersion_relation};
+use crate::Result;
+use glam::{Vec2, Vec4};
+use serde::Serialize;
+use shared::{CascadeParams, SimConstants};
+use std::f32::consts::TAU;
+use std::fs;
+
+// the frequency spectrum is binned logarithmically between these, rad/s, to find its peak
+const PEAK_BINS: usize = 128;
+const MIN_OMEGA: f32 = 0.05;
+const MAX_OMEGA: f32 = 50.0;
+
+// the usual sea state parameters of a frequency spectrum. moments are m_n = integral of
+// omega^n S(omega), so periods are in seconds and direction is in radians the same way as
+// wind_offset, the way the waves travel
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct SeaState {
+    pub m0: f32,
+    pub m1: f32,
+    pub m2: f32,
+    pub m4: f32,
+    // significant wave height 4 sqrt(m0)
+    pub hs: f32,
+    // peak period, of the binned spectrum
+    pub tp: f32,
+    // mean period m0 / m1 and zero crossing period sqrt(m0 / m2)
+    pub tm01: f32,
+    pub tz: f32,
+    // cartwright and longuet-higgins, 0 for a single frequency and towards 1 for a broad spectrum
+    pub bandwidth: f32,
+    pub direction: f32,
+}
+
+// what the spectrum asks for next to what the surface actually holds, at time
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct SeaStateReport {
+    pub time: f32,
+    pub spectral: SeaState,
+    pub realised: SeaState,
+}
+
+impl SeaStateReport {
+    pub fn export(&self, path: &str) -> Result {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+// sums omega^n of every wavenumber weighted by its variance, along with the binned spectrum and a
+// direction weighted the same way
+#[derive(Default)]
+struct Moments {
+    m: [f32; 5],
+    bins: Vec<f32>,
+    direction: Vec2,
+}
+
+impl Moments {
+    fn new() -> Self {
+        Self {
+            bins: vec![0.0; PEAK_BINS],
+            ..Default::default()
+        }
+    }
+
+    fn add(&mut self, omega: f32, variance: f32) {
+        for (n, m) in self.m.iter_mut().enumerate() {
+            *m += omega.powi(n as i32) * variance;
+        }
+        let t = (omega / MIN_OMEGA).ln() / (MAX_OMEGA / MIN_OMEGA).ln();
+        if (0.0..1.0).contains(&t) {
+            self.bins[(t * PEAK_BINS as f32) as usize] += variance;
+        }
+    }
+
+    // bins are wider at higher frequencies so they are divided by their width for the density
+    fn peak_omega(&self) -> f32 {
+        let ratio = (MAX_OMEGA / MIN_OMEGA).powf(1.0 / PEAK_BINS as f32);
+        let (peak, _) = self
+            .bins
+            .iter()
+            .enumerate()
+            .map(|(i, energy)| (i, energy / (MIN_OMEGA * ratio.powi(i as i32) * (ratio - 1.0))))
+            .fold((0, 0.0), |best, bin| if bin.1 > best.1 { bin } else { best });
+        MIN_OMEGA * ratio.powf(peak as f32 + 0.5)
+    }
+
+    fn sea_state(&self) -> SeaState {
+        let [m0, m1, m2, _, m4] = self.m;
+        let ratio = |a: f32, b: f32| if b > 0.0 { a / b } else { 0.0 };
+        SeaState {
+            m0,
+            m1,
+            m2,
+            m4,
+            hs: 4.0 * m0.sqrt(),
+            tp: TAU / self.peak_omega(),
+            tm01: TAU * ratio(m0, m1),
+            tz: TAU * ratio(m0, m2).sqrt(),
+            bandwidth: (1.0 - ratio(m2 * m2, m0 * m4)).clamp(0.0, 1.0).sqrt(),
+            direction: self.direction.y.atan2(self.direction.x),
+        }
+    }
+}
+
+// the texel at (x, y) of a centred spectrum, see sim::initial_spectra
+fn wavenumber(i: usize, params: &CascadeParams) -> Vec2 {
+    let size = params.size as usize;
+    let dk = TAU / params.lengthscale;
+    let half = 0.5 * params.size as f32;
+    Vec2::new((i % size) as f32 - half, (i / size) as f32 - half) * dk
+}
+
+// from the initial spectrum texture of each cascade. h(k, t) is h0(k) e^(i omega t) plus
+// conj(h0(-k)) e^(-i omega t), which averages to |h0(k)|^2 + |h0(-k)|^2 of variance over time. the
+// first half travels along k and the second against it. each cascade is scaled by its scale
+// factor when drawn, so its variance is by the square
+pub fn spectral(spectra: &[Vec<Vec4>], params: &[CascadeParams], consts: &SimConstants) -> SeaState {
+    let mut moments = Moments::new();
+    for (spectrum, params) in spectra.iter().zip(params) {
+        let weight = params.scale_factor * params.scale_factor;
+        for (i, texel) in spectrum.iter().enumerate() {
+            let k = wavenumber(i, params);
+            if k == Vec2::ZERO {
+                continue;
+            }
+            let along = Vec2::new(texel.x, texel.y).length_squared() * weight;
+            let against = Vec2::new(texel.z, texel.w).length_squared() * weight;
+            moments.add(dispersion_relation(k.length(), consts), along + against);
+            moments.direction += k.normalize() * (along - against);
+        }
+    }
+    moments.sea_state()
+}
+
+// one cascade's maps as read back, cropped to its size
+pub struct RealisedCascade {
+    pub displacement: Vec<Vec4>,
+    pub velocity: Vec<Vec4>,
+    pub acceleration: Vec<Vec4>,
+}
+
+// from the displacement, velocity and acceleration maps. the variances of the height and of its
+// first and second time derivatives are m0, m2 and m4 directly. m1 and the peak need the
+// spectrum, so the heights are transformed and each wavenumber given its frequency through the
+// dispersion relation. a snapshot cant tell which way a wave is heading, but the orbital velocity
+// under a crest points the way it travels, so the direction is that of height times horizontal
+// velocity
+pub fn realised(cascades: &[RealisedCascade], params: &[CascadeParams], consts: &SimConstants) -> SeaState {
+    let mut moments = Moments::new();
+    let mut variances = [0.0; 3];
+    for (cascade, params) in cascades.iter().zip(params) {
+        let weight = params.scale_factor * params.scale_factor;
+        let size = params.size as usize;
+        variances[0] += variance(cascade.displacement.iter().map(|d| d.y)) * weight;
+        variances[1] += variance(cascade.velocity.iter().map(|v| v.y)) * weight;
+        variances[2] += variance(cascade.acceleration.iter().map(|a| a.y)) * weight;
+
+        let mut heights: Vec<Vec4> = cascade.displacement.iter().map(|d| Vec4::new(d.y, 0.0, 0.0, 0.0)).collect();
+        fft::fft2d(&mut heights, size);
+        for (i, texel) in heights.iter().enumerate() {
+            let k = wavenumber(i, params);
+            if k != Vec2::ZERO {
+                let energy = Vec2::new(texel.x, texel.y).length_squared() * weight;
+                moments.add(dispersion_relation(k.length(), consts), energy);
+            }
+        }
+        let mean = cascade.displacement.iter().map(|d| d.y).sum::<f32>() / (size * size) as f32;
+        for (d, v) in cascade.displacement.iter().zip(&cascade.velocity) {
+            moments.direction += (d.y - mean) * Vec2::new(v.x, v.z) * weight;
+        }
+    }
+    // m0 is the same either way, the measured derivatives replace the ones from the fft
+    moments.m[0] = variances[0];
+    moments.m[2] = variances[1];
+    moments.m[4] = variances[2];
+    moments.sea_state()
+}
+
+fn variance(values: impl Iterator<Item = f32> + Clone) -> f32 {
+    let count = values.clone().count().max(1) as f32;
+    let mean = values.clone().sum::<f32>() / count;
+    values.map(|v| (v - mean) * (v - mean)).sum::<f32>() / count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::SQRT_2;
+
+    const AMPLITUDE: f32 = 0.8;
+    // wavenumber of the test wave in bins of the cascade, off both axes so direction means something
+    const BINS: (i32, i32) = (5, -3);
+
+    fn params() -> CascadeParams {
+        CascadeParams {
+            size: 64,
+            lengthscale: 100.0,
+            ..Default::default()
+        }
+    }
+
+    fn wave() -> (Vec2, f32) {
+        let k = Vec2::new(BINS.0 as f32, BINS.1 as f32) * TAU / params().lengthscale;
+        (k, dispersion_relation(k.length(), &SimConstants::default()))
+    }
+
+    // peak is the middle of a log spaced bin, so within half a bin of the true period
+    fn assert_sea_state(state: SeaState) {
+        let (k, omega) = wave();
+        let period = TAU / omega;
+        let half_bin = (MAX_OMEGA / MIN_OMEGA).powf(0.5 / PEAK_BINS as f32) - 1.0;
+        assert!((state.hs - 4.0 * AMPLITUDE / SQRT_2).abs() < 1e-3, "hs {}", state.hs);
+        assert!((state.tp - period).abs() < half_bin * period, "tp {} against {}", state.tp, period);
+        assert!((state.tm01 - period).abs() < 1e-3 * period, "tm01 {} against {}", state.tm01, period);
+        assert!((state.tz - period).abs() < 1e-3 * period, "tz {} against {}", state.tz, period);
+        assert!(state.bandwidth < 0.05, "bandwidth {}", state.bandwidth);
+        assert!((state.direction - k.y.atan2(k.x)).abs() < 1e-3, "direction {}", state.direction);
+    }
+
+    // a cos(k.x - omega t) at t = 0 with linear theory for its derivatives. the orbital velocity
+    // is along k and in phase with the height
+    #[test]
+    fn realised_single_wave() {
+        let params = params();
+        let (k, omega) = wave();
+        let size = params.size as usize;
+        let mut cascade = RealisedCascade {
+            displacement: vec![],
+            velocity: vec![],
+            acceleration: vec![],
+        };
+        for y in 0..size {
+            for x in 0..size {
+                let phase = TAU * (BINS.0 * x as i32 + BINS.1 * y as i32) as f32 / size as f32;
+                let orbital = k.normalize() * AMPLITUDE * omega * phase.cos();
+                cascade.displacement.push(Vec4::new(0.0, AMPLITUDE * phase.cos(), 0.0, 0.0));
+                cascade.velocity.push(Vec4::new(orbital.x, AMPLITUDE * omega * phase.sin(), orbital.y, 0.0));
+                cascade.acceleration.push(Vec4::new(0.0, -AMPLITUDE * omega * omega * phase.cos(), 0.0, 0.0));
+            }
+        }
+        assert_sea_state(realised(&[cascade], &[params], &SimConstants::default()));
+    }
+
+    // the same wave as an initial spectrum, h0(k) of a / 2 with its conjugate packed into the zw
+    // of -k the way the conjugates pass leaves it
+    #[test]
+    fn spectral_single_wave() {
+        let params = params();
+        let size = params.size as i32;
+        let texel = |bins: (i32, i32)| ((bins.1 + size / 2) * size + bins.0 + size / 2) as usize;
+        let mut spectrum = vec![Vec4::ZERO; (size * size) as usize];
+        spectrum[texel(BINS)] = Vec4::new(0.5 * AMPLITUDE, 0.0, 0.0, 0.0);
+        spectrum[texel((-BINS.0, -BINS.1))] = Vec4::new(0.0, 0.0, 0.5 * AMPLITUDE, 0.0);
+        assert_sea_state(spectral(&[spectrum], &[params], &SimConstants::default()));
+    }
+}
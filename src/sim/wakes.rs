@@ -0,0 +1,112 @@
+use super::bodies::Bodies;
+use crate::cast_slice;
+use glam::{Vec2, Vec3Swizzles, Vec4, Vec4Swizzles};
+use shared::{WakeSource, MAX_WAKES, WAKE_POINTS};
+use std::collections::VecDeque;
+use std::mem;
+
+// distance a body moves before another point is added to its trail
+const TRAIL_SPACING: f32 = 4.0;
+// slower than this a body doesnt add to its trail, so drifting bodies dont leave wakes
+const MIN_WAKE_SPEED: f32 = 0.5;
+// how far outside the kelvin angle of the whole trail the wake is assumed to be zero
+const BOUNDS_MARGIN: f32 = 0.4;
+
+// where a body has been, (x, z, speed, time) newest first
+#[derive(Default)]
+pub struct Trail {
+    points: VecDeque<Vec4>,
+}
+
+impl Trail {
+    pub fn record(&mut self, position: Vec2, speed: f32, time: f32) {
+        if speed < MIN_WAKE_SPEED {
+            return;
+        }
+        let moved = self.points.front().map_or(f32::MAX, |p| (p.xy() - position).length());
+        if moved >= TRAIL_SPACING {
+            self.points.push_front(Vec4::new(position.x, position.y, speed, time));
+            // the body itself takes the first point of the source
+            self.points.truncate(WAKE_POINTS - 1);
+        }
+    }
+}
+
+// the wakes are analytic, the gpu only needs the trails. see the wake shader for the pattern.
+// the impacts from Ripples are the second binding, as both are added on top of the cascades
+pub struct Wakes {
+    pub buffer: wgpu::Buffer,
+    pub layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Wakes {
+    pub fn new(device: &wgpu::Device, impacts: &wgpu::Buffer) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            size: (MAX_WAKES * mem::size_of::<WakeSource>()) as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            label: Some("Wake Sources"),
+        });
+        let entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            // drawn by main_vs and read back by sim::query
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[entry(0), entry(1)],
+            label: Some("Wakes Layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: impacts.as_entire_binding(),
+                },
+            ],
+            label: Some("Wakes"),
+        });
+        Self {
+            buffer,
+            layout,
+            bind_group,
+        }
+    }
+
+    // one source for each of the first MAX_WAKES bodies with a trail, the rest are dropped.
+    // unused sources are zeroed so the shader skips them
+    pub fn upload(&self, queue: &wgpu::Queue, bodies: &Bodies, time: f32) {
+        let mut sources = [WakeSource::default(); MAX_WAKES];
+        let trails = bodies.bodies.iter().filter(|body| !body.trail.points.is_empty());
+        for (source, body) in sources.iter_mut().zip(trails) {
+            let head = body.position.xz();
+            let speed = body.velocity.xz().length();
+            let points: Vec<Vec4> = [Vec4::new(head.x, head.y, speed, time)]
+                .into_iter()
+                .chain(body.trail.points.iter().copied())
+                .collect();
+            source.points[..points.len()].copy_from_slice(&points);
+            source.count = points.len() as u32;
+
+            let (length, beam) = body.shape.footprint();
+            source.length = length;
+            source.beam = beam;
+            let travelled: f32 = points.windows(2).map(|w| (w[1].xy() - w[0].xy()).length()).sum();
+            let centre = points.iter().map(|p| p.xy()).sum::<Vec2>() / points.len() as f32;
+            let radius = points.iter().map(|p| (p.xy() - centre).length()).fold(0.0, f32::max);
+            source.bounds = Vec4::new(centre.x, centre.y, radius + BOUNDS_MARGIN * travelled + length, 0.0);
+        }
+        queue.write_buffer(&self.buffer, 0, cast_slice(&sources));
+    }
+}